@@ -2,32 +2,70 @@ use std::error::Error;
 
 use clap::{command, Command, arg};
 
-use journaldb::{Db};
+use journaldb::{flags::Flag, store, time::{self, DisplayZone, TimeFormat}};
 
 mod util;
 use crate::util::*;
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let mut db = Db::new("journal.db");
-    db.initialize_db()?;
-    db.update_entries()?;
-
     let matches = command!()
     .propagate_version(true)
     .subcommand_required(true)
+    .arg(arg!(--journal <PATH> "SQLite journal file, or a directory of Markdown files when it is a directory or ends with /")
+        .default_value("journal.db")
+        .global(true))
+    .arg(arg!(--timezone <ZONE> "Time zone to show times in: local, utc, original, +02:00 or Europe/Berlin")
+        .global(true))
+    .arg(arg!(--"time-format" <FORMAT> "strftime format to show times with")
+        .global(true))
     .subcommand(
         Command::new("create")
-            .about("Create new Entry"),
+            .about("Create new Entry")
+            .arg(arg!(--date <DATE> "When the entry was written, e.g. 2023-03-01, \"2023-03-01 21:00\" or \"yesterday 9pm\""))
+            .arg(arg!(--template <NAME> "Start from a template")),
+    )
+    .subcommand(
+        Command::new("today")
+            .about("Edit today's Entry, creating it from the daily template if missing"),
+    )
+    .subcommand(
+        Command::new("append")
+            .about("Add a line starting with the current time to today's Entry")
+            .arg(arg!(<text> "Text to add")),
     )
     .subcommand(
         Command::new("list")
-            .about("List all Entries"),
+            .about("List all Entries")
+            .arg(arg!(--query <QUERY> "Only list matching entries, e.g. 'tag:work -tag:draft after:2024-01-01 \"exact phrase\" title:retro', combined with AND, OR, NOT and parentheses"))
+            .arg(arg!(--saved <NAME> "Only list entries matching a saved search").conflicts_with("query"))
+            .arg(arg!(--archived "Also list archived entries, queries always include them"))
+            .arg(arg!(--format <FORMAT> "Output format, json includes content, tags and timestamps")
+                .value_parser(["text", "json"])
+                .default_value("text")),
     )
     .subcommand(
         Command::new("delete")
             .about("Delete an Entry")
             .arg(arg!([entry_id])),
     )
+    .subcommand(
+        Command::new("pin")
+            .about("List an Entry before the others")
+            .arg(arg!(<entry_id>).value_parser(clap::value_parser!(u32)))
+            .arg(arg!(--undo "Unpin the Entry instead")),
+    )
+    .subcommand(
+        Command::new("star")
+            .about("Mark an Entry as a favorite, find them with --query is:starred")
+            .arg(arg!(<entry_id>).value_parser(clap::value_parser!(u32)))
+            .arg(arg!(--undo "Remove the star instead")),
+    )
+    .subcommand(
+        Command::new("archive")
+            .about("Hide an Entry from lists, queries still find it")
+            .arg(arg!(<entry_id>).value_parser(clap::value_parser!(u32)))
+            .arg(arg!(--undo "Bring the Entry back instead")),
+    )
     .subcommand(
         Command::new("show")
             .about("Show journal entry"),
@@ -36,24 +74,239 @@ fn main() -> Result<(), Box<dyn Error>> {
         Command::new("edit")
             .about("Edit journal entry"),
     )
+    .subcommand(
+        Command::new("attach")
+            .about("Store a file with an Entry")
+            .arg(arg!(<entry_id>).value_parser(clap::value_parser!(u32)))
+            .arg(arg!(<file> "File to attach")),
+    )
+    .subcommand(
+        Command::new("attachments")
+            .about("List the files of an Entry")
+            .arg(arg!(<entry_id>).value_parser(clap::value_parser!(u32)))
+            .arg(arg!(--extract <DIR> "Write the files into DIR")),
+    )
+    .subcommand(
+        Command::new("detach")
+            .about("Remove an attachment")
+            .arg(arg!(<attachment_id>).value_parser(clap::value_parser!(u32))),
+    )
+    .subcommand(
+        Command::new("export")
+            .about("Write all entries and saved searches as JSON, to FILE or standard output")
+            .arg(arg!([file] "File to write")),
+    )
+    .subcommand(
+        Command::new("import")
+            .about("Add the entries and saved searches of a JSON export, keeping their timestamps")
+            .arg(arg!(<file> "File to read")),
+    )
+    .subcommand(
+        Command::new("merge")
+            .about("Bring in the entries of another copy of the journal, keeping the newer edit when both changed one")
+            .arg(arg!(<other> "SQLite journal file to merge")),
+    )
+    .subcommand(
+        Command::new("backup")
+            .about("Copy the journal while in use, by default into backups/ next to it with the time in the name")
+            .arg(arg!(--out <FILE> "Write the backup to FILE instead"))
+            .arg(arg!(--keep <N> "Number of timestamped backups to keep")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("10")),
+    )
+    .subcommand(
+        Command::new("restore")
+            .about("Replace the journal with a backup once it checks out, the replaced journal is backed up first")
+            .arg(arg!(<file> "Backup to restore")),
+    )
+    .subcommand(
+        Command::new("doctor")
+            .about("Check the journal file for corruption and rows referring to nothing")
+            .arg(arg!(--repair "Remove such rows without asking")),
+    )
+    .subcommand(
+        Command::new("config")
+            .about("Show or change settings")
+            .arg(arg!([key] "Setting to show or change, e.g. time.zone or time.format"))
+            .arg(arg!([value] "New value of the setting")),
+    )
+    .subcommand(
+        Command::new("stats")
+            .about("Show writing statistics")
+            .arg(arg!(--format <FORMAT> "Output format")
+                .value_parser(["text", "json"])
+                .default_value("text"))
+            .arg(arg!(--top <N> "Number of top tags to show")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("5")),
+    )
+    .subcommand(
+        Command::new("template")
+            .about("Manage entry templates, which may use {{date}}, {{weekday}}, {{week}} and {{prompt:Question}}")
+            .subcommand_required(true)
+            .subcommand(Command::new("list").about("List the templates"))
+            .subcommand(
+                Command::new("add")
+                    .about("Add or replace a template, the content is written in the editor")
+                    .arg(arg!(<name>))
+                    .arg(arg!(--title <TITLE> "Title of new entries").default_value(""))
+                    .arg(arg!(--tags <TAGS> "Tags of new entries, separated by comma").default_value("")),
+            )
+            .subcommand(
+                Command::new("remove")
+                    .about("Remove a template")
+                    .arg(arg!(<name>)),
+            ),
+    )
+    .subcommand(
+        Command::new("saved")
+            .about("Manage saved searches")
+            .subcommand_required(true)
+            .subcommand(Command::new("list").about("List the saved searches with their number of entries"))
+            .subcommand(
+                Command::new("add")
+                    .about("Save a query under a name, replacing an existing one")
+                    .arg(arg!(<name>))
+                    .arg(arg!(<query> "Query as for list --query")),
+            )
+            .subcommand(
+                Command::new("remove")
+                    .about("Remove a saved search")
+                    .arg(arg!(<name>)),
+            ),
+    )
+    .subcommand(
+        Command::new("passphrase")
+            .about("Encrypt the journal with a passphrase or change it")
+            .arg(arg!(--remove "Store the journal unencrypted again")),
+    )
     .get_matches();
+
+    let mut store = store::open(matches.get_one::<String>("journal").unwrap())?;
+    for warning in store.warnings() {
+        eprintln!("warning: {}", warning);
+    }
+    let mut time_format = match store.as_db() {
+        Some(db) => {
+            unlock(db)?;
+            db.update_entries()?;
+            db.time_format()?
+        }
+        None => TimeFormat::default(),
+    };
+    if let Some(zone) = matches.get_one::<String>("timezone") {
+        time_format.zone = zone.parse::<DisplayZone>()?;
+    }
+    if let Some(format) = matches.get_one::<String>("time-format") {
+        time::check_format(format)?;
+        time_format.format = format.to_string();
+    }
+
+    let store = store.as_mut();
     match matches.subcommand() {
-        Some(("create", _)) => create_journal_entry(&mut db),
-        Some(("list", _)) => print_journal_entries(&mut db),
-        Some(("delete", args)) => Ok({
+        Some(("create", args)) => {
+            let date = args.get_one::<String>("date")
+                .map(|d| time::parse_datetime(d, chrono::Local::now()))
+                .transpose()?;
+            create_journal_entry(store, date, args.get_one::<String>("template").map(String::as_str))
+        },
+        Some(("today", _)) => edit_daily_entry(sqlite(store, "today")?),
+        Some(("append", args)) => append_to_daily_entry(sqlite(store, "append")?, args.get_one::<String>("text").unwrap()),
+        Some(("list", args)) => {
+            let format = args.get_one::<String>("format").unwrap();
+            match args.get_one::<String>("saved") {
+                Some(name) => print_saved_search(sqlite(store, "--saved")?, name, format),
+                None => print_journal_entries(
+                    store,
+                    args.get_one::<String>("query").map(String::as_str),
+                    args.get_flag("archived"),
+                    format,
+                ),
+            }
+        },
+        Some(("delete", args)) => {
             if let Some(x) = args.get_one::<String>("entry_id") {
                 if let Ok(entry_id) = x.parse::<u32>() {
-                    delete_journal_entry(&mut db, entry_id)?;
+                    delete_journal_entry(store, entry_id)?;
                 }
                 else {
                     println!("Entry id must be a number");
                 }
             }
-        }),
-        Some(("show", _)) => show_journal_entry(&db),
-        Some(("edit", _)) => edit_journal_entry(&mut db),
+            Ok(())
+        },
+        Some(("pin", args)) => flag_entry(store, *args.get_one::<u32>("entry_id").unwrap(), Flag::Pinned, !args.get_flag("undo")),
+        Some(("star", args)) => flag_entry(store, *args.get_one::<u32>("entry_id").unwrap(), Flag::Starred, !args.get_flag("undo")),
+        Some(("archive", args)) => flag_entry(store, *args.get_one::<u32>("entry_id").unwrap(), Flag::Archived, !args.get_flag("undo")),
+        Some(("show", _)) => show_journal_entry(store, &time_format),
+        Some(("edit", _)) => edit_journal_entry(store),
+        Some(("attach", args)) => attach_file(
+            sqlite(store, "attach")?,
+            *args.get_one::<u32>("entry_id").unwrap(),
+            args.get_one::<String>("file").unwrap(),
+        ),
+        Some(("attachments", args)) => list_attachments(
+            sqlite(store, "attachments")?,
+            *args.get_one::<u32>("entry_id").unwrap(),
+            args.get_one::<String>("extract").map(String::as_str),
+        ),
+        Some(("detach", args)) => remove_attachment(sqlite(store, "detach")?, *args.get_one::<u32>("attachment_id").unwrap()),
+        Some(("export", args)) => export_entries(store, args.get_one::<String>("file").map(String::as_str)),
+        Some(("import", args)) => import_entries(store, args.get_one::<String>("file").unwrap()),
+        Some(("merge", args)) => merge_journal(sqlite(store, "merge")?, args.get_one::<String>("other").unwrap()),
+        Some(("backup", args)) => backup_journal(
+            sqlite(store, "backup")?,
+            args.get_one::<String>("out").map(String::as_str),
+            *args.get_one::<usize>("keep").unwrap(),
+        ),
+        Some(("restore", args)) => restore_journal(sqlite(store, "restore")?, args.get_one::<String>("file").unwrap()),
+        Some(("doctor", args)) => doctor(sqlite(store, "doctor")?, args.get_flag("repair")),
+        Some(("config", args)) => configure(
+            sqlite(store, "config")?,
+            args.get_one::<String>("key").map(String::as_str),
+            args.get_one::<String>("value").map(String::as_str),
+        ),
+        Some(("stats", args)) => print_stats(
+            store,
+            &time_format,
+            args.get_one::<String>("format").unwrap(),
+            *args.get_one::<usize>("top").unwrap(),
+        ),
+        Some(("template", args)) => {
+            let db = sqlite(store, "template")?;
+            match args.subcommand() {
+                Some(("list", _)) => list_templates(db),
+                Some(("add", args)) => add_template(
+                    db,
+                    args.get_one::<String>("name").unwrap(),
+                    args.get_one::<String>("title").unwrap(),
+                    args.get_one::<String>("tags").unwrap(),
+                ),
+                Some(("remove", args)) => remove_template(db, args.get_one::<String>("name").unwrap()),
+                _ => unreachable!("template requires a subcommand"),
+            }
+        },
+        Some(("saved", args)) => {
+            let db = sqlite(store, "saved")?;
+            match args.subcommand() {
+                Some(("list", _)) => list_saved_searches(db),
+                Some(("add", args)) => save_search(
+                    db,
+                    args.get_one::<String>("name").unwrap(),
+                    args.get_one::<String>("query").unwrap(),
+                ),
+                Some(("remove", args)) => remove_saved_search(db, args.get_one::<String>("name").unwrap()),
+                _ => unreachable!("saved requires a subcommand"),
+            }
+        },
+        Some(("passphrase", args)) => change_passphrase(sqlite(store, "passphrase")?, args.get_flag("remove")),
         _ => unreachable!("Exhausted list of subcommands and subcommand_required prevents 'None'"),
     }?;
+    // Changes are saved either way, only the mirror misses them
+    if let Some(error) = store.as_db().map(|db| db.git_mirror_error()).transpose()?.flatten() {
+        eprintln!("warning: the git mirror is behind: {}", error);
+        eprintln!("`journalcli config git.mirror <dir>` commits the whole journal again");
+    }
     Ok(())
 }
 
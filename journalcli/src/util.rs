@@ -1,32 +1,92 @@
-use std::error::Error;
+use std::{collections::HashMap, error::Error, fs, io::{IsTerminal, Write}, path::{Path, PathBuf}};
 
-use dialoguer::{Input, Editor, Select, theme::ColorfulTheme, console::Term};
-use journaldb::{Tag, Entry, Db};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chrono::{DateTime, FixedOffset};
+use dialoguer::{Confirm, Input, Editor, Password, Select, theme::ColorfulTheme, console::Term};
+use journaldb::{Tag, Entry, Db, attachments, backup, crypto::CryptoError, daily, doctor::Problem, export::Export, flags::{self, Flag}, git, query::Query, stats::Stats, store::JournalStore, templates::Template, time::{self, DisplayZone, TimeFormat}};
+use serde_json::json;
 
-pub fn create_journal_entry(db: &mut Db) -> Result<(), Box<dyn Error>> {
+/// Environment variable read instead of prompting for the passphrase
+const PASSPHRASE_VAR: &str = "JOURNAL_PASSPHRASE";
+
+/// The SQLite journal behind `store`, for commands other journals don't support
+pub fn sqlite<'a>(store: &'a dyn JournalStore, command: &str) -> Result<&'a Db, Box<dyn Error>> {
+    store
+        .as_db()
+        .ok_or_else(|| format!("{} needs a SQLite journal", command).into())
+}
+
+/// Asks for the passphrase of an encrypted journal until it is right
+pub fn unlock(db: &Db) -> Result<(), Box<dyn Error>> {
+    if !db.is_locked()? {
+        return Ok(());
+    }
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_VAR) {
+        return Ok(db.unlock(&passphrase)?);
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(format!("{}, set {} when not running in a terminal", CryptoError::Locked, PASSPHRASE_VAR).into());
+    }
+    for _ in 0..3 {
+        let passphrase = Password::new().with_prompt("Passphrase").interact()?;
+        match db.unlock(&passphrase) {
+            Err(CryptoError::WrongPassphrase) => println!("Wrong passphrase, try again"),
+            result => return Ok(result?),
+        }
+    }
+    Err(CryptoError::WrongPassphrase.into())
+}
+
+pub fn change_passphrase(db: &Db, remove: bool) -> Result<(), Box<dyn Error>> {
+    if remove {
+        db.set_passphrase(None)?;
+        println!("Journal is no longer encrypted");
+        return Ok(());
+    }
+    let passphrase = Password::new()
+        .with_prompt("New passphrase")
+        .with_confirmation("Repeat passphrase", "Passphrases don't match")
+        .interact()?;
+    db.set_passphrase(Some(&passphrase))?;
+    println!("Journal encrypted with the new passphrase");
+    Ok(())
+}
+
+pub fn create_journal_entry(
+    store: &mut dyn JournalStore,
+    date: Option<DateTime<FixedOffset>>,
+    template: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let when = date.unwrap_or_else(|| chrono::offset::Local::now().fixed_offset());
+    let draft = match template {
+        Some(name) => {
+            let template = sqlite(store, "--template")?.get_template(name)?.ok_or_else(|| format!("template {} not found", name))?;
+            Some(template.render(when.date_naive(), &answer_prompts(&template)?))
+        }
+        None => None,
+    };
+    let title = match &draft {
+        Some(draft) if !draft.get_title().is_empty() => draft.get_title(),
+        _ => format!("{} Entry", when.format("%d-%m-%Y")),
+    };
     let title: String = Input::new()
         .with_prompt("Enter entry_title")
-        .with_initial_text(format!("{} Entry", chrono::offset::Local::now().format("%d-%m-%Y")))
+        .with_initial_text(title)
         .interact_text()?;
 
-    if let Some(content) = Editor::new().edit("Enter entry content")? {
-        // let tags: Vec<Tag> = Input::<String>::new()
-        //     .with_prompt("Enter tags separated by comma")
-        //     .allow_empty(true)
-        //     .interact_text()?
-        //     .split(',')
-        //     .map(|t| {
-        //         Tag::new(String::from(t))
-        //     }).collect();
-        // let tags = if tags.len() > 0 {
-        //     Some(tags)
-        // }    
-        // else {
-        //     None
-        // };
-
+    let initial_content = draft.as_ref().map(Entry::get_content).unwrap_or_else(|| "Enter entry content".to_string());
+    // A filled in template is worth keeping even if the editor is closed without changes
+    if let Some(content) = Editor::new().require_save(draft.is_none()).edit(&initial_content)? {
+        let initial_tags = draft
+            .and_then(|d| d.get_tags())
+            .unwrap_or_default()
+            .iter()
+            .map(Tag::get_tag)
+            .collect::<Vec<String>>()
+            .join(",");
         let tags: Option<Vec<Tag>> = Input::<String>::new()
             .with_prompt("Enter tags separated by comma")
+            .with_initial_text(initial_tags)
             .allow_empty(true)
             .interact_text()?
             .split(',')
@@ -43,26 +103,149 @@ pub fn create_journal_entry(db: &mut Db) -> Result<(), Box<dyn Error>> {
             content,
             tags,
         );
+        if let Some(date) = date {
+            entry.set_created_time(date);
+        }
 
-        db.create_entry(&mut entry)?;
+        store.create_entry(&mut entry)?;
     } 
 
     Ok(())
 
 }
 
-pub fn print_journal_entries(db: &mut Db) -> Result<(), Box<dyn Error>> {
-    let entries = db.get_entries();
+/// Answers the `{{prompt:...}}` questions of a template on the terminal
+fn answer_prompts(template: &Template) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let mut answers = HashMap::new();
+    for question in template.prompts() {
+        let answer: String = Input::new().with_prompt(&question).allow_empty(true).interact_text()?;
+        answers.insert(question, answer);
+    }
+    Ok(answers)
+}
+
+/// Opens today's entry in the editor, creating it from the daily template first if needed
+pub fn edit_daily_entry(db: &Db) -> Result<(), Box<dyn Error>> {
+    let now = chrono::offset::Local::now().fixed_offset();
+    let answers = match db.find_daily_entry(now.date_naive())? {
+        Some(_) => HashMap::new(),
+        None => match db.daily_template()? {
+            Some(template) => answer_prompts(&template)?,
+            None => HashMap::new(),
+        },
+    };
+    let mut entry = db.open_daily_entry(now, &answers)?;
+    if let Some(content) = Editor::new().require_save(false).edit(&entry.get_content())? {
+        entry.set_content(content);
+        db.edit_entry(&mut entry)?;
+    }
+    println!("{} - {}", entry.get_id(), entry.get_title());
+    Ok(())
+}
+
+pub fn append_to_daily_entry(db: &Db, text: &str) -> Result<(), Box<dyn Error>> {
+    let entry = db.append_to_daily_entry(chrono::offset::Local::now().fixed_offset(), text)?;
+    println!("Added to {} - {}", entry.get_id(), entry.get_title());
+    Ok(())
+}
+
+pub fn list_templates(db: &Db) -> Result<(), Box<dyn Error>> {
+    for template in db.get_templates()? {
+        println!("{} - {} [{}]", template.name, template.title, template.tags.join(","));
+        for question in template.prompts() {
+            println!("    asks: {}", question);
+        }
+    }
+    Ok(())
+}
+
+/// Saves a template, with the content written in the editor
+pub fn add_template(db: &Db, name: &str, title: &str, tags: &str) -> Result<(), Box<dyn Error>> {
+    let existing = db.get_template(name)?.map(|t| t.content).unwrap_or_default();
+    let Some(content) = Editor::new().require_save(false).edit(&existing)? else {
+        return Ok(());
+    };
+    db.save_template(&Template {
+        name: name.to_string(),
+        title: title.to_string(),
+        content,
+        tags: tags.split(',').map(str::trim).filter(|t| !t.is_empty()).map(str::to_string).collect(),
+    })?;
+    println!("Saved template {}", name);
+    Ok(())
+}
+
+pub fn remove_template(db: &Db, name: &str) -> Result<(), Box<dyn Error>> {
+    if db.remove_template(name)? {
+        println!("Removed template {}", name);
+    } else {
+        println!("No template {}", name);
+    }
+    Ok(())
+}
+
+/// Prints the entries pinned ones first, archived ones only when `archived` is set or they match `query`
+pub fn print_journal_entries(store: &dyn JournalStore, query: Option<&str>, archived: bool, format: &str) -> Result<(), Box<dyn Error>> {
+    let entries = match query {
+        Some(query) => {
+            let parsed = Query::parse(query).inspect_err(|e| eprintln!("{}", e.pointer(query)))?;
+            flags::arrange(store.search(&parsed)?, true)
+        }
+        None => flags::arrange(store.get_entries(), archived),
+    };
+    print_entries(&entries, format)
+}
+
+pub fn print_saved_search(db: &Db, name: &str, format: &str) -> Result<(), Box<dyn Error>> {
+    print_entries(&flags::arrange(db.run_saved_search(name)?, true), format)
+}
+
+fn print_entries(entries: &[Entry], format: &str) -> Result<(), Box<dyn Error>> {
+    if format == "json" {
+        println!("{}", serde_json::to_string_pretty(entries)?);
+        return Ok(());
+    }
     for entry in entries {
-        println!("{} - {}", entry.get_id(), entry.get_title());
+        match entry.markers().as_str() {
+            "" => println!("{} - {}", entry.get_id(), entry.get_title()),
+            markers => println!("{} - {} {}", entry.get_id(), markers, entry.get_title()),
+        }
     }
+    Ok(())
+}
+
+pub fn list_saved_searches(db: &Db) -> Result<(), Box<dyn Error>> {
+    for (search, count) in db.count_saved_searches()? {
+        match count {
+            Ok(count) => println!("{} ({}) - {}", search.name, count, search.query),
+            Err(e) => println!("{} (?) - {}: {}", search.name, search.query, e),
+        }
+    }
+    Ok(())
+}
 
+pub fn save_search(db: &Db, name: &str, query: &str) -> Result<(), Box<dyn Error>> {
+    if let Err(e) = Query::parse(query) {
+        eprintln!("{}", e.pointer(query));
+        return Err(e.into());
+    }
+    db.save_search(name, query)?;
+    println!("Saved search {}", name);
     Ok(())
 }
 
-pub fn delete_journal_entry(db: &mut Db, entry_id: u32) -> Result<(), Box<dyn Error>> {
-    if let Some(entry ) = db.get_entry_by_id(entry_id) {
-        db.delete_entry(& entry)?;
+pub fn remove_saved_search(db: &Db, name: &str) -> Result<(), Box<dyn Error>> {
+    if db.remove_saved_search(name)? {
+        println!("Removed saved search {}", name);
+    } else {
+        println!("No saved search {}", name);
+    }
+    Ok(())
+}
+
+pub fn delete_journal_entry(store: &mut dyn JournalStore, entry_id: u32) -> Result<(), Box<dyn Error>> {
+    if let Some(entry ) = store.get_entry_by_id(entry_id) {
+        store.delete_entry(& entry)?;
         println!("Entry [{} - {}] deleted", entry.get_id(), entry.get_title());
     }
     else {
@@ -71,14 +254,22 @@ pub fn delete_journal_entry(db: &mut Db, entry_id: u32) -> Result<(), Box<dyn Er
     Ok(())
 }
 
-pub fn show_journal_entry(db: &Db) -> Result<(), Box<dyn Error>> {
-    let entries = db.get_entries();
+pub fn flag_entry(store: &mut dyn JournalStore, entry_id: u32, flag: Flag, on: bool) -> Result<(), Box<dyn Error>> {
+    let entry = store.get_entry_by_id(entry_id).ok_or_else(|| format!("no entry with id {}", entry_id))?;
+    store.set_flag(entry_id, flag, on)?;
+    let not = if on { "" } else { "no longer " };
+    println!("Entry [{} - {}] is {}{}", entry_id, entry.get_title(), not, flag);
+    Ok(())
+}
+
+pub fn show_journal_entry(store: &dyn JournalStore, time_format: &TimeFormat) -> Result<(), Box<dyn Error>> {
+    let entries = store.get_entries();
     let items = &entries
         .iter()
         .map(|e| e.get_title())
         .collect::<Vec<String>>();
     let selection = Select::with_theme(&ColorfulTheme::default())
-        .items(&items)
+        .items(items)
         .default(0)
         .interact_on_opt(&Term::stderr())?;
 
@@ -95,44 +286,64 @@ pub fn show_journal_entry(db: &Db) -> Result<(), Box<dyn Error>> {
                 .map(|t| t.get_tag())
                 .collect::<Vec<String>>()
                 .join(","),
-            entries[index].get_created_time(),
-            entries[index].get_updated_time(),
+            time_format.format(&entries[index].get_created_time()),
+            time_format.format(&entries[index].get_updated_time()),
         ),
         None => println!("None selected"),
     }
+    // Links are only indexed in SQLite journals
+    if let (Some(index), Some(db)) = (selection, store.as_db()) {
+        let id = entries[index].get_id();
+        let links = db.outgoing_links(id)?;
+        if !links.is_empty() {
+            println!("\nLinks:");
+            for link in links {
+                match link.target.and_then(|target| db.get_entry_by_id(target)) {
+                    Some(target) => println!("{} - {}", target.get_id(), target.get_title()),
+                    None => println!("[[{}]] (no such entry)", link.text),
+                }
+            }
+        }
+        let backlinks = db.backlinks(id)?;
+        if !backlinks.is_empty() {
+            println!("\nBacklinks:");
+            for source in backlinks {
+                println!("{} - {}", source.get_id(), source.get_title());
+            }
+        }
+    }
     Ok(())
 }
 
-pub fn edit_journal_entry(db: &mut Db) -> Result<(), Box<dyn Error>> {
-    let entries = db.get_entries();
+pub fn edit_journal_entry(store: &mut dyn JournalStore) -> Result<(), Box<dyn Error>> {
+    let entries = store.get_entries();
     let items = &entries
         .iter()
         .map(|e| e.get_title())
         .collect::<Vec<String>>();
     let selection = Select::with_theme(&ColorfulTheme::default())
-        .items(&items)
+        .items(items)
         .default(0)
         .interact_on_opt(&Term::stderr())
         .unwrap()
         .unwrap();
     let title: String = Input::new()
         .with_prompt("Enter entry_title")
-        .with_initial_text(format!("{}", &entries[selection].get_title()))
+        .with_initial_text(entries[selection].get_title())
         .interact_text()?;
     let mut content = entries[selection].get_content();
     if let Ok(Some(c)) = Editor::new()
-        .edit(&format!("{}", &content))
+        .edit(&content)
         {
             content = c;}
-    else { }
     let tags: Option<Vec<Tag>> = Input::<String>::new()
         .with_prompt("Enter tags separated by comma")
-        .with_initial_text(format!("{}", entries[selection]
+        .with_initial_text(entries[selection]
             .get_tags()
             // .unwrap_or(vec![Tag::new("".to_string())])
             .unwrap_or(vec![])
             .iter()
-            .map(|t| t.get_tag()).collect::<Vec<String>>().join(",")))
+            .map(|t| t.get_tag()).collect::<Vec<String>>().join(","))
         .allow_empty(true)
         .interact_text()?
         .split(',')
@@ -147,6 +358,277 @@ pub fn edit_journal_entry(db: &mut Db) -> Result<(), Box<dyn Error>> {
     entry.set_title(title);
     entry.set_content(content);
     entry.set_tags(tags);
-    db.edit_entry(&mut entry)?;
+    store.edit_entry(&mut entry)?;
+    Ok(())
+}
+
+pub fn attach_file(db: &Db, entry_id: u32, file: &str) -> Result<(), Box<dyn Error>> {
+    if db.get_entry_by_id(entry_id).is_none() {
+        return Err(format!("Entry with id {} not found", entry_id).into());
+    }
+    let attachment = db.attach_file(entry_id, Path::new(file))?;
+    println!("Attached {} ({}, {} bytes) as {}", attachment.filename, attachment.mime, attachment.size, attachment.id);
     Ok(())
-}
\ No newline at end of file
+}
+
+pub fn list_attachments(db: &Db, entry_id: u32, extract: Option<&str>) -> Result<(), Box<dyn Error>> {
+    for attachment in db.get_attachments(entry_id)? {
+        match extract {
+            Some(dir) => {
+                let path = db.extract_attachment(attachment.id, Path::new(dir))?;
+                println!("{} - {}", attachment.id, path.display());
+            }
+            None => println!(
+                "{} - {} ({}, {} bytes, sha256 {})",
+                attachment.id, attachment.filename, attachment.mime, attachment.size, attachment.hash
+            ),
+        }
+    }
+    Ok(())
+}
+
+pub fn remove_attachment(db: &Db, attachment_id: u32) -> Result<(), Box<dyn Error>> {
+    if db.remove_attachment(attachment_id)? {
+        println!("Attachment {} removed", attachment_id);
+    }
+    else {
+        println!("Attachment with id {} not found", attachment_id);
+    }
+    Ok(())
+}
+
+pub fn export_entries(store: &dyn JournalStore, file: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let output = serde_json::to_string_pretty(&Export::of(store)?)?;
+    match file {
+        Some(file) => fs::write(file, output)?,
+        None => writeln!(std::io::stdout(), "{}", output)?,
+    }
+    Ok(())
+}
+
+/// Creates the entries of an export as new entries. Ids are not kept, timestamps are.
+/// Saved searches replace the ones with the same name.
+pub fn import_entries(store: &mut dyn JournalStore, file: &str) -> Result<(), Box<dyn Error>> {
+    let input: serde_json::Value = serde_json::from_str(&fs::read_to_string(file)?)?;
+    // Older exports are just the list of entries
+    let items = input
+        .get("entries")
+        .unwrap_or(&input)
+        .as_array()
+        .ok_or("expected a list of entries")?;
+    let mut searches = Vec::new();
+    for search in input.get("saved_searches").and_then(|s| s.as_array()).into_iter().flatten() {
+        let field = |key: &str| search.get(key).and_then(|v| v.as_str()).ok_or("saved search without name or query");
+        let (name, query) = (field("name")?, field("query")?);
+        Query::parse(query).map_err(|e| format!("saved search {}: {}", name, e))?;
+        searches.push((name, query));
+    }
+    let mut entries = Vec::new();
+    for (index, item) in items.iter().enumerate() {
+        let entry: Entry = serde_json::from_value(item.clone()).map_err(|e| format!("entry {}: {}", index + 1, e))?;
+        let mut files = Vec::new();
+        for file in item.get("attachments").and_then(|a| a.as_array()).into_iter().flatten() {
+            let filename = file.get("filename").and_then(|f| f.as_str()).ok_or("attachment without filename")?;
+            let data = BASE64.decode(file.get("data").and_then(|d| d.as_str()).unwrap_or_default())?;
+            if let Some(hash) = file.get("sha256").and_then(|h| h.as_str()) {
+                if attachments::sha256(&data) != hash {
+                    return Err(format!("attachment {} doesn't match its sha256", filename).into());
+                }
+            }
+            let mime = file.get("mime").and_then(|m| m.as_str()).map(str::to_string);
+            files.push((filename.to_string(), mime, data));
+        }
+        entries.push((entry, files));
+    }
+    if store.as_db().is_none() && entries.iter().any(|(_, files)| !files.is_empty()) {
+        sqlite(store, "importing attachments")?;
+    }
+    if !searches.is_empty() {
+        sqlite(store, "importing saved searches")?;
+    }
+    for (entry, files) in entries.iter_mut() {
+        store.create_entry(entry)?;
+        for (filename, mime, data) in files.iter() {
+            sqlite(store, "importing attachments")?.add_attachment(entry.get_id(), filename, mime.as_deref(), data)?;
+        }
+    }
+    for (name, query) in &searches {
+        sqlite(store, "importing saved searches")?.save_search(name, query)?;
+    }
+    println!("Imported {} entries and {} saved searches", entries.len(), searches.len());
+    Ok(())
+}
+
+/// Settings understood by the frontends, with a description
+const SETTINGS: &[(&str, &str)] = &[
+    (time::ZONE_SETTING, "time zone to show times in: local, utc, original, +02:00 or Europe/Berlin"),
+    (time::FORMAT_SETTING, "strftime format to show times with"),
+    (daily::TITLE_SETTING, "strftime format of the titles of daily entries"),
+    (daily::TEMPLATE_SETTING, "template new daily entries start from"),
+    (git::MIRROR_SETTING, "git repository every change is committed to as Markdown, empty to stop"),
+];
+
+/// Brings the entries of the journal at `other` into this one and prints what changed
+pub fn merge_journal(db: &Db, other: &str) -> Result<(), Box<dyn Error>> {
+    if !Path::new(other).is_file() {
+        return Err(format!("{} is not a journal file", other).into());
+    }
+    let other = Db::new(other);
+    other.initialize_db()?;
+    unlock(&other)?;
+    println!("{}", db.merge(&other)?);
+    Ok(())
+}
+
+/// Where timestamped backups go, next to the journal
+fn backup_dir(db: &Db) -> PathBuf {
+    Path::new(db.get_filename()).parent().unwrap_or(Path::new("")).join("backups")
+}
+
+/// Writes a backup to `out`, or a timestamped one keeping the `keep` newest
+pub fn backup_journal(db: &Db, out: Option<&str>, keep: usize) -> Result<(), Box<dyn Error>> {
+    let path = match out {
+        Some(out) if Path::new(out).exists() => return Err(format!("{} already exists", out).into()),
+        Some(out) => {
+            db.backup_to(Path::new(out))?;
+            PathBuf::from(out)
+        }
+        None => db.rotate_backups(&backup_dir(db), keep)?,
+    };
+    println!("Backed up to {}", path.display());
+    Ok(())
+}
+
+/// Replaces the journal with a backup, after backing up the journal it replaces
+pub fn restore_journal(db: &Db, file: &str) -> Result<(), Box<dyn Error>> {
+    backup::check_backup(Path::new(file))?;
+    let replaced = db.rotate_backups(&backup_dir(db), usize::MAX)?;
+    db.restore_from(Path::new(file))?;
+    unlock(db)?;
+    db.update_entries()?;
+    println!("Restored {} entries from {}, the replaced journal is in {}", db.get_entries().len(), file, replaced.display());
+    Ok(())
+}
+
+/// Checks the journal file and removes dangling rows when `repair` is set or the user agrees
+pub fn doctor(db: &Db, repair: bool) -> Result<(), Box<dyn Error>> {
+    let problems = db.check()?;
+    if problems.is_empty() {
+        println!("No problems found");
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    if !problems.iter().all(Problem::is_repairable) {
+        println!("The file is damaged, restore a backup with `journalcli restore`");
+    }
+    if !problems.iter().any(Problem::is_repairable) {
+        return Ok(());
+    }
+    let repair = repair
+        || (std::io::stdin().is_terminal()
+            && Confirm::new().with_prompt("Remove the rows referring to nothing?").default(false).interact()?);
+    if repair {
+        println!("Removed {} rows", db.repair()?);
+    }
+    Ok(())
+}
+
+pub fn configure(db: &Db, key: Option<&str>, value: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let key = match key {
+        Some(key) => key,
+        None => {
+            let values = db.get_settings()?;
+            for (key, description) in SETTINGS {
+                let value = values.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+                println!("{} = {}  # {}", key, value.unwrap_or("(default)"), description);
+            }
+            return Ok(());
+        }
+    };
+    if !SETTINGS.iter().any(|(k, _)| *k == key) {
+        return Err(format!("unknown setting '{}'", key).into());
+    }
+    match value {
+        Some(value) => {
+            match key {
+                time::ZONE_SETTING => { value.parse::<DisplayZone>()?; },
+                time::FORMAT_SETTING => time::check_format(value)?,
+                daily::TITLE_SETTING => daily::check_title(value)?,
+                daily::TEMPLATE_SETTING if db.get_template(value)?.is_none() => {
+                    return Err(format!("template {} not found", value).into());
+                }
+                // Starts with a commit of the whole journal
+                git::MIRROR_SETTING => {
+                    return db.set_git_mirror(Some(Path::new(value)).filter(|_| !value.is_empty()));
+                }
+                _ => {}
+            }
+            db.set_setting(key, value)?;
+        }
+        None => println!("{}", db.get_setting(key)?.unwrap_or_default()),
+    }
+    Ok(())
+}
+
+pub fn print_stats(store: &dyn JournalStore, time_format: &TimeFormat, format: &str, top_tags: usize) -> Result<(), Box<dyn Error>> {
+    // Streaks end today in the zone times are shown in
+    let today = time_format.date(&chrono::Local::now().fixed_offset());
+    let stats = Stats::compute(&store.get_entries(), time_format, today, top_tags);
+    if format == "json" {
+        let output = json!({
+            "total_entries": stats.total_entries,
+            "total_words": stats.total_words,
+            "average_words": stats.average_words(),
+            "words_per_entry": stats.words_per_entry,
+            "current_streak": stats.current_streak,
+            "longest_streak": stats.longest_streak,
+            "entries_per_weekday": stats.entries_per_weekday,
+            "entries_per_month": stats.entries_per_month,
+            "top_tags": stats.top_tags.iter()
+                .map(|(tag, count)| json!({"tag": tag, "entries": count}))
+                .collect::<Vec<serde_json::Value>>(),
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
+
+    println!("Entries:        {}", stats.total_entries);
+    println!("Words:          {}", stats.total_words);
+    println!("Words/entry:    {:.1}", stats.average_words());
+    println!("Current streak: {} days", stats.current_streak);
+    println!("Longest streak: {} days", stats.longest_streak);
+    // Bars fit the terminal, the space left of them is "  Mon 1234 "
+    let width = match Term::stdout().size_checked() {
+        Some((_, columns)) => (columns as usize).saturating_sub(11).max(1),
+        None => HISTOGRAM_WIDTH,
+    };
+    println!("\nEntries per weekday:");
+    let most = stats.entries_per_weekday.iter().copied().max().unwrap_or_default();
+    for (day, count) in ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"].iter().zip(stats.entries_per_weekday) {
+        println!("{}", histogram_line(day, count, most, width));
+    }
+    println!("\nEntries per month:");
+    let months = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let most = stats.entries_per_month.iter().copied().max().unwrap_or_default();
+    for (month, count) in months.iter().zip(stats.entries_per_month) {
+        println!("{}", histogram_line(month, count, most, width));
+    }
+    if !stats.top_tags.is_empty() {
+        println!("\nTop tags:");
+        for (tag, count) in &stats.top_tags {
+            println!("  {:<20} {}", tag, count);
+        }
+    }
+    Ok(())
+}
+
+/// Longest bar of the stats histograms when the output isn't a terminal
+const HISTOGRAM_WIDTH: usize = 50;
+
+/// A labelled bar, shrunk so the bar of `most` is `width` long. Counts above zero keep one `#`.
+fn histogram_line(label: &str, count: u64, most: u64, width: usize) -> String {
+    let length = if most as usize > width { (count * width as u64).div_ceil(most) } else { count };
+    format!("  {} {:>4} {}", label, count, "#".repeat(length as usize)).trim_end().to_string()
+}
@@ -0,0 +1,196 @@
+use std::{error::Error, fs, path::{Path, PathBuf}};
+
+use rusqlite::OptionalExtension;
+use sha2::{Digest, Sha256};
+
+use crate::Db;
+
+/// A file stored with an entry. The content is only loaded on request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attachment {
+    pub id: u32,
+    pub entry_id: u32,
+    pub filename: String,
+    pub mime: String,
+    /// Hex encoded SHA-256 of the content
+    pub hash: String,
+    /// Size of the content in bytes
+    pub size: u64,
+}
+
+pub fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// MIME type for a file name, `application/octet-stream` for unknown extensions
+pub fn guess_mime(filename: &str) -> String {
+    mime_guess::from_path(filename).first_or_octet_stream().to_string()
+}
+
+impl Db {
+    /// Stores `data` with the entry. The MIME type is guessed from the file name when not given.
+    /// Adding the same content under the same name again returns the existing attachment.
+    pub fn add_attachment(
+        &self,
+        entry_id: u32,
+        filename: &str,
+        mime: Option<&str>,
+        data: &[u8],
+    ) -> Result<Attachment, rusqlite::Error> {
+        let conn = self.conn();
+        // Asked of the table, since a merge adds attachments before the entry list is refreshed
+        conn.query_row("SELECT entry_id FROM entries WHERE entry_id = ?1", (entry_id,), |r| r.get::<_, u32>(0))?;
+        let hash = sha256(data);
+        // Sealed names and hashes differ every time, so they are compared once opened
+        let existing = self.get_attachments(entry_id)?.into_iter().find(|a| a.filename == filename && a.hash == hash);
+        if let Some(existing) = existing {
+            return Ok(existing);
+        }
+
+        let mime = mime.map(str::to_string).unwrap_or_else(|| guess_mime(filename));
+        conn.execute(
+            "INSERT INTO attachments (entry_id, filename, mime, hash, size, data) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            (entry_id, self.seal_text(filename), self.seal_text(&mime), self.seal_text(&hash), data.len() as u64,
+                self.seal_bytes(data)),
+        )?;
+        Ok(Attachment {
+            id: conn.last_insert_rowid() as u32,
+            entry_id,
+            filename: filename.to_string(),
+            mime,
+            hash,
+            size: data.len() as u64,
+        })
+    }
+
+    /// Reads `path` and stores it with the entry under its file name
+    pub fn attach_file(&self, entry_id: u32, path: &Path) -> Result<Attachment, Box<dyn Error>> {
+        let data = fs::read(path)?;
+        let filename = path
+            .file_name()
+            .ok_or_else(|| format!("{} is not a file", path.display()))?
+            .to_string_lossy();
+        Ok(self.add_attachment(entry_id, &filename, None, &data)?)
+    }
+
+    pub fn get_attachment(&self, attachment_id: u32) -> Result<Option<Attachment>, rusqlite::Error> {
+        self.conn().query_row(
+            "SELECT attachment_id, entry_id, filename, mime, hash, size FROM attachments WHERE attachment_id = ?1",
+            (attachment_id,),
+            |r| self.read_attachment(r),
+        ).optional()
+    }
+
+    /// Attachments of an entry in the order they were added
+    pub fn get_attachments(&self, entry_id: u32) -> Result<Vec<Attachment>, rusqlite::Error> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT attachment_id, entry_id, filename, mime, hash, size FROM attachments
+            WHERE entry_id = ?1 ORDER BY attachment_id",
+        )?;
+        let attachments = stmt.query_map((entry_id,), |r| self.read_attachment(r))?.collect();
+        attachments
+    }
+
+    /// Attachment from a row of id, entry id, file name, MIME type, hash and size. The file name,
+    /// MIME type and hash are sealed like the content.
+    fn read_attachment(&self, r: &rusqlite::Row) -> Result<Attachment, rusqlite::Error> {
+        Ok(Attachment {
+            id: r.get(0)?,
+            entry_id: r.get(1)?,
+            filename: self.open_text(r.get(2)?)?,
+            mime: self.open_text(r.get(3)?)?,
+            hash: self.open_text(r.get(4)?)?,
+            size: r.get(5)?,
+        })
+    }
+
+    pub fn get_attachment_data(&self, attachment_id: u32) -> Result<Vec<u8>, rusqlite::Error> {
+        let data = self.conn().query_row(
+            "SELECT data FROM attachments WHERE attachment_id = ?1",
+            (attachment_id,),
+            |r| r.get(0),
+        )?;
+        Ok(self.open_bytes(data)?)
+    }
+
+    /// Writes the attachment into `dir` under its file name and returns the written path
+    pub fn extract_attachment(&self, attachment_id: u32, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let attachment = self
+            .get_attachment(attachment_id)?
+            .ok_or_else(|| format!("attachment {} not found", attachment_id))?;
+        // Only keep the last component so stored names can't point outside of `dir`
+        let filename = Path::new(&attachment.filename)
+            .file_name()
+            .ok_or_else(|| format!("attachment {} has no usable file name", attachment_id))?;
+        let path = dir.join(filename);
+        fs::write(&path, self.get_attachment_data(attachment_id)?)?;
+        Ok(path)
+    }
+
+    /// Returns whether the attachment existed
+    pub fn remove_attachment(&self, attachment_id: u32) -> Result<bool, rusqlite::Error> {
+        let removed = self.conn().execute(
+            "DELETE FROM attachments WHERE attachment_id = ?1",
+            (attachment_id,),
+        )?;
+        Ok(removed > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Entry;
+
+    fn db_with_entry() -> (Db, u32) {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        let mut entry = Entry::new("title".to_string(), "content".to_string(), None);
+        db.create_entry(&mut entry).unwrap();
+        (db, entry.get_id())
+    }
+
+    #[test]
+    fn add_list_and_remove() {
+        let (db, entry_id) = db_with_entry();
+        let png = db.add_attachment(entry_id, "screen.png", None, b"not really a png").unwrap();
+        assert_eq!(png.mime, "image/png");
+        assert_eq!(png.size, 16);
+        assert_eq!(png.hash, sha256(b"not really a png"));
+        let pdf = db.add_attachment(entry_id, "report", Some("application/pdf"), b"%PDF").unwrap();
+        assert_eq!(db.get_attachments(entry_id).unwrap(), vec![png.clone(), pdf.clone()]);
+        assert_eq!(db.get_attachment_data(pdf.id).unwrap(), b"%PDF");
+
+        // The same file again is not stored twice
+        let again = db.add_attachment(entry_id, "screen.png", None, b"not really a png").unwrap();
+        assert_eq!(again, png);
+        assert_eq!(db.get_attachments(entry_id).unwrap().len(), 2);
+
+        assert!(db.remove_attachment(png.id).unwrap());
+        assert!(!db.remove_attachment(png.id).unwrap());
+        assert_eq!(db.get_attachments(entry_id).unwrap(), vec![pdf]);
+        assert!(db.add_attachment(entry_id + 1, "x.txt", None, b"x").is_err());
+    }
+
+    #[test]
+    fn extract_to_directory() {
+        let (db, entry_id) = db_with_entry();
+        let attachment = db.add_attachment(entry_id, "../notes.txt", None, b"hello").unwrap();
+        let dir = std::env::temp_dir().join(format!("journaldb-extract-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = db.extract_attachment(attachment.id, &dir).unwrap();
+        assert_eq!(path, dir.join("notes.txt"));
+        assert_eq!(fs::read(&path).unwrap(), b"hello");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn deleting_entry_removes_attachments() {
+        let (db, entry_id) = db_with_entry();
+        let attachment = db.add_attachment(entry_id, "a.txt", None, b"a").unwrap();
+        let entry = db.get_entry_by_id(entry_id).unwrap();
+        db.delete_entry(&entry).unwrap();
+        assert_eq!(db.get_attachment(attachment.id).unwrap(), None);
+    }
+}
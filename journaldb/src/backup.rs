@@ -0,0 +1,147 @@
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+use rusqlite::{backup::Progress, Connection, DatabaseName, OpenFlags};
+
+use crate::{changes::Change, Db, BUSY_TIMEOUT, MIGRATIONS};
+
+/// Time in backup file names, sorting them from the oldest. Milliseconds keep a backup taken
+/// right before a restore apart from one taken just before.
+const STAMP_FORMAT: &str = "%Y%m%d-%H%M%S%.3f";
+
+/// Fails unless `path` is an intact journal this version can open
+pub fn check_backup(path: &Path) -> Result<(), Box<dyn Error>> {
+    let invalid = |why: &str| format!("{} is no usable journal: {}", path.display(), why);
+    if !path.is_file() {
+        return Err(invalid("no such file").into());
+    }
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let integrity: String = conn
+        .query_row("PRAGMA integrity_check", (), |r| r.get(0))
+        .map_err(|e| invalid(&e.to_string()))?;
+    if integrity != "ok" {
+        return Err(invalid(&integrity).into());
+    }
+    let tables: u32 = conn.query_row(
+        "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name IN ('entries', 'tags', 'entry_tags')",
+        (),
+        |r| r.get(0),
+    )?;
+    if tables != 3 {
+        return Err(invalid("the journal tables are missing").into());
+    }
+    let version: usize = conn.query_row("PRAGMA user_version", (), |r| r.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(invalid("it was written by a newer version").into());
+    }
+    Ok(())
+}
+
+impl Db {
+    /// Copies the journal to `path` with SQLite's online backup, writers only wait for single steps
+    pub fn backup_to(&self, path: &Path) -> Result<(), rusqlite::Error> {
+        self.conn().backup(DatabaseName::Main, path, None::<fn(Progress)>)?;
+        // A single file, without the -wal and -shm files opening it in WAL mode leaves behind
+        Connection::open(path)?.pragma_update(None, "journal_mode", "DELETE")
+    }
+
+    /// Writes a backup named after the journal and the current time into `dir`, like
+    /// `journal-20240301-120000.000.db`, and removes all but the `keep` newest of them
+    pub fn rotate_backups(&self, dir: &Path, keep: usize) -> Result<PathBuf, Box<dyn Error>> {
+        let stem = Path::new(&self.filename)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "journal".to_string());
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}-{}.db", stem, Local::now().format(STAMP_FORMAT)));
+        if path.exists() {
+            return Err(format!("{} already exists", path.display()).into());
+        }
+        self.backup_to(&path)?;
+
+        let prefix = format!("{}-", stem);
+        let mut backups: Vec<PathBuf> = fs::read_dir(dir)?
+            .map(|f| f.map(|f| f.path()))
+            .collect::<Result<_, _>>()?;
+        backups.retain(|b| {
+            let name = b.file_name().unwrap_or_default().to_string_lossy();
+            name.strip_prefix(&prefix)
+                .and_then(|rest| rest.strip_suffix(".db"))
+                .is_some_and(|stamp| chrono::NaiveDateTime::parse_from_str(stamp, STAMP_FORMAT).is_ok())
+        });
+        backups.sort();
+        for old in &backups[..backups.len().saturating_sub(keep.max(1))] {
+            fs::remove_file(old)?;
+        }
+        Ok(path)
+    }
+
+    /// Replaces the journal with the backup at `path` once `check_backup` accepts it. A restored
+    /// encrypted journal has to be unlocked again.
+    pub fn restore_from(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        check_backup(path)?;
+        if self.filename == ":memory:" {
+            return Err("only journal files can be restored".into());
+        }
+        {
+            // Other threads wait until the journal is replaced
+            let _conn = self.conn();
+            let mut target = Connection::open(&self.filename)?;
+            target.busy_timeout(BUSY_TIMEOUT)?;
+            target.restore(DatabaseName::Main, path, None::<fn(Progress)>)?;
+        }
+        *self.cipher.write() = None;
+        // Backups from older versions get the newer tables
+        self.initialize_db()?;
+        if !self.is_locked()? {
+            self.update_entries()?;
+        }
+        self.notify(Change::External);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Entry;
+
+    #[test]
+    fn backup_rotate_and_restore() {
+        let dir = std::env::temp_dir().join(format!("journaldb-backup-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let db = Db::new(dir.join("journal.db").to_str().unwrap());
+        db.initialize_db().unwrap();
+        db.create_entry(&mut Entry::new("Kept".to_string(), String::new(), None)).unwrap();
+
+        let backups = dir.join("backups");
+        for stamp in ["20240101-080000.000", "20240102-080000.000", "20240103-080000.000"] {
+            fs::create_dir_all(&backups).unwrap();
+            fs::write(backups.join(format!("journal-{}.db", stamp)), "").unwrap();
+        }
+        fs::write(backups.join("notes.txt"), "not a backup").unwrap();
+        let backup = db.rotate_backups(&backups, 2).unwrap();
+        let mut left: Vec<String> = fs::read_dir(&backups)
+            .unwrap()
+            .map(|f| f.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        left.sort();
+        assert_eq!(left, vec!["journal-20240103-080000.000.db".to_string(), backup.file_name().unwrap().to_string_lossy().to_string(), "notes.txt".to_string()]);
+        check_backup(&backup).unwrap();
+
+        db.create_entry(&mut Entry::new("Lost".to_string(), String::new(), None)).unwrap();
+        assert!(db.restore_from(&backups.join("journal-20240103-080000.000.db")).is_err());
+        assert!(db.restore_from(&backups.join("notes.txt")).is_err());
+        assert_eq!(db.get_entries().len(), 2);
+        db.restore_from(&backup).unwrap();
+        assert_eq!(db.get_entries().iter().map(Entry::get_title).collect::<Vec<_>>(), vec!["Kept"]);
+
+        drop(db);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
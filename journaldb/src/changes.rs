@@ -0,0 +1,85 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use rusqlite::Connection;
+
+use crate::Db;
+
+/// A change to the journal, sent to subscribers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// An entry was created, edited or deleted through this `Db`
+    Entry(u32),
+    /// Another connection, e.g. journalcli in another process, wrote to the journal
+    External,
+}
+
+/// Counter SQLite bumps when another connection commits to the file
+pub(crate) fn data_version(conn: &Connection) -> Result<i64, rusqlite::Error> {
+    conn.query_row("PRAGMA data_version", (), |r| r.get(0))
+}
+
+impl Db {
+    /// Changes from now on. Writes through this `Db` are sent right away, writes by other processes
+    /// once `poll_changes` notices them. The subscription ends when the receiver is dropped.
+    pub fn subscribe(&self) -> Receiver<Change> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().push(sender);
+        receiver
+    }
+
+    pub(crate) fn notify(&self, change: Change) {
+        self.subscribers.lock().retain(|s: &Sender<Change>| s.send(change).is_ok());
+    }
+
+    /// Checks whether another connection wrote since the last check, and tells subscribers if so.
+    /// Cheap enough to call a few times a second.
+    pub fn poll_changes(&self) -> Result<bool, rusqlite::Error> {
+        let version = data_version(&self.conn())?;
+        let mut last = self.data_version.lock();
+        if *last == version {
+            return Ok(false);
+        }
+        *last = version;
+        drop(last);
+        self.notify(Change::External);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Entry;
+
+    #[test]
+    fn subscribers_hear_about_changes() {
+        let path = std::env::temp_dir().join(format!("journaldb-changes-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        let db = Db::new(path);
+        db.initialize_db().unwrap();
+        let changes = db.subscribe();
+        let mut entry = Entry::new("Title".to_string(), "Content".to_string(), None);
+        db.create_entry(&mut entry).unwrap();
+        entry.set_content("Changed".to_string());
+        db.edit_entry(&mut entry).unwrap();
+        // Saving without changes isn't a change
+        db.edit_entry(&mut entry).unwrap();
+        db.delete_entry(&entry).unwrap();
+        assert_eq!(changes.try_iter().collect::<Vec<_>>(), vec![Change::Entry(entry.id); 3]);
+        assert!(!db.poll_changes().unwrap());
+
+        let other = Db::new(path);
+        other.create_entry(&mut Entry::new("Elsewhere".to_string(), String::new(), None)).unwrap();
+        assert!(db.poll_changes().unwrap());
+        assert!(!db.poll_changes().unwrap());
+        assert_eq!(changes.try_iter().collect::<Vec<_>>(), vec![Change::External]);
+
+        drop(changes);
+        db.create_entry(&mut Entry::new("Unheard".to_string(), String::new(), None)).unwrap();
+        assert!(db.subscribers.lock().is_empty());
+        drop((db, other));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+        }
+    }
+}
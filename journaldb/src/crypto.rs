@@ -0,0 +1,452 @@
+use std::fmt;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use rusqlite::{
+    functions::FunctionFlags,
+    types::{Type, Value},
+    OptionalExtension, Transaction, TransactionBehavior,
+};
+
+use crate::Db;
+
+/// Encrypted as a known value to tell a wrong passphrase from a corrupt journal
+const VERIFIER: &[u8] = b"journal";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+#[derive(Debug)]
+pub enum CryptoError {
+    WrongPassphrase,
+    /// The journal is encrypted and was not unlocked with its passphrase
+    Locked,
+    NotEncrypted,
+    /// Stored data could not be decrypted with the right key
+    Corrupt,
+    /// The journal is mirrored into a git repository, which would keep the text in plain files
+    Mirrored,
+    Kdf(String),
+    Sqlite(rusqlite::Error),
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoError::WrongPassphrase => write!(f, "wrong passphrase"),
+            CryptoError::Locked => write!(f, "the journal is encrypted, a passphrase is needed to open it"),
+            CryptoError::NotEncrypted => write!(f, "the journal is not encrypted"),
+            CryptoError::Corrupt => write!(f, "encrypted data is corrupt"),
+            CryptoError::Mirrored => write!(f, "the journal is mirrored into git, stop mirroring it before encrypting it"),
+            CryptoError::Kdf(e) => write!(f, "can't derive key: {}", e),
+            CryptoError::Sqlite(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+impl From<rusqlite::Error> for CryptoError {
+    fn from(e: rusqlite::Error) -> Self {
+        CryptoError::Sqlite(e)
+    }
+}
+
+impl From<CryptoError> for rusqlite::Error {
+    fn from(e: CryptoError) -> Self {
+        match e {
+            CryptoError::Sqlite(e) => e,
+            e => rusqlite::Error::FromSqlConversionFailure(0, Type::Blob, Box::new(e)),
+        }
+    }
+}
+
+/// Key derived from the passphrase, encrypting values as nonce followed by ciphertext
+#[derive(Clone)]
+pub(crate) struct Cipher {
+    aead: XChaCha20Poly1305,
+}
+
+/// Argon2 cost of new keys. Stored with the salt, so it can be raised without breaking old journals.
+fn kdf_params() -> Params {
+    if cfg!(test) {
+        Params::new(64, 1, 1, None).unwrap()
+    } else {
+        Params::default()
+    }
+}
+
+impl Cipher {
+    fn derive(passphrase: &str, salt: &[u8], params: Params) -> Result<Self, CryptoError> {
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| CryptoError::Kdf(e.to_string()))?;
+        Ok(Cipher {
+            aead: XChaCha20Poly1305::new(&key.into()),
+        })
+    }
+
+    fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut data = nonce.to_vec();
+        // Only fails for plaintexts larger than 256 GiB
+        data.extend(self.aead.encrypt(&nonce, plaintext).expect("plaintext too large"));
+        data
+    }
+
+    fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        if data.len() < NONCE_LEN {
+            return Err(CryptoError::Corrupt);
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        self.aead
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| CryptoError::Corrupt)
+    }
+}
+
+fn open_text(cipher: Option<&Cipher>, value: Value) -> Result<String, CryptoError> {
+    match value {
+        Value::Null => Ok(String::new()),
+        Value::Text(text) => Ok(text),
+        Value::Blob(data) => {
+            let cipher = cipher.ok_or(CryptoError::Locked)?;
+            String::from_utf8(cipher.decrypt(&data)?).map_err(|_| CryptoError::Corrupt)
+        }
+        Value::Integer(i) => Ok(i.to_string()),
+        Value::Real(r) => Ok(r.to_string()),
+    }
+}
+
+/// Salt, Argon2 parameters and verifier of an encrypted journal
+struct KeyInfo {
+    salt: Vec<u8>,
+    params: Params,
+    verifier: Vec<u8>,
+}
+
+impl Db {
+    /// Opens an encrypted journal, failing with `CryptoError::WrongPassphrase` for a wrong passphrase
+    pub fn open_encrypted(filename: &str, passphrase: &str) -> Result<Db, CryptoError> {
+        let db = Db::new(filename);
+        db.initialize_db()?;
+        db.unlock(passphrase)?;
+        Ok(db)
+    }
+
+    fn key_info(&self) -> Result<Option<KeyInfo>, rusqlite::Error> {
+        self.conn().query_row(
+            "SELECT salt, m_cost, t_cost, p_cost, verifier FROM encryption",
+            (),
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)),
+        ).optional().map(|info| info.map(|(salt, m, t, p, verifier)| KeyInfo {
+            salt,
+            params: Params::new(m, t, p, None).unwrap_or_default(),
+            verifier,
+        }))
+    }
+
+    /// Whether entries are stored encrypted. Needs an initialized journal.
+    pub fn is_encrypted(&self) -> Result<bool, rusqlite::Error> {
+        Ok(self.key_info()?.is_some())
+    }
+
+    /// Whether the journal is encrypted and still needs its passphrase
+    pub fn is_locked(&self) -> Result<bool, rusqlite::Error> {
+        // Not holding the key while waiting for the connection, see `Db` for the lock order
+        let unlocked = self.cipher.read().is_some();
+        Ok(!unlocked && self.is_encrypted()?)
+    }
+
+    /// Derives the key of an encrypted journal and loads its entries
+    pub fn unlock(&self, passphrase: &str) -> Result<(), CryptoError> {
+        let info = self.key_info()?.ok_or(CryptoError::NotEncrypted)?;
+        let cipher = Cipher::derive(passphrase, &info.salt, info.params)?;
+        match cipher.decrypt(&info.verifier) {
+            Ok(verifier) if verifier == VERIFIER => {}
+            _ => return Err(CryptoError::WrongPassphrase),
+        }
+        *self.cipher.write() = Some(cipher);
+        self.update_entries()?;
+        Ok(())
+    }
+
+    /// Forgets the key when another process changed the passphrase or removed the encryption, so
+    /// nothing is written with a key the journal no longer has. Returns whether the journal is
+    /// locked now.
+    pub fn recheck_key(&self) -> Result<bool, rusqlite::Error> {
+        let _conn = self.conn();
+        let info = self.key_info()?;
+        let mut cipher = self.cipher.write();
+        let current = match (&info, &*cipher) {
+            (Some(info), Some(key)) => key.decrypt(&info.verifier).is_ok_and(|v| v == VERIFIER),
+            _ => true,
+        };
+        if info.is_none() || !current {
+            *cipher = None;
+        }
+        Ok(info.is_some() && cipher.is_none())
+    }
+
+    /// Encrypts the journal with a new passphrase, or stores it in plain text again with `None`.
+    /// An encrypted journal has to be unlocked first, and a mirrored one can't be encrypted.
+    pub fn set_passphrase(&self, passphrase: Option<&str>) -> Result<(), CryptoError> {
+        // Derived before taking the journal, it takes a while on purpose
+        let (cipher, info) = match passphrase {
+            Some(passphrase) => {
+                let mut salt = vec![0u8; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                let params = kdf_params();
+                let cipher = Cipher::derive(passphrase, &salt, params.clone())?;
+                let verifier = cipher.encrypt(VERIFIER);
+                (Some(cipher), Some(KeyInfo { salt, params, verifier }))
+            }
+            None => (None, None),
+        };
+        // Other threads and processes wait until the journal is sealed with the new key, so nothing
+        // they write is left out of the copy read below or overwritten with it
+        let conn = self.conn();
+        let tx = Transaction::new_unchecked(&conn, TransactionBehavior::Immediate)?;
+        if self.is_locked()? {
+            return Err(CryptoError::Locked);
+        }
+        if passphrase.is_some() && self.get_setting(crate::MIRROR_SETTING)?.is_some() {
+            return Err(CryptoError::Mirrored);
+        }
+        self.update_entries()?;
+        let mut attachments = Vec::new();
+        {
+            let mut stmt = tx.prepare("SELECT attachment_id, filename, mime, hash, data FROM attachments")?;
+            let rows = stmt.query_map((), |r| {
+                Ok((r.get::<usize, u32>(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get::<usize, Vec<u8>>(4)?))
+            })?;
+            for row in rows {
+                let (id, filename, mime, hash, data) = row?;
+                let (filename, mime, hash) = (self.open_text(filename)?, self.open_text(mime)?, self.open_text(hash)?);
+                attachments.push((id, filename, mime, hash, self.open_bytes(data)?));
+            }
+        }
+        let mut revisions = Vec::new();
+        {
+            let mut stmt = tx.prepare("SELECT revision_id, title, content FROM entry_revisions")?;
+            for row in stmt.query_map((), |r| Ok((r.get::<usize, u32>(0)?, r.get(1)?, r.get(2)?)))? {
+                let (id, title, content) = row?;
+                revisions.push((id, self.open_text(title)?, self.open_text(content)?));
+            }
+        }
+        let old_cipher = std::mem::replace(&mut *self.cipher.write(), cipher);
+
+        let result = (|| -> Result<(), rusqlite::Error> {
+            for entry in &self.get_entries() {
+                tx.execute(
+                    "UPDATE entries SET entry_title = ?1, entry_content = ?2 WHERE entry_id = ?3",
+                    (self.seal_text(&entry.title), self.seal_text(&entry.content), entry.id),
+                )?;
+                // Re-encrypting is no edit, undo what the update trigger did
+                tx.execute(
+                    "UPDATE entries SET entry_updated_time = ?1 WHERE entry_id = ?2",
+                    (entry.updated_time, entry.id),
+                )?;
+            }
+            for (id, filename, mime, hash, data) in &attachments {
+                tx.execute(
+                    "UPDATE attachments SET filename = ?1, mime = ?2, hash = ?3, data = ?4 WHERE attachment_id = ?5",
+                    (self.seal_text(filename), self.seal_text(mime), self.seal_text(hash), self.seal_bytes(data), id),
+                )?;
+            }
+            for (id, title, content) in &revisions {
+                tx.execute(
+                    "UPDATE entry_revisions SET title = ?1, content = ?2 WHERE revision_id = ?3",
+                    (self.seal_text(title), self.seal_text(content), id),
+                )?;
+            }
+            // Link targets are sealed like the content they come from
+            self.save_all_links()?;
+            tx.execute("DELETE FROM encryption", ())?;
+            if let Some(info) = &info {
+                tx.execute(
+                    "INSERT INTO encryption (id, salt, m_cost, t_cost, p_cost, verifier) VALUES (0, ?1, ?2, ?3, ?4, ?5)",
+                    (&info.salt, info.params.m_cost(), info.params.t_cost(), info.params.p_cost(), &info.verifier),
+                )?;
+            }
+            Ok(())
+        })();
+        match result {
+            Ok(()) => tx.commit()?,
+            Err(e) => {
+                drop(tx);
+                *self.cipher.write() = old_cipher;
+                return Err(e.into());
+            }
+        }
+        // The old text is still in freed pages and the write-ahead log, rebuild the file without it
+        conn.pragma_update(None, "secure_delete", true)?;
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", (), |_| Ok(()))?;
+        conn.execute("VACUUM", ())?;
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", (), |_| Ok(()))?;
+        self.update_entries()?;
+        Ok(())
+    }
+
+    /// Value to store for a title or content: encrypted bytes in encrypted journals, text otherwise
+    pub(crate) fn seal_text(&self, text: &str) -> Value {
+        match &*self.cipher.read() {
+            Some(cipher) => Value::Blob(cipher.encrypt(text.as_bytes())),
+            None => Value::Text(text.to_string()),
+        }
+    }
+
+    /// Reads a stored title or content
+    pub(crate) fn open_text(&self, value: Value) -> Result<String, CryptoError> {
+        open_text(self.cipher.read().as_ref(), value)
+    }
+
+    /// Lets SQL read titles and content with `journal_text(column)`, decrypted with the current key
+    pub(crate) fn register_text_function(&self) -> Result<(), rusqlite::Error> {
+        let cipher = self.cipher.read().clone();
+        self.conn().create_scalar_function(
+            "journal_text",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            move |ctx| Ok(open_text(cipher.as_ref(), ctx.get(0)?)?),
+        )
+    }
+
+    pub(crate) fn seal_bytes(&self, data: &[u8]) -> Vec<u8> {
+        match &*self.cipher.read() {
+            Some(cipher) => cipher.encrypt(data),
+            None => data.to_vec(),
+        }
+    }
+
+    pub(crate) fn open_bytes(&self, data: Vec<u8>) -> Result<Vec<u8>, CryptoError> {
+        let cipher = self.cipher.read().clone();
+        match &cipher {
+            Some(cipher) => cipher.decrypt(&data),
+            None if self.is_encrypted()? => Err(CryptoError::Locked),
+            None => Ok(data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Entry;
+
+    fn temp_db(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("journaldb-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn encrypt_and_reopen() {
+        let path = temp_db("encrypt");
+        let db = Db::new(&path);
+        db.initialize_db().unwrap();
+        let mut entry = Entry::new("secret title".to_string(), "secret content".to_string(), None);
+        db.create_entry(&mut entry).unwrap();
+        db.add_attachment(entry.get_id(), "a.txt", None, b"secret file").unwrap();
+        let updated = db.get_entries()[0].updated_time;
+        db.set_passphrase(Some("hunter2")).unwrap();
+        assert!(db.is_encrypted().unwrap());
+        let raw: Value = db.conn().query_row("SELECT entry_title FROM entries", (), |r| r.get(0)).unwrap();
+        assert!(matches!(raw, Value::Blob(_)));
+        // Names and hashes of attachments tell about their content as well
+        let raw: (Value, Value, Value) = db.conn()
+            .query_row("SELECT filename, mime, hash FROM attachments", (), |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap();
+        assert!(matches!(raw, (Value::Blob(_), Value::Blob(_), Value::Blob(_))));
+        drop(db);
+
+        let locked = Db::new(&path);
+        locked.initialize_db().unwrap();
+        assert!(locked.is_locked().unwrap());
+        assert!(locked.update_entries().is_err());
+        assert!(matches!(Db::open_encrypted(&path, "wrong"), Err(CryptoError::WrongPassphrase)));
+
+        let db = Db::open_encrypted(&path, "hunter2").unwrap();
+        assert_eq!(db.get_entries()[0].title, "secret title");
+        assert_eq!(db.get_entries()[0].updated_time, updated);
+        let attachment = db.get_attachments(entry.get_id()).unwrap().remove(0);
+        assert_eq!((attachment.filename.as_str(), attachment.hash), ("a.txt", crate::attachments::sha256(b"secret file")));
+        assert_eq!(db.get_attachment_data(attachment.id).unwrap(), b"secret file");
+        // Adding it again still finds the stored one
+        assert_eq!(db.add_attachment(entry.get_id(), "a.txt", None, b"secret file").unwrap().id, attachment.id);
+
+        // New entries and edits are encrypted as well
+        let mut second = Entry::new("second".to_string(), "more".to_string(), None);
+        db.create_entry(&mut second).unwrap();
+        second.content = "edited".to_string();
+        db.edit_entry(&mut second).unwrap();
+        let raw: Value = db.conn()
+            .query_row("SELECT entry_content FROM entries WHERE entry_id = ?1", (second.get_id(),), |r| r.get(0))
+            .unwrap();
+        assert!(matches!(raw, Value::Blob(_)));
+        drop(db);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn encrypting_leaves_no_plain_text() {
+        let path = temp_db("plain-text");
+        let db = Db::new(&path);
+        db.initialize_db().unwrap();
+        // Long enough to span pages that are freed when the encrypted version is written
+        let content = "diary ".repeat(5000);
+        let mut entry = Entry::new("diary title".to_string(), content.clone(), None);
+        db.create_entry(&mut entry).unwrap();
+        entry.content = format!("{}edited", content);
+        db.edit_entry(&mut entry).unwrap();
+        db.add_attachment(entry.get_id(), "diary.txt", None, content.as_bytes()).unwrap();
+        db.set_passphrase(Some("hunter2")).unwrap();
+
+        for file in [path.clone(), format!("{}-wal", path)] {
+            let bytes = std::fs::read(&file).unwrap_or_default();
+            for plain in ["diary title", "diary diary", "diary.txt"] {
+                assert!(!bytes.windows(plain.len()).any(|w| w == plain.as_bytes()), "{} found in {}", plain, file);
+            }
+        }
+        drop(db);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn change_and_remove_passphrase() {
+        let path = temp_db("passphrase");
+        let db = Db::new(&path);
+        db.initialize_db().unwrap();
+        let mut entry = Entry::new("title".to_string(), "content".to_string(), None);
+        db.create_entry(&mut entry).unwrap();
+        assert!(matches!(db.unlock("x"), Err(CryptoError::NotEncrypted)));
+        db.set_passphrase(Some("old")).unwrap();
+        db.set_passphrase(Some("new")).unwrap();
+        *db.cipher.write() = None;
+        assert!(matches!(db.set_passphrase(None), Err(CryptoError::Locked)));
+        assert!(matches!(db.unlock("old"), Err(CryptoError::WrongPassphrase)));
+        db.unlock("new").unwrap();
+        assert!(!db.recheck_key().unwrap());
+
+        // Another process changing the passphrase leaves this one locked
+        let other = Db::new(&path);
+        other.initialize_db().unwrap();
+        other.unlock("new").unwrap();
+        other.set_passphrase(Some("newer")).unwrap();
+        assert!(db.recheck_key().unwrap());
+        db.unlock("newer").unwrap();
+        // And removing it makes this one write plain text again
+        other.set_passphrase(None).unwrap();
+        assert!(!db.recheck_key().unwrap());
+        db.set_passphrase(Some("newest")).unwrap();
+        db.set_passphrase(None).unwrap();
+        assert!(!db.is_encrypted().unwrap());
+        let raw: Value = db.conn().query_row("SELECT entry_content FROM entries", (), |r| r.get(0)).unwrap();
+        assert_eq!(raw, Value::Text("content".to_string()));
+        drop((db, other));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
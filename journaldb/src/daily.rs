@@ -0,0 +1,151 @@
+use std::{collections::HashMap, fmt::Write};
+
+use chrono::{DateTime, FixedOffset, NaiveDate};
+
+use crate::{
+    templates::Template,
+    time::{self, TimeError},
+    Db, Entry,
+};
+
+/// Settings key of the title of daily entries, a chrono `strftime` string
+pub const TITLE_SETTING: &str = "daily.title";
+/// Settings key of the name of the template new daily entries start from
+pub const TEMPLATE_SETTING: &str = "daily.template";
+
+pub const DEFAULT_TITLE: &str = "%Y-%m-%d";
+
+/// Fails if chrono can't format dates with `pattern`, e.g. when it asks for the time of day or a
+/// time zone, which dates don't have
+pub fn check_title(pattern: &str) -> Result<(), TimeError> {
+    time::check_format(pattern)?;
+    let mut title = String::new();
+    let sample = NaiveDate::from_ymd_opt(2000, 1, 1).expect("valid date");
+    write!(title, "{}", sample.format(pattern)).map_err(|_| TimeError::InvalidFormat(pattern.to_string()))
+}
+
+impl Db {
+    /// Title of the daily entry of `date`, invalid patterns fall back to the default
+    pub fn daily_title(&self, date: NaiveDate) -> Result<String, rusqlite::Error> {
+        let pattern = self
+            .get_setting(TITLE_SETTING)?
+            .filter(|p| check_title(p).is_ok())
+            .unwrap_or_else(|| DEFAULT_TITLE.to_string());
+        Ok(date.format(&pattern).to_string())
+    }
+
+    /// Template configured for daily entries, if it exists
+    pub fn daily_template(&self) -> Result<Option<Template>, rusqlite::Error> {
+        match self.get_setting(TEMPLATE_SETTING)? {
+            Some(name) => self.get_template(&name),
+            None => Ok(None),
+        }
+    }
+
+    /// Entry written on `date`, on the clock where it was written, with the daily title
+    pub fn find_daily_entry(&self, date: NaiveDate) -> Result<Option<Entry>, rusqlite::Error> {
+        let title = self.daily_title(date)?;
+        Ok(self
+            .entries
+            .read()
+            .iter()
+            .filter(|e| e.get_created_time().date_naive() == date && e.title == title)
+            .min_by_key(|e| e.id)
+            .cloned())
+    }
+
+    /// Returns the daily entry of the day of `now`, creating it from the daily template when missing.
+    /// `answers` fill the `{{prompt:...}}` questions of the template.
+    pub fn open_daily_entry(
+        &self,
+        now: DateTime<FixedOffset>,
+        answers: &HashMap<String, String>,
+    ) -> Result<Entry, rusqlite::Error> {
+        let date = now.date_naive();
+        if let Some(entry) = self.find_daily_entry(date)? {
+            return Ok(entry);
+        }
+        let mut entry = match self.daily_template()? {
+            Some(template) => template.render(date, answers),
+            None => Entry::new(String::new(), String::new(), None),
+        };
+        entry.set_title(self.daily_title(date)?);
+        entry.set_created_time(now);
+        self.create_entry(&mut entry)?;
+        Ok(entry)
+    }
+
+    /// Adds `text` as a line starting with the time of `now` to the daily entry, creating it when missing
+    pub fn append_to_daily_entry(&self, now: DateTime<FixedOffset>, text: &str) -> Result<Entry, rusqlite::Error> {
+        let mut entry = self.open_daily_entry(now, &HashMap::new())?;
+        let mut content = entry.get_content();
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&format!("{} {}", now.format("%H:%M"), text.trim_end()));
+        entry.set_content(content);
+        self.edit_entry(&mut entry)?;
+        Ok(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(time).unwrap()
+    }
+
+    #[test]
+    fn open_or_create_daily_entry() {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        db.set_setting(TITLE_SETTING, "Daily %d.%m.").unwrap();
+        db.set_setting(TEMPLATE_SETTING, "daily").unwrap();
+        db.save_template(&Template {
+            name: "daily".to_string(),
+            title: "ignored".to_string(),
+            content: "# {{weekday}}\nMood: {{prompt:Mood?}}".to_string(),
+            tags: vec!["daily".to_string()],
+        })
+        .unwrap();
+        // An entry of the same day with another title is not the daily entry
+        let mut other =
+            Entry::new("Other".to_string(), String::new(), None).with_created_time(at("2023-03-01T08:00:00+01:00"));
+        db.create_entry(&mut other).unwrap();
+
+        let answers = HashMap::from([("Mood?".to_string(), "good".to_string())]);
+        let entry = db.open_daily_entry(at("2023-03-01T09:00:00+01:00"), &answers).unwrap();
+        assert_eq!(entry.get_title(), "Daily 01.03.");
+        assert_eq!(entry.get_content(), "# Wednesday\nMood: good");
+        assert_eq!(entry.get_tags().unwrap()[0].get_tag(), "daily");
+        let again = db.open_daily_entry(at("2023-03-01T23:00:00+01:00"), &HashMap::new()).unwrap();
+        assert_eq!(again.get_id(), entry.get_id());
+
+        let appended = db.append_to_daily_entry(at("2023-03-01T14:05:00+01:00"), "Lunch\n").unwrap();
+        assert_eq!(appended.get_id(), entry.get_id());
+        assert_eq!(db.get_entry_by_id(entry.get_id()).unwrap().get_content(), "# Wednesday\nMood: good\n14:05 Lunch");
+
+        // The next day gets a new entry, also when appending to it first
+        let next = db.append_to_daily_entry(at("2023-03-02T07:30:00+01:00"), "Up early").unwrap();
+        assert_ne!(next.get_id(), entry.get_id());
+        assert_eq!(next.get_title(), "Daily 02.03.");
+        assert_eq!(next.get_content(), "# Thursday\nMood: \n07:30 Up early");
+    }
+
+    #[test]
+    fn titles_without_time_of_day() {
+        assert!(check_title("Daily %A, %d.%m.").is_ok());
+        assert_eq!(check_title("%H:%M"), Err(TimeError::InvalidFormat("%H:%M".to_string())));
+        assert!(check_title("%Z").is_err());
+        assert!(check_title("%Q").is_err());
+
+        // Titles set around `journalcli config` fall back to the default instead of panicking
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        db.set_setting(TITLE_SETTING, "%Y %H:%M").unwrap();
+        let date = NaiveDate::from_ymd_opt(2023, 3, 1).unwrap();
+        assert_eq!(db.daily_title(date).unwrap(), "2023-03-01");
+    }
+}
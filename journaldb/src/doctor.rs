@@ -0,0 +1,144 @@
+use std::fmt;
+
+use crate::Db;
+
+/// Something wrong with the journal file, found by `Db::check`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// A message of `PRAGMA integrity_check`. Only restoring a backup helps.
+    Corrupt(String),
+    /// A row of `table` referring to a row of `parent` that doesn't exist, like an attachment of a
+    /// deleted entry
+    ForeignKey { table: String, rowid: i64, parent: String },
+    /// A tag given to an entry that doesn't exist
+    OrphanEntryTag { entry_id: u32, tag_id: u32 },
+    /// An entry tagged with a tag that doesn't exist
+    MissingTag { entry_id: u32, tag_id: u32 },
+}
+
+impl Problem {
+    /// Whether `Db::repair` fixes it, by removing the dangling row
+    pub fn is_repairable(&self) -> bool {
+        !matches!(self, Problem::Corrupt(_))
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Corrupt(message) => write!(f, "corrupt: {}", message),
+            Problem::ForeignKey { table, rowid, parent } => {
+                write!(f, "row {} of {} refers to a missing row of {}", rowid, table, parent)
+            }
+            Problem::OrphanEntryTag { entry_id, tag_id } => {
+                write!(f, "tag {} is given to entry {}, which doesn't exist", tag_id, entry_id)
+            }
+            Problem::MissingTag { entry_id, tag_id } => {
+                write!(f, "entry {} has tag {}, which doesn't exist", entry_id, tag_id)
+            }
+        }
+    }
+}
+
+impl Db {
+    /// Runs SQLite's integrity and foreign key checks and looks for tags pointing nowhere
+    pub fn check(&self) -> Result<Vec<Problem>, rusqlite::Error> {
+        let conn = self.conn();
+        let mut problems = Vec::new();
+        let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+        for message in stmt.query_map((), |r| r.get::<usize, String>(0))? {
+            let message = message?;
+            if message != "ok" {
+                problems.push(Problem::Corrupt(message));
+            }
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT entry_id, tag_id, entry_id NOT IN (SELECT entry_id FROM entries) FROM entry_tags
+            WHERE entry_id NOT IN (SELECT entry_id FROM entries) OR tag_id NOT IN (SELECT tag_id FROM tags)",
+        )?;
+        for row in stmt.query_map((), |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))? {
+            let (entry_id, tag_id, orphan) = row?;
+            problems.push(match orphan {
+                true => Problem::OrphanEntryTag { entry_id, tag_id },
+                false => Problem::MissingTag { entry_id, tag_id },
+            });
+        }
+
+        // entry_tags is covered above, with the ids that help more
+        let mut stmt = conn.prepare("PRAGMA foreign_key_check")?;
+        for row in stmt.query_map((), |r| Ok((r.get::<usize, String>(0)?, r.get(1)?, r.get(2)?)))? {
+            let (table, rowid, parent) = row?;
+            if table != "entry_tags" {
+                problems.push(Problem::ForeignKey { table, rowid, parent });
+            }
+        }
+        Ok(problems)
+    }
+
+    /// Removes the rows `check` finds dangling, returns how many. Corruption is left alone.
+    pub fn repair(&self) -> Result<usize, rusqlite::Error> {
+        let conn = self.conn();
+        let tx = conn.unchecked_transaction()?;
+        let mut repaired = 0;
+        for problem in self.check()? {
+            repaired += match &problem {
+                Problem::Corrupt(_) => 0,
+                Problem::ForeignKey { table, rowid, .. } => {
+                    tx.execute(&format!("DELETE FROM \"{}\" WHERE rowid = ?1", table.replace('"', "\"\"")), (rowid,))?
+                }
+                Problem::OrphanEntryTag { entry_id, tag_id } | Problem::MissingTag { entry_id, tag_id } => {
+                    tx.execute("DELETE FROM entry_tags WHERE entry_id = ?1 AND tag_id = ?2", (entry_id, tag_id))?
+                }
+            };
+        }
+        tx.commit()?;
+        self.update_entries()?;
+        Ok(repaired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Entry, Tag};
+
+    #[test]
+    fn finds_and_repairs_dangling_rows() {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        let mut entry = Entry::new("Title".to_string(), String::new(), Some(vec![Tag::new("kept".to_string())]));
+        db.create_entry(&mut entry).unwrap();
+        db.add_attachment(entry.get_id(), "a.txt", None, b"a").unwrap();
+        assert_eq!(db.check().unwrap(), vec![]);
+
+        // Rows older versions or other tools not enforcing foreign keys could leave behind
+        db.conn()
+            .execute_batch(
+                "PRAGMA foreign_keys = OFF;
+                INSERT INTO entry_tags (entry_id, tag_id) VALUES (1, 99);
+                INSERT INTO entry_tags (entry_id, tag_id) VALUES (42, 1);
+                INSERT INTO attachments (entry_id, filename, mime, hash, size, data) VALUES (42, 'b', 'text/plain', '', 0, x'');
+                PRAGMA foreign_keys = ON;",
+            )
+            .unwrap();
+        let problems = db.check().unwrap();
+        assert_eq!(
+            problems,
+            vec![
+                Problem::MissingTag { entry_id: 1, tag_id: 99 },
+                Problem::OrphanEntryTag { entry_id: 42, tag_id: 1 },
+                Problem::ForeignKey { table: "attachments".to_string(), rowid: 2, parent: "entries".to_string() },
+            ]
+        );
+        assert!(problems.iter().all(Problem::is_repairable));
+        assert_eq!(problems[0].to_string(), "entry 1 has tag 99, which doesn't exist");
+        // Loading skips the missing tag instead of failing
+        db.update_entries().unwrap();
+        assert_eq!(db.get_entries()[0].get_tags().unwrap().len(), 1);
+
+        assert_eq!(db.repair().unwrap(), 3);
+        assert_eq!(db.check().unwrap(), vec![]);
+        assert_eq!(db.get_attachments(entry.get_id()).unwrap().len(), 1);
+    }
+}
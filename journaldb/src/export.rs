@@ -0,0 +1,91 @@
+use std::error::Error;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::Serialize;
+
+use crate::{store::JournalStore, Entry};
+
+/// Everything in a journal as one document, in the format `journalcli import` reads
+#[derive(Serialize)]
+pub struct Export {
+    pub entries: Vec<ExportedEntry>,
+    pub saved_searches: Vec<ExportedSearch>,
+}
+
+/// An entry with the files attached to it
+#[derive(Serialize)]
+pub struct ExportedEntry {
+    #[serde(flatten)]
+    pub entry: Entry,
+    pub attachments: Vec<ExportedAttachment>,
+}
+
+#[derive(Serialize)]
+pub struct ExportedAttachment {
+    pub filename: String,
+    pub mime: String,
+    pub sha256: String,
+    /// The content in base64
+    pub data: String,
+}
+
+#[derive(Serialize)]
+pub struct ExportedSearch {
+    pub name: String,
+    pub query: String,
+}
+
+impl Export {
+    /// Reads all entries of `store`. Only SQLite journals have attachments and saved searches.
+    pub fn of(store: &dyn JournalStore) -> Result<Export, Box<dyn Error>> {
+        let mut entries = Vec::new();
+        for entry in store.get_entries() {
+            let mut attachments = Vec::new();
+            if let Some(db) = store.as_db() {
+                for attachment in db.get_attachments(entry.get_id())? {
+                    attachments.push(ExportedAttachment {
+                        data: BASE64.encode(db.get_attachment_data(attachment.id)?),
+                        filename: attachment.filename,
+                        mime: attachment.mime,
+                        sha256: attachment.hash,
+                    });
+                }
+            }
+            entries.push(ExportedEntry { entry, attachments });
+        }
+        let saved_searches = match store.as_db() {
+            Some(db) => db
+                .get_saved_searches()?
+                .into_iter()
+                .map(|s| ExportedSearch { name: s.name, query: s.query })
+                .collect(),
+            None => Vec::new(),
+        };
+        Ok(Export { entries, saved_searches })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Db;
+    use serde_json::json;
+
+    #[test]
+    fn export_attachments_and_searches() {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        let mut entry = Entry::new("First".to_string(), "Dear diary".to_string(), None);
+        db.create_entry(&mut entry).unwrap();
+        db.add_attachment(entry.get_id(), "note.txt", None, b"hi").unwrap();
+        db.save_search("open", "tag:retro").unwrap();
+
+        let export = serde_json::to_value(Export::of(&db).unwrap()).unwrap();
+        assert_eq!(export["entries"][0]["title"], json!("First"));
+        assert_eq!(
+            export["entries"][0]["attachments"],
+            json!([{ "filename": "note.txt", "mime": "text/plain", "sha256": crate::attachments::sha256(b"hi"), "data": "aGk=" }])
+        );
+        assert_eq!(export["saved_searches"], json!([{ "name": "open", "query": "tag:retro" }]));
+    }
+}
@@ -0,0 +1,189 @@
+use std::{fmt, str::FromStr};
+
+use crate::{changes::Change, Db, Entry};
+
+/// Marks on an entry besides its text. Setting them doesn't move the updated time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Flag {
+    /// Listed before the other entries
+    Pinned,
+    Starred,
+    /// Left out of lists, but still found by searches
+    Archived,
+}
+
+impl Flag {
+    pub const ALL: [Flag; 3] = [Flag::Pinned, Flag::Starred, Flag::Archived];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Flag::Pinned => "pinned",
+            Flag::Starred => "starred",
+            Flag::Archived => "archived",
+        }
+    }
+
+    /// Shown in front of the title in entry lists
+    pub fn marker(self) -> char {
+        match self {
+            Flag::Pinned => '^',
+            Flag::Starred => '*',
+            Flag::Archived => '~',
+        }
+    }
+
+    /// Starts the commit message of the git mirror when the flag is set or cleared
+    #[cfg(feature = "git")]
+    pub(crate) fn action(self, on: bool) -> &'static str {
+        match (self, on) {
+            (Flag::Pinned, true) => "Pin",
+            (Flag::Pinned, false) => "Unpin",
+            (Flag::Starred, true) => "Star",
+            (Flag::Starred, false) => "Unstar",
+            (Flag::Archived, true) => "Archive",
+            (Flag::Archived, false) => "Unarchive",
+        }
+    }
+
+    /// Column of `entries` the flag is kept in
+    pub(crate) fn column(self) -> &'static str {
+        match self {
+            Flag::Pinned => "entry_pinned",
+            Flag::Starred => "entry_starred",
+            Flag::Archived => "entry_archived",
+        }
+    }
+}
+
+impl fmt::Display for Flag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Flag {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Flag::ALL
+            .into_iter()
+            .find(|f| f.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown flag {}, use pinned, starred or archived", s))
+    }
+}
+
+impl Entry {
+    pub fn has_flag(&self, flag: Flag) -> bool {
+        match flag {
+            Flag::Pinned => self.pinned,
+            Flag::Starred => self.starred,
+            Flag::Archived => self.archived,
+        }
+    }
+
+    /// Only kept by `create_entry`, stored entries are flagged with `JournalStore::set_flag`
+    pub fn set_flag(&mut self, flag: Flag, on: bool) {
+        match flag {
+            Flag::Pinned => self.pinned = on,
+            Flag::Starred => self.starred = on,
+            Flag::Archived => self.archived = on,
+        }
+    }
+
+    /// Markers of the flags the entry has, like `^*`, empty without any
+    pub fn markers(&self) -> String {
+        Flag::ALL.into_iter().filter(|f| self.has_flag(*f)).map(Flag::marker).collect()
+    }
+}
+
+/// Entries the way lists show them: pinned ones first, otherwise in the given order, and archived
+/// ones only when `archived` is set, e.g. for search results
+pub fn arrange(entries: Vec<Entry>, archived: bool) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = entries.into_iter().filter(|e| archived || !e.archived).collect();
+    entries.sort_by_key(|e| !e.pinned);
+    entries
+}
+
+impl Db {
+    /// Sets or clears `flag` on an entry
+    pub fn set_flag(&self, entry_id: u32, flag: Flag, on: bool) -> Result<(), rusqlite::Error> {
+        self.check_mirror()?;
+        let updated = self.conn().execute(
+            &format!("UPDATE entries SET {} = ?1 WHERE entry_id = ?2", flag.column()),
+            (on, entry_id),
+        )?;
+        if updated == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        self.update_entries()?;
+        // The Markdown files of the mirror keep the flags
+        #[cfg(feature = "git")]
+        self.mirror_entry(entry_id, flag.action(on));
+        self.notify(Change::Entry(entry_id));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::Query;
+
+    #[test]
+    fn flags_order_and_hide_entries() {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        for title in ["Old", "Middle", "New"] {
+            db.create_entry(&mut Entry::new(title.to_string(), String::new(), None)).unwrap();
+        }
+        let updated = db.get_entry_by_id(3).unwrap().get_updated_time();
+        db.set_flag(3, Flag::Pinned, true).unwrap();
+        db.set_flag(3, Flag::Starred, true).unwrap();
+        db.set_flag(1, Flag::Archived, true).unwrap();
+        assert!(db.set_flag(9, Flag::Starred, true).is_err());
+
+        let entry = db.get_entry_by_id(3).unwrap();
+        assert_eq!((entry.markers(), entry.get_updated_time()), ("^*".to_string(), updated));
+        let titles = |entries: Vec<Entry>| entries.iter().map(Entry::get_title).collect::<Vec<_>>();
+        assert_eq!(titles(arrange(db.get_entries(), false)), vec!["New", "Middle"]);
+        let found = db.search(&Query::parse("is:archived OR is:starred").unwrap()).unwrap();
+        assert_eq!(titles(arrange(found, true)), vec!["New", "Old"]);
+        assert!(Query::parse("is:deleted").is_err());
+
+        db.set_flag(3, Flag::Pinned, false).unwrap();
+        assert_eq!(titles(arrange(db.get_entries(), true)), vec!["Old", "Middle", "New"]);
+        let mut copy = db.get_entry_by_id(1).unwrap();
+        db.create_entry(&mut copy).unwrap();
+        assert!(db.get_entry_by_id(copy.get_id()).unwrap().has_flag(Flag::Archived));
+    }
+
+    #[test]
+    fn merges_keep_flags_of_both_journals() {
+        let laptop = Db::new(":memory:");
+        laptop.initialize_db().unwrap();
+        for title in ["Flagged", "Edited"] {
+            laptop.create_entry(&mut Entry::new(title.to_string(), String::new(), None)).unwrap();
+        }
+        let desktop = Db::new(":memory:");
+        desktop.initialize_db().unwrap();
+        desktop.merge(&laptop).unwrap();
+
+        // Flags set on one side only, next to an edit that replaces the laptop's version
+        desktop.set_flag(1, Flag::Starred, true).unwrap();
+        laptop.set_flag(2, Flag::Archived, true).unwrap();
+        desktop.set_flag(2, Flag::Pinned, true).unwrap();
+        let mut edited = desktop.get_entry_by_id(2).unwrap();
+        edited.set_content("Later".to_string());
+        desktop.edit_entry(&mut edited).unwrap();
+
+        let report = laptop.merge(&desktop).unwrap();
+        assert_eq!((report.unchanged, report.updated.len()), (1, 1));
+        assert_eq!(laptop.get_entry_by_id(1).unwrap().markers(), "*");
+        let merged = laptop.get_entry_by_id(2).unwrap();
+        assert_eq!((merged.markers(), merged.get_content()), ("^~".to_string(), "Later".to_string()));
+
+        // Both sides agree after merging back
+        desktop.merge(&laptop).unwrap();
+        assert_eq!(desktop.get_entry_by_id(2).unwrap().markers(), "^~");
+    }
+}
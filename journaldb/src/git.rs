@@ -0,0 +1,324 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use git2::{Repository, Signature};
+
+use crate::{
+    markdown::{file_name, to_markdown},
+    Db, Entry,
+};
+
+pub use crate::{MIRROR_ERROR_SETTING, MIRROR_SETTING};
+
+/// A git repository holding the entries as Markdown files in the format of `MarkdownStore`, with
+/// one commit per change. Pushing it somewhere is left to git.
+pub struct GitMirror {
+    repo: Repository,
+}
+
+/// Lets `with_git_mirror` return the errors of both libraries
+fn mirror_error(e: git2::Error) -> rusqlite::Error {
+    rusqlite::Error::UserFunctionError(Box::new(e))
+}
+
+impl GitMirror {
+    /// Opens the repository at `dir`, creating it when missing
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, git2::Error> {
+        let repo = match Repository::open(&dir) {
+            Ok(repo) => repo,
+            Err(_) => {
+                fs::create_dir_all(&dir).map_err(|e| git2::Error::from_str(&e.to_string()))?;
+                Repository::init(&dir)?
+            }
+        };
+        if repo.workdir().is_none() {
+            return Err(git2::Error::from_str("the mirror can't be a bare repository"));
+        }
+        Ok(GitMirror { repo })
+    }
+
+    fn dir(&self) -> &Path {
+        self.repo.workdir().expect("checked in open")
+    }
+
+    /// File of the entry, the existing one when it was renamed since, so `git log --follow` and
+    /// blame keep working
+    fn file_of(&self, entry: &Entry) -> Result<PathBuf, git2::Error> {
+        let prefix = format!("{}-", entry.id);
+        let exact = format!("{}.md", entry.id);
+        let existing = fs::read_dir(self.dir())
+            .map_err(|e| git2::Error::from_str(&e.to_string()))?
+            .filter_map(|f| f.ok())
+            .map(|f| f.file_name().to_string_lossy().to_string())
+            .find(|name| name == &exact || (name.starts_with(&prefix) && name.ends_with(".md")));
+        Ok(PathBuf::from(existing.unwrap_or_else(|| file_name(entry))))
+    }
+
+    /// Writes the entry and commits it, unless the file didn't change
+    pub fn save(&self, entry: &Entry, message: &str) -> Result<(), git2::Error> {
+        let file = self.file_of(entry)?;
+        fs::write(self.dir().join(&file), to_markdown(entry)).map_err(|e| git2::Error::from_str(&e.to_string()))?;
+        let mut index = self.repo.index()?;
+        index.add_path(&file)?;
+        self.commit(&mut index, message)
+    }
+
+    pub fn remove(&self, entry: &Entry, message: &str) -> Result<(), git2::Error> {
+        let file = self.file_of(entry)?;
+        let path = self.dir().join(&file);
+        if !path.exists() {
+            return Ok(());
+        }
+        fs::remove_file(path).map_err(|e| git2::Error::from_str(&e.to_string()))?;
+        let mut index = self.repo.index()?;
+        index.remove_path(&file)?;
+        self.commit(&mut index, message)
+    }
+
+    /// Removes the tracked entry files of ids other than those of `entries`, e.g. of entries
+    /// deleted while the journal wasn't mirrored, so they don't come back and reused ids don't
+    /// find them
+    fn remove_others(&self, index: &mut git2::Index, entries: &[Entry]) -> Result<(), git2::Error> {
+        let stale: Vec<PathBuf> = index
+            .iter()
+            .map(|e| PathBuf::from(String::from_utf8_lossy(&e.path).to_string()))
+            .filter(|path| path.parent() == Some(Path::new("")))
+            .filter(|path| {
+                let name = path.to_string_lossy();
+                let id = name.strip_suffix(".md").map(|stem| stem.split('-').next().unwrap_or_default());
+                id.and_then(|id| id.parse::<u32>().ok()).is_some_and(|id| !entries.iter().any(|e| e.id == id))
+            })
+            .collect();
+        for path in stale {
+            let _ = fs::remove_file(self.dir().join(&path));
+            index.remove_path(&path)?;
+        }
+        Ok(())
+    }
+
+    fn commit(&self, index: &mut git2::Index, message: &str) -> Result<(), git2::Error> {
+        index.write()?;
+        let tree = self.repo.find_tree(index.write_tree()?)?;
+        let parent = self.repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        if parent.as_ref().is_some_and(|p| p.tree_id() == tree.id()) {
+            return Ok(());
+        }
+        // The user's git identity when configured
+        let signature = self.repo.signature().or_else(|_| Signature::now("journal", "journal@localhost"))?;
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        self.repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)?;
+        Ok(())
+    }
+}
+
+/// Commit subject naming the entry
+fn message(action: &str, entry: &Entry) -> String {
+    match entry.title.lines().next().unwrap_or("") {
+        "" => format!("{} entry {}", action, entry.id),
+        title => format!("{} entry {}: {}", action, entry.id, title),
+    }
+}
+
+impl Db {
+    /// Mirrors the journal into the git repository at `dir` from now on, starting with a commit of
+    /// all entries, or stops mirroring with `None`. Encrypted journals aren't mirrored, their text
+    /// would end up in plain files.
+    pub fn set_git_mirror(&self, dir: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(dir) = dir else {
+            self.conn().execute("DELETE FROM settings WHERE key IN (?1, ?2)", (MIRROR_SETTING, MIRROR_ERROR_SETTING))?;
+            *self.git_mirror.lock() = None;
+            return Ok(());
+        };
+        if self.is_encrypted()? {
+            return Err("encrypted journals can't be mirrored into git".into());
+        }
+        let dir = fs::canonicalize(dir).or_else(|_| fs::create_dir_all(dir).and_then(|_| fs::canonicalize(dir)))?;
+        let mirror = GitMirror::open(&dir)?;
+        self.update_entries()?;
+        let entries = self.get_entries();
+        let mut index = mirror.repo.index()?;
+        mirror.remove_others(&mut index, &entries)?;
+        for entry in &entries {
+            fs::write(mirror.dir().join(mirror.file_of(entry)?), to_markdown(entry))?;
+        }
+        index.add_all(["*.md"], git2::IndexAddOption::DEFAULT, None)?;
+        let count = match entries.len() {
+            1 => "1 entry".to_string(),
+            n => format!("{} entries", n),
+        };
+        mirror.commit(&mut index, &format!("Mirror {}", count))?;
+        self.set_setting(MIRROR_SETTING, &dir.to_string_lossy())?;
+        self.conn().execute("DELETE FROM settings WHERE key = ?1", (MIRROR_ERROR_SETTING,))?;
+        *self.git_mirror.lock() = Some((dir, mirror));
+        Ok(())
+    }
+
+    /// Runs `f` on the mirror set in the settings, which another process may have changed
+    fn with_git_mirror(&self, f: impl FnOnce(&GitMirror) -> Result<(), git2::Error>) -> Result<(), rusqlite::Error> {
+        let Some(dir) = self.get_setting(MIRROR_SETTING)?.map(PathBuf::from) else {
+            return Ok(());
+        };
+        if self.is_encrypted()? {
+            return Ok(());
+        }
+        let mut cached = self.git_mirror.lock();
+        if cached.as_ref().is_none_or(|(d, _)| *d != dir) {
+            *cached = Some((dir.clone(), GitMirror::open(&dir).map_err(mirror_error)?));
+        }
+        let (_, mirror) = cached.as_ref().expect("opened above");
+        f(mirror).map_err(mirror_error)
+    }
+
+    /// Why the mirror missed a change, until `set_git_mirror` commits the whole journal again
+    pub fn git_mirror_error(&self) -> Result<Option<String>, rusqlite::Error> {
+        self.get_setting(MIRROR_ERROR_SETTING)
+    }
+
+    /// Keeps the error of a failed commit instead of returning it, the change is in the journal
+    /// already and only the mirror is behind
+    fn record_mirror_error(&self, result: Result<(), rusqlite::Error>) {
+        if let Err(e) = result {
+            let _ = self.set_setting(MIRROR_ERROR_SETTING, &e.to_string());
+        }
+    }
+
+    /// Commits the stored version of the entry, `action` starts the commit message
+    pub(crate) fn mirror_entry(&self, entry_id: u32, action: &str) {
+        let Some(entry) = self.get_entry_by_id(entry_id) else {
+            return;
+        };
+        self.record_mirror_error(self.with_git_mirror(|mirror| mirror.save(&entry, &message(action, &entry))));
+    }
+
+    pub(crate) fn mirror_delete(&self, entry: &Entry) {
+        self.record_mirror_error(self.with_git_mirror(|mirror| mirror.remove(entry, &message("Delete", entry))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{crypto::CryptoError, flags::Flag, markdown::MarkdownStore, store::JournalStore, Tag};
+
+    /// Commit messages from the newest
+    fn log(dir: &Path) -> Vec<String> {
+        let repo = Repository::open(dir).unwrap();
+        let mut walk = repo.revwalk().unwrap();
+        walk.push_head().unwrap();
+        walk.map(|id| repo.find_commit(id.unwrap()).unwrap().summary().unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn commits_each_change() {
+        let dir = std::env::temp_dir().join(format!("journaldb-git-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        let long_ago = chrono::DateTime::parse_from_rfc3339("2020-01-01T12:00:00+00:00").unwrap();
+        db.create_entry(&mut Entry::new("Before".to_string(), "Old".to_string(), None).with_updated_time(long_ago)).unwrap();
+        db.set_git_mirror(Some(&dir)).unwrap();
+
+        let mut entry = Entry::new("Trip to Rome".to_string(), "Pasta".to_string(), Some(vec![Tag::new("travel".to_string())]));
+        db.create_entry(&mut entry).unwrap();
+        entry.set_title("Rome".to_string());
+        entry.set_content("Pizza".to_string());
+        db.edit_entry(&mut entry).unwrap();
+        // Saving without changes makes no commit
+        db.edit_entry(&mut entry).unwrap();
+        db.set_flag(2, Flag::Starred, true).unwrap();
+        let mut before = db.get_entry_by_id(1).unwrap();
+        db.touch(&mut before).unwrap();
+        db.delete_entry(&before).unwrap();
+
+        assert_eq!(log(&dir), vec!["Delete entry 1: Before", "Touch entry 1: Before", "Star entry 2: Rome", "Edit entry 2: Rome", "Create entry 2: Trip to Rome", "Mirror 1 entry"]);
+        // The file keeps its first name
+        let text = fs::read_to_string(dir.join("2-trip-to-rome.md")).unwrap();
+        assert!(text.contains("title: Rome\n") && text.contains("starred: true\n") && text.ends_with("Pizza"));
+        assert!(!dir.join("1-before.md").exists());
+        let store = MarkdownStore::open(&dir).unwrap();
+        assert_eq!(store.get_entries().iter().map(Entry::get_title).collect::<Vec<_>>(), vec!["Rome"]);
+
+        // The mirror would keep the text in plain files
+        assert!(matches!(db.set_passphrase(Some("secret")), Err(CryptoError::Mirrored)));
+        assert!(!db.is_encrypted().unwrap());
+
+        db.set_git_mirror(None).unwrap();
+        db.create_entry(&mut Entry::new("Unmirrored".to_string(), String::new(), None)).unwrap();
+        assert_eq!(log(&dir).len(), 6);
+
+        db.set_passphrase(Some("secret")).unwrap();
+        assert!(db.set_git_mirror(Some(&dir)).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn mirroring_again_drops_deleted_entries() {
+        let dir = std::env::temp_dir().join(format!("journaldb-git-again-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        db.create_entry(&mut Entry::new("Kept".to_string(), String::new(), None)).unwrap();
+        let mut gone = Entry::new("Gone".to_string(), String::new(), None);
+        db.create_entry(&mut gone).unwrap();
+        db.set_git_mirror(Some(&dir)).unwrap();
+
+        db.set_git_mirror(None).unwrap();
+        db.delete_entry(&gone).unwrap();
+        db.set_git_mirror(Some(&dir)).unwrap();
+        let files = || {
+            let repo = Repository::open(&dir).unwrap();
+            let tree = repo.head().unwrap().peel_to_tree().unwrap();
+            tree.iter().map(|f| f.name().unwrap().to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(log(&dir), vec!["Mirror 1 entry", "Mirror 2 entries"]);
+        assert_eq!(files(), vec!["1-kept.md"]);
+        assert!(!dir.join("2-gone.md").exists());
+
+        // The id of the deleted entry comes back with a file of its own
+        db.create_entry(&mut Entry::new("Reused".to_string(), String::new(), None)).unwrap();
+        assert_eq!(files(), vec!["1-kept.md", "2-reused.md"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failing_mirror_keeps_the_change() {
+        let dir = std::env::temp_dir().join(format!("journaldb-git-failing-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        db.set_git_mirror(Some(&dir)).unwrap();
+        // A file where the repository should be
+        let file = dir.join("not-a-dir");
+        fs::write(&file, "").unwrap();
+        db.set_setting(MIRROR_SETTING, &file.to_string_lossy()).unwrap();
+
+        let mut entry = Entry::new("Saved".to_string(), String::new(), None);
+        db.create_entry(&mut entry).unwrap();
+        db.delete_entry(&entry).unwrap();
+        assert!(db.get_entries().is_empty());
+        assert!(db.git_mirror_error().unwrap().is_some());
+
+        db.set_git_mirror(Some(&dir)).unwrap();
+        assert_eq!(db.git_mirror_error().unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn merges_are_mirrored_after_commit() {
+        let dir = std::env::temp_dir().join(format!("journaldb-git-merge-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        db.set_git_mirror(Some(&dir)).unwrap();
+        let other = Db::new(":memory:");
+        other.initialize_db().unwrap();
+        other.create_entry(&mut Entry::new("Elsewhere".to_string(), String::new(), None)).unwrap();
+
+        db.merge(&other).unwrap();
+        assert_eq!(log(&dir), vec!["Create entry 1: Elsewhere", "Mirror 0 entries"]);
+        assert!(dir.join("1-elsewhere.md").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,14 +1,68 @@
-use std::{collections::HashMap, ops::Deref};
+use std::collections::HashMap;
 
-use rusqlite::{Connection};
+use chrono::{DateTime, FixedOffset, Local};
+use parking_lot::{Mutex, ReentrantMutex, ReentrantMutexGuard, RwLock};
+use rusqlite::{Connection, OptionalExtension};
 
+pub mod attachments;
+pub mod backup;
+pub mod changes;
+pub mod crypto;
+pub mod daily;
+pub mod doctor;
+#[cfg(feature = "serde")]
+pub mod export;
+pub mod flags;
+#[cfg(feature = "git")]
+pub mod git;
+pub mod links;
+pub mod markdown;
+pub mod merge;
+pub mod query;
+pub mod revisions;
+pub mod saved_searches;
+#[cfg(feature = "serde")]
+mod serialization;
+pub mod stats;
+pub mod store;
+pub mod templates;
+pub mod time;
+
+use time::{DisplayZone, TimeFormat, FORMAT_SETTING, ZONE_SETTING};
+
+/// A SQLite journal. It can be shared between threads, e.g. in an `Arc`: they take turns on the
+/// connection, and other processes writing the same file are waited for.
+///
+/// Locks are only taken in the order of the fields: a thread holding one of the other locks never
+/// waits for the connection, so the guards of `cipher`, `entries` and the others are dropped first.
 pub struct Db {
     filename: String,
-    conn: Connection,
-    entries: Vec<Entry>,
+    /// Re-entrant so methods can call each other while holding the connection
+    conn: ReentrantMutex<Connection>,
+    entries: RwLock<Vec<Entry>>,
+    /// Key of an unlocked encrypted journal
+    cipher: RwLock<Option<crypto::Cipher>>,
+    subscribers: Mutex<Vec<std::sync::mpsc::Sender<changes::Change>>>,
+    /// `PRAGMA data_version` at the last `poll_changes`
+    data_version: Mutex<i64>,
+    /// Repository changes are mirrored into, with its directory
+    #[cfg(feature = "git")]
+    git_mirror: Mutex<Option<(std::path::PathBuf, git::GitMirror)>>,
 }
 
+/// Directory of the git repository changes are mirrored into, unset for no mirror. Read by builds
+/// without the `git` feature too, which refuse to change a mirrored journal.
+pub const MIRROR_SETTING: &str = "git.mirror";
+/// Why the last change couldn't be committed to the mirror, unset while the mirror is up to date
+pub const MIRROR_ERROR_SETTING: &str = "git.mirror.error";
+
+/// How long to wait for another process to finish writing before failing with "database is locked"
+const BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Serialized as its name
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "String", into = "String"))]
 pub struct Tag {
     id: u32,
     tag: String,
@@ -33,6 +87,21 @@ impl From<String> for Tag {
     }
 }
 
+impl From<Tag> for String {
+    fn from(t: Tag) -> Self {
+        t.tag
+    }
+}
+
+/// Tags are equal by name, whether they were read from the journal or not
+impl PartialEq for Tag {
+    fn eq(&self, other: &Self) -> bool {
+        self.tag == other.tag
+    }
+}
+
+impl Eq for Tag {}
+
 // fn assemble_tags(tags: Vec<&str>) -> Option<Vec<Tag>> {
     // let tags = tags.iter().map(|&t| {
         // Tag::new(String::from(t))
@@ -47,14 +116,25 @@ impl From<String> for Tag {
 
 // }
 
-#[derive(Clone)]
+/// Serialized with the fields `id`, `title`, `content`, `tags` (a list of names), the RFC3339
+/// timestamps `created` and `updated`, and `pinned`, `starred` and `archived` when set
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "serialization::EntryRecord", into = "serialization::EntryRecord"))]
 pub struct Entry {
     id: u32,
-    created_time: u32,
-    updated_time: u32,
+    /// Seconds since the epoch
+    created_time: i64,
+    /// Offset from UTC in seconds where the entry was written
+    created_offset: i32,
+    updated_time: i64,
+    updated_offset: i32,
     title: String,
     content: String,
     tags: Option<Vec<Tag>>,
+    pinned: bool,
+    starred: bool,
+    archived: bool,
 }
 
 impl Entry {
@@ -86,44 +166,281 @@ impl Entry {
         self.tags = tags;
     }
 
-    pub fn get_created_time(&self) -> String {
-        let time = chrono::NaiveDateTime::from_timestamp_opt(self.created_time as i64, 0).unwrap();
-        time.format("%Y-%m-%d %H:%M:%S").to_string()
+    /// Creation time in the offset the entry was written in
+    pub fn get_created_time(&self) -> DateTime<FixedOffset> {
+        time::from_parts(self.created_time, self.created_offset)
+    }
+
+    /// Day the entry was written, in the offset it was written in
+    pub fn get_created_date(&self) -> chrono::NaiveDate {
+        self.get_created_time().date_naive()
+    }
+
+    /// Backdates the entry, keeping the offset of `time` as the one it was written in
+    pub fn set_created_time(&mut self, time: DateTime<FixedOffset>) {
+        self.created_time = time.timestamp();
+        self.created_offset = time.offset().local_minus_utc();
     }
 
-    pub fn get_updated_time(&self) -> String {
-        let time = chrono::NaiveDateTime::from_timestamp_opt(self.updated_time as i64, 0).unwrap();
-        time.format("%Y-%m-%d %H:%M:%S").to_string()
+    pub fn with_created_time(mut self, time: DateTime<FixedOffset>) -> Self {
+        self.set_created_time(time);
+        self
+    }
+
+    pub fn get_updated_time(&self) -> DateTime<FixedOffset> {
+        time::from_parts(self.updated_time, self.updated_offset)
+    }
+
+    /// Only kept by `create_entry`, e.g. for imports. Editing an entry moves it to the current time.
+    pub fn set_updated_time(&mut self, time: DateTime<FixedOffset>) {
+        self.updated_time = time.timestamp();
+        self.updated_offset = time.offset().local_minus_utc();
+    }
+
+    pub fn with_updated_time(mut self, time: DateTime<FixedOffset>) -> Self {
+        self.set_updated_time(time);
+        self
     }
 
     pub fn new(title: String, content: String, tags: Option<Vec<Tag>>) -> Self {
+        let now = Local::now();
+        let offset = now.offset().local_minus_utc();
         Entry {
             id: 0,
-            created_time: 0,
-            updated_time: 0,
+            created_time: now.timestamp(),
+            created_offset: offset,
+            updated_time: now.timestamp(),
+            updated_offset: offset,
             title,
             content,
-            tags
+            tags,
+            pinned: false,
+            starred: false,
+            archived: false,
         }
     }
 }
 
+/// Schema changes, applied in order. `PRAGMA user_version` counts the applied ones.
+const MIGRATIONS: &[&str] = &[
+    // Keep the UTC offset timestamps were recorded in, and user settings
+    "ALTER TABLE entries ADD COLUMN entry_created_offset INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE entries ADD COLUMN entry_updated_offset INTEGER NOT NULL DEFAULT 0;
+    DROP VIEW IF EXISTS entries_w_tags;
+    CREATE VIEW entries_w_tags AS SELECT entries.entry_id, entry_created_time, entry_updated_time, entry_title,
+            entry_content, group_concat(tags.tag_id, ':') AS tags, entry_created_offset, entry_updated_offset
+        FROM
+            (entries LEFT JOIN entry_tags ON entries.entry_id = entry_tags.entry_id)
+            LEFT JOIN tags ON entry_tags.tag_id = tags.tag_id
+        GROUP BY entries.entry_id;
+    CREATE TABLE IF NOT EXISTS settings (
+        key TEXT NOT NULL PRIMARY KEY,
+        value TEXT NOT NULL
+    );",
+    // The first trigger bumped every row on each edit. Only bump the edited row, and only when the
+    // statement didn't set the updated time itself.
+    "DROP TRIGGER IF EXISTS update_updated_time;
+    CREATE TRIGGER update_updated_time AFTER UPDATE OF entry_title, entry_content ON entries
+    FOR EACH ROW
+    WHEN (NEW.entry_title IS NOT OLD.entry_title OR NEW.entry_content IS NOT OLD.entry_content)
+        AND NEW.entry_updated_time IS OLD.entry_updated_time
+    BEGIN
+        UPDATE entries SET entry_updated_time = strftime('%s', 'now') WHERE entry_id = NEW.entry_id;
+    END;",
+    // Files stored with entries
+    "CREATE TABLE attachments (
+        attachment_id INTEGER NOT NULL PRIMARY KEY,
+        entry_id INTEGER NOT NULL,
+        filename TEXT NOT NULL,
+        mime TEXT NOT NULL,
+        hash TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        data BLOB NOT NULL,
+        FOREIGN KEY(entry_id) REFERENCES entries(entry_id)
+    );
+    CREATE INDEX attachments_entry_id ON attachments(entry_id);
+    CREATE TRIGGER delete_deleted_entry_attachments
+    AFTER DELETE ON entries
+    FOR EACH ROW
+    BEGIN
+        DELETE FROM attachments WHERE entry_id = OLD.entry_id;
+    END;",
+    // Key derivation parameters of encrypted journals
+    "CREATE TABLE encryption (
+        id INTEGER NOT NULL PRIMARY KEY CHECK (id = 0),
+        salt BLOB NOT NULL,
+        m_cost INTEGER NOT NULL,
+        t_cost INTEGER NOT NULL,
+        p_cost INTEGER NOT NULL,
+        verifier BLOB NOT NULL
+    );",
+    // `[[...]]` references between entries, filled from the content of existing entries on next load
+    "CREATE TABLE entry_links (
+        entry_id INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        target NOT NULL,
+        PRIMARY KEY(entry_id, position),
+        FOREIGN KEY(entry_id) REFERENCES entries(entry_id)
+    );
+    CREATE TRIGGER delete_deleted_entry_links
+    AFTER DELETE ON entries
+    FOR EACH ROW
+    BEGIN
+        DELETE FROM entry_links WHERE entry_id = OLD.entry_id;
+    END;
+    INSERT OR REPLACE INTO settings (key, value) VALUES ('links.reindex', '1');",
+    // Named entry skeletons, tags are stored comma separated
+    "CREATE TABLE templates (
+        name TEXT NOT NULL PRIMARY KEY,
+        title TEXT NOT NULL,
+        content TEXT NOT NULL,
+        tags TEXT NOT NULL
+    );",
+    // Named queries
+    "CREATE TABLE saved_searches (
+        name TEXT NOT NULL PRIMARY KEY,
+        query TEXT NOT NULL
+    );",
+    // Ids that match entries across copies of the journal, filled in by `assign_uuids`, and the
+    // versions edits replaced
+    "ALTER TABLE entries ADD COLUMN entry_uuid TEXT;
+    CREATE UNIQUE INDEX entries_uuid ON entries(entry_uuid);
+    CREATE TABLE entry_revisions (
+        revision_id INTEGER NOT NULL PRIMARY KEY,
+        entry_id INTEGER NOT NULL,
+        title NOT NULL,
+        content NOT NULL,
+        tags TEXT NOT NULL,
+        updated_time INTEGER NOT NULL,
+        updated_offset INTEGER NOT NULL,
+        FOREIGN KEY(entry_id) REFERENCES entries(entry_id)
+    );
+    CREATE INDEX entry_revisions_entry_id ON entry_revisions(entry_id);
+    CREATE TRIGGER delete_deleted_entry_revisions
+    AFTER DELETE ON entries
+    FOR EACH ROW
+    BEGIN
+        DELETE FROM entry_revisions WHERE entry_id = OLD.entry_id;
+    END;",
+    // Foreign keys are enforced from now on. Rows referring to entries and tags are deleted with
+    // them by ON DELETE CASCADE instead of triggers, and tags are loaded with a join instead of the
+    // view. Dangling rows, which `Db::check` would report, aren't carried over.
+    "DROP TRIGGER delete_deleted_entry_tags;
+    DROP TRIGGER delete_deleted_entry_attachments;
+    DROP TRIGGER delete_deleted_entry_links;
+    DROP TRIGGER delete_deleted_entry_revisions;
+    DROP VIEW entries_w_tags;
+
+    CREATE TABLE entry_tags_new (
+        entry_id INTEGER NOT NULL,
+        tag_id INTEGER NOT NULL,
+        FOREIGN KEY(entry_id) REFERENCES entries(entry_id) ON DELETE CASCADE,
+        FOREIGN KEY(tag_id) REFERENCES tags(tag_id) ON DELETE CASCADE,
+        UNIQUE(entry_id, tag_id)
+    );
+    INSERT INTO entry_tags_new SELECT entry_id, tag_id FROM entry_tags
+        WHERE entry_id IN (SELECT entry_id FROM entries) AND tag_id IN (SELECT tag_id FROM tags)
+        ORDER BY rowid;
+    DROP TABLE entry_tags;
+    ALTER TABLE entry_tags_new RENAME TO entry_tags;
+    CREATE INDEX entry_tags_tag_id ON entry_tags(tag_id);
+    CREATE TRIGGER delete_unused_tags
+    AFTER DELETE ON entry_tags
+    BEGIN
+        DELETE FROM tags WHERE tag_id NOT IN (SELECT tag_id FROM entry_tags);
+    END;
+
+    CREATE TABLE attachments_new (
+        attachment_id INTEGER NOT NULL PRIMARY KEY,
+        entry_id INTEGER NOT NULL,
+        filename TEXT NOT NULL,
+        mime TEXT NOT NULL,
+        hash TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        data BLOB NOT NULL,
+        FOREIGN KEY(entry_id) REFERENCES entries(entry_id) ON DELETE CASCADE
+    );
+    INSERT INTO attachments_new SELECT * FROM attachments WHERE entry_id IN (SELECT entry_id FROM entries);
+    DROP TABLE attachments;
+    ALTER TABLE attachments_new RENAME TO attachments;
+    CREATE INDEX attachments_entry_id ON attachments(entry_id);
+
+    CREATE TABLE entry_links_new (
+        entry_id INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        target NOT NULL,
+        PRIMARY KEY(entry_id, position),
+        FOREIGN KEY(entry_id) REFERENCES entries(entry_id) ON DELETE CASCADE
+    );
+    INSERT INTO entry_links_new SELECT * FROM entry_links WHERE entry_id IN (SELECT entry_id FROM entries);
+    DROP TABLE entry_links;
+    ALTER TABLE entry_links_new RENAME TO entry_links;
+
+    CREATE TABLE entry_revisions_new (
+        revision_id INTEGER NOT NULL PRIMARY KEY,
+        entry_id INTEGER NOT NULL,
+        title NOT NULL,
+        content NOT NULL,
+        tags TEXT NOT NULL,
+        updated_time INTEGER NOT NULL,
+        updated_offset INTEGER NOT NULL,
+        FOREIGN KEY(entry_id) REFERENCES entries(entry_id) ON DELETE CASCADE
+    );
+    INSERT INTO entry_revisions_new SELECT * FROM entry_revisions WHERE entry_id IN (SELECT entry_id FROM entries);
+    DROP TABLE entry_revisions;
+    ALTER TABLE entry_revisions_new RENAME TO entry_revisions;
+    CREATE INDEX entry_revisions_entry_id ON entry_revisions(entry_id);",
+    // `flags::Flag`s of entries
+    "ALTER TABLE entries ADD COLUMN entry_pinned INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE entries ADD COLUMN entry_starred INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE entries ADD COLUMN entry_archived INTEGER NOT NULL DEFAULT 0;",
+];
+
 impl Db {
     pub fn new(filename: &str) -> Self {
+        let conn = Connection::open(filename).unwrap();
         Self {
             filename: filename.to_string(),
-            conn: Connection::open(filename).unwrap(),
-            entries: Vec::new(),
+            conn: ReentrantMutex::new(conn),
+            entries: RwLock::new(Vec::new()),
+            cipher: RwLock::new(None),
+            subscribers: Mutex::new(Vec::new()),
+            data_version: Mutex::new(0),
+            #[cfg(feature = "git")]
+            git_mirror: Mutex::new(None),
         }
     }
 
+    /// The connection, held by the calling thread until the guard is dropped
+    pub(crate) fn conn(&self) -> ReentrantMutexGuard<'_, Connection> {
+        self.conn.lock()
+    }
+
+    pub fn get_filename(&self) -> &str {
+        &self.filename
+    }
+
     pub fn get_entries(&self) -> Vec<Entry> {
-        self.entries.clone()
+        self.entries.read().clone()
     }
 
-    pub fn initialize_db(&self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Prepares the connection and brings the schema up to date. Fails when the file is no
+    /// SQLite database.
+    pub fn initialize_db(&self) -> Result<(), rusqlite::Error> {
+        self.conn().busy_timeout(BUSY_TIMEOUT)?;
+        // SQLite leaves them unenforced unless asked, on every connection
+        self.conn().pragma_update(None, "foreign_keys", true)?;
+        *self.data_version.lock() = changes::data_version(&self.conn())?;
+        self.create_tables()?;
+        self.migrate(MIGRATIONS.len())
+    }
+
+    /// The schema of the first version, which `MIGRATIONS` build on
+    fn create_tables(&self) -> Result<(), rusqlite::Error> {
         // let conn = Connection::open(&self.filename)?;
-        self.conn.execute(
+        // Readers don't block the writer, so journalcli can write while journaltui is open
+        self.conn().query_row("PRAGMA journal_mode = WAL", (), |_| Ok(()))?;
+        self.conn().execute(
             "CREATE TABLE IF NOT EXISTS entries (
                 entry_id INTEGER NOT NULL PRIMARY KEY,
                 entry_created_time timestamp default (strftime('%s', 'now')),
@@ -133,7 +450,7 @@ impl Db {
             )",
             (),
         )?;
-        self.conn.execute(
+        self.conn().execute(
             "CREATE TABLE IF NOT EXISTS tags (
                 tag_id INTEGER NOT NULL PRIMARY KEY,
                 tag TEXT,
@@ -141,7 +458,7 @@ impl Db {
             )",
             (),
         )?;
-        self.conn.execute(
+        self.conn().execute(
             "CREATE TABLE IF NOT EXISTS entry_tags (
                 entry_id INTEGER,
                 tag_id INTEGER,
@@ -151,154 +468,317 @@ impl Db {
             )",
             (),
         )?;
-        self.conn.execute(
-            "CREATE VIEW IF NOT EXISTS entries_w_tags AS SELECT entries.entry_id, entry_created_time, entry_updated_time, entry_title, 
-                    entry_content, group_concat(tags.tag_id, ':') AS tags
-                FROM
-                    (entries LEFT JOIN entry_tags ON entries.entry_id = entry_tags.entry_id)
-                    LEFT JOIN tags ON entry_tags.tag_id = tags.tag_id
-                GROUP BY entries.entry_id;
-            ",
-            (),
-        )?;
-        self.conn.execute(
-            "CREATE TRIGGER IF NOT EXISTS update_updated_time UPDATE OF entry_title, entry_content ON entries
-            BEGIN
-                UPDATE entries SET entry_updated_time=strftime('%s', 'now') WHERE entry_id = entry_id;
-            END;",
-            (),
-        )?;
-        self.conn.execute(
-            "CREATE TRIGGER IF NOT EXISTS delete_deleted_entry_tags
-            AFTER DELETE ON entries
-            FOR EACH ROW
-            BEGIN
-                DELETE FROM entry_tags WHERE entry_id = OLD.entry_id;
-            END;",
-            (),
-        )?;
-        self.conn.execute(
-            "CREATE TRIGGER IF NOT EXISTS delete_unused_tags
-            AFTER DELETE ON entry_tags
-            BEGIN
-                DELETE FROM tags WHERE tag_id NOT IN (SELECT tag_id FROM entry_tags);
-            END;",
-            (),
+        // The view and the triggers below are replaced or dropped by migrations, so only journals
+        // older than those get them
+        let version: usize = self.conn().query_row("PRAGMA user_version", (), |r| r.get(0))?;
+        if version == 0 {
+            self.conn().execute_batch(
+                "CREATE VIEW IF NOT EXISTS entries_w_tags AS SELECT entries.entry_id, entry_created_time, entry_updated_time, entry_title,
+                        entry_content, group_concat(tags.tag_id, ':') AS tags
+                    FROM
+                        (entries LEFT JOIN entry_tags ON entries.entry_id = entry_tags.entry_id)
+                        LEFT JOIN tags ON entry_tags.tag_id = tags.tag_id
+                    GROUP BY entries.entry_id;
+                CREATE TRIGGER IF NOT EXISTS update_updated_time UPDATE OF entry_title, entry_content ON entries
+                BEGIN
+                    UPDATE entries SET entry_updated_time=strftime('%s', 'now') WHERE entry_id = entry_id;
+                END;
+                CREATE TRIGGER IF NOT EXISTS delete_deleted_entry_tags
+                AFTER DELETE ON entries
+                FOR EACH ROW
+                BEGIN
+                    DELETE FROM entry_tags WHERE entry_id = OLD.entry_id;
+                END;
+                CREATE TRIGGER IF NOT EXISTS delete_unused_tags
+                AFTER DELETE ON entry_tags
+                BEGIN
+                    DELETE FROM tags WHERE tag_id NOT IN (SELECT tag_id FROM entry_tags);
+                END;",
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Brings the schema up to `target`, the latest version outside of tests
+    fn migrate(&self, target: usize) -> Result<(), rusqlite::Error> {
+        let conn = self.conn();
+        let version: usize = conn.query_row("PRAGMA user_version", (), |r| r.get(0))?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().take(target).skip(version) {
+            conn.execute_batch(&format!(
+                "BEGIN;\n{}\nPRAGMA user_version = {};\nCOMMIT;",
+                migration,
+                i + 1
+            ))?;
+        }
+        self.assign_uuids()?;
+        Ok(())
+    }
+
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>, rusqlite::Error> {
+        self.conn()
+            .query_row("SELECT value FROM settings WHERE key = ?1", (key,), |r| r.get(0))
+            .optional()
+    }
+
+    pub fn set_setting(&self, key: &str, value: &str) -> Result<(), rusqlite::Error> {
+        self.conn().execute(
+            "INSERT INTO settings (key, value) VALUES (?1, ?2)
+            ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            (key, value),
         )?;
         Ok(())
     }
+
+    /// Fails when the journal is mirrored into git but this build can't commit to the mirror,
+    /// instead of leaving changes out of it
+    pub(crate) fn check_mirror(&self) -> Result<(), rusqlite::Error> {
+        if cfg!(not(feature = "git")) && self.get_setting(MIRROR_SETTING)?.is_some() {
+            return Err(rusqlite::Error::UserFunctionError(
+                "journal is mirrored into git but this build has no git support".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn get_settings(&self) -> Result<Vec<(String, String)>, rusqlite::Error> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT key, value FROM settings ORDER BY key")?;
+        let settings = stmt
+            .query_map((), |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect();
+        settings
+    }
+
+    /// Display zone and format from the settings, falling back to the defaults for unusable values
+    pub fn time_format(&self) -> Result<TimeFormat, rusqlite::Error> {
+        let zone = self.get_setting(ZONE_SETTING)?;
+        let format = self.get_setting(FORMAT_SETTING)?;
+        let zone = zone.and_then(|z| z.parse::<DisplayZone>().ok()).unwrap_or_default();
+        let format = format
+            .filter(|f| time::check_format(f).is_ok())
+            .unwrap_or_else(|| time::DEFAULT_FORMAT.to_string());
+        Ok(TimeFormat::new(zone, &format))
+    }
     
-    fn get_tags(&self) -> Result<HashMap<u32,Tag>, rusqlite::Error> {
-        let mut tags = HashMap::new();
-        //let conn = Connection::open(&self.filename)?;
-        let mut stmt = self.conn.prepare(
-            "SELECT tag_id, tag FROM tags;",
+    /// Tags of every entry, in the order they were given
+    fn get_entry_tags(&self) -> Result<HashMap<u32, Vec<Tag>>, rusqlite::Error> {
+        let mut tags: HashMap<u32, Vec<Tag>> = HashMap::new();
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT entry_tags.entry_id, tags.tag_id, tags.tag FROM entry_tags
+            JOIN tags ON tags.tag_id = entry_tags.tag_id
+            ORDER BY entry_tags.rowid",
         )?;
         let results = stmt.query_map((), |row| {
-            Ok(Tag {
-                id: row.get(0)?,
-                tag: row.get(1)?,
-            })
+            Ok((row.get(0)?, Tag {
+                id: row.get(1)?,
+                tag: row.get(2)?,
+            }))
         })?;
-    
-        for t in results {
-            if let Ok(tag) = t {
-                tags.insert(tag.id, tag);
-            }
+        for result in results {
+            let (entry_id, tag) = result?;
+            tags.entry(entry_id).or_default().push(tag);
         }
-    
         Ok(tags)
     }
 
-    pub fn update_entries(&mut self) -> Result<(), rusqlite::Error> {
-        let tags = self.get_tags()?;
+    pub fn update_entries(&self) -> Result<(), rusqlite::Error> {
+        let conn = self.conn();
+        // What is read below includes every change up to here, `poll_changes` reports later ones
+        *self.data_version.lock() = changes::data_version(&conn)?;
+        let mut tags = self.get_entry_tags()?;
         let mut entries = Vec::new();
-        let mut stmt = self.conn.prepare(
-            "SELECT * FROM entries_w_tags"
+        let mut stmt = conn.prepare(
+            "SELECT entry_id, entry_created_time, entry_created_offset, entry_updated_time, entry_updated_offset,
+                entry_title, entry_content, entry_pinned, entry_starred, entry_archived
+            FROM entries ORDER BY entry_id"
         )?;
         let results = stmt.query_map((), |row| {
-            let entry_tags: Option<Vec<Tag>> = match row.get::<usize, String>(5) {
-                Ok(entry_tags_db) => {
-                    let entry_tags = entry_tags_db.split(':').map(|x| {
-                        if let Ok(tag_id) = x.parse() {
-                            let tag = tags.get(&tag_id).unwrap().deref().clone();
-                            Some(tag)
-                        }
-                        else {
-                            None
-                        }
-                    }).collect::<Option<Vec<Tag>>>();
-                    entry_tags
-                },
-                Err(_) => None
-            };
+            let id = row.get(0)?;
             Ok(Entry {
-                id: row.get(0)?,
+                id,
                 created_time: row.get(1)?,
-                updated_time: row.get(2)?,
-                title: row.get(3)?,
-                content: row.get(4)?,
-                tags: entry_tags
+                created_offset: row.get(2)?,
+                updated_time: row.get(3)?,
+                updated_offset: row.get(4)?,
+                title: self.open_text(row.get(5)?)?,
+                content: self.open_text(row.get(6)?)?,
+                tags: tags.remove(&id),
+                pinned: row.get(7)?,
+                starred: row.get(8)?,
+                archived: row.get(9)?,
             })
         })?;
-        for r in results {
-            if let Ok(entry) = r {
-                entries.push(entry);
-            }
+        // Fail instead of hiding entries of a locked or corrupt journal
+        for entry in results {
+            entries.push(entry?);
+        }
+        *self.entries.write() = entries;
+        if self.get_setting(links::REINDEX_SETTING)?.is_some() {
+            self.save_all_links()?;
+            self.conn().execute("DELETE FROM settings WHERE key = ?1", (links::REINDEX_SETTING,))?;
         }
-        self.entries = entries;
         Ok(())
     }
 
-    fn create_tag(&mut self, tag:&str) -> Result<u32, rusqlite::Error> {
+    fn create_tag(&self, tag:&str) -> Result<u32, rusqlite::Error> {
         // let conn = Connection::open(&self.filename)?;
-        let mut stmt = self.conn.prepare("SELECT tag_id FROM tags where tag = ?1")?;
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT tag_id FROM tags where tag = ?1")?;
         match stmt.query_row([&tag], |r| r.get(0)) {
             Ok(id) => Ok(id),
             _ => {
-                self.conn.execute(
+                conn.execute(
                     "INSERT INTO tags (tag) VALUES (?1)",
                     (&tag,)
                 )?;
-                Ok(self.conn.last_insert_rowid() as u32)
+                Ok(conn.last_insert_rowid() as u32)
             },
         }
     }
 
-    pub fn edit_entry(&mut self, entry: &mut Entry) -> Result<(), rusqlite::Error> {
-        self.conn.execute(
-            "UPDATE entries SET entry_title = ?1, entry_content = ?2 WHERE entry_id = ?3",
-            (&entry.title, &entry.content, &entry.id),
+    fn get_entry_tag_names(&self, entry_id: u32) -> Result<Vec<String>, rusqlite::Error> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT tag FROM entry_tags JOIN tags ON entry_tags.tag_id = tags.tag_id WHERE entry_id = ?1",
         )?;
-        self.conn.execute(
-            "DELETE FROM entry_tags WHERE entry_id = ?1",
+        let tags = stmt.query_map((entry_id,), |r| r.get(0))?.collect();
+        tags
+    }
+
+    /// Saves the title, content and tags of `entry`, keeping the version it replaces in the history.
+    /// The updated time only moves when one of them actually changed.
+    pub fn edit_entry(&self, entry: &mut Entry) -> Result<(), rusqlite::Error> {
+        self.check_mirror()?;
+        let conn = self.conn();
+        // Other processes see the edit with its history and updated time, or not at all
+        let tx = conn.unchecked_transaction()?;
+        let previous = conn.query_row(
+            "SELECT entry_title, entry_content, entry_updated_time, entry_updated_offset FROM entries WHERE entry_id = ?1",
             (&entry.id,),
+            |r| Ok(Entry {
+                title: self.open_text(r.get(0)?)?,
+                content: self.open_text(r.get(1)?)?,
+                updated_time: r.get(2)?,
+                updated_offset: r.get(3)?,
+                tags: None,
+                ..entry.clone()
+            }),
         )?;
-        if let Some(tags) = entry.tags.clone() {
-            for mut tag in tags {
-                if let Ok(tag_id) = self.create_tag(&tag.tag) {
-                    tag.id = tag_id;
-                    self.conn.execute(
-                        "INSERT INTO entry_tags (entry_id, tag_id) VALUES (?1, ?2)",
-                        (&entry.id, &tag.id),
-                    )?;
-                }
+        let tags: Vec<Tag> = self.get_entry_tag_names(entry.id)?.into_iter().map(Tag::new).collect();
+        let previous = Entry { tags: Some(tags).filter(|t| !t.is_empty()), ..previous };
+        let mut changed = false;
+        if previous.title != entry.title || previous.content != entry.content {
+            conn.execute(
+                "UPDATE entries SET entry_title = ?1, entry_content = ?2 WHERE entry_id = ?3",
+                (self.seal_text(&entry.title), self.seal_text(&entry.content), &entry.id),
+            )?;
+            self.save_links(entry.id, &entry.content)?;
+            changed = true;
+        }
+        changed |= self.save_tags(entry)?;
+
+        if changed {
+            self.save_revision(entry.id, &previous)?;
+            self.set_updated_now(entry)?;
+        }
+        tx.commit()?;
+        self.update_entries()?;
+        if changed {
+            self.notify(changes::Change::Entry(entry.id));
+            #[cfg(feature = "git")]
+            self.mirror_entry(entry.id, "Edit");
+        }
+        Ok(())
+    }
+
+    /// Makes the stored tags of `entry` match its tags. Returns whether they changed.
+    pub(crate) fn save_tags(&self, entry: &Entry) -> Result<bool, rusqlite::Error> {
+        let conn = self.conn();
+        let old_tags = self.get_entry_tag_names(entry.id)?;
+        let mut new_tags: Vec<String> = Vec::new();
+        for tag in entry.tags.iter().flatten() {
+            if !new_tags.contains(&tag.tag) {
+                new_tags.push(tag.tag.clone());
             }
         }
+        let mut changed = false;
+        for tag in old_tags.iter().filter(|t| !new_tags.contains(t)) {
+            conn.execute(
+                "DELETE FROM entry_tags WHERE entry_id = ?1 AND tag_id = (SELECT tag_id FROM tags WHERE tag = ?2)",
+                (&entry.id, tag),
+            )?;
+            changed = true;
+        }
+        for tag in new_tags.iter().filter(|t| !old_tags.contains(t)) {
+            let tag_id = self.create_tag(tag)?;
+            conn.execute(
+                "INSERT INTO entry_tags (entry_id, tag_id) VALUES (?1, ?2)",
+                (&entry.id, tag_id),
+            )?;
+            changed = true;
+        }
+        Ok(changed)
+    }
+
+    /// Sets the updated time of `entry` to now, in the local offset, without changing anything else
+    pub fn touch(&self, entry: &mut Entry) -> Result<(), rusqlite::Error> {
+        self.check_mirror()?;
+        self.set_updated_now(entry)?;
         self.update_entries()?;
+        // The mirror keeps the updated time in the header
+        #[cfg(feature = "git")]
+        self.mirror_entry(entry.id, "Touch");
+        self.notify(changes::Change::Entry(entry.id));
+        Ok(())
+    }
+
+    /// Stores now as the updated time of `entry`, leaving the entry list and the subscribers to the caller
+    fn set_updated_now(&self, entry: &mut Entry) -> Result<(), rusqlite::Error> {
+        let now = Local::now().fixed_offset();
+        let updated = self.conn().execute(
+            "UPDATE entries SET entry_updated_time = ?1, entry_updated_offset = ?2 WHERE entry_id = ?3",
+            (now.timestamp(), now.offset().local_minus_utc(), &entry.id),
+        )?;
+        if updated == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        entry.set_updated_time(now);
         Ok(())
     }
 
     // fn create_entry(&mut self, entry_title: &str, entry_content: &str, tags: Option<Vec<String>>) 
-    pub fn create_entry(&mut self, entry: &mut Entry) // -> Result<(), rusqlite::Error> {
+    pub fn create_entry(&self, entry: &mut Entry) // -> Result<(), rusqlite::Error> {
             -> Result<(), rusqlite::Error> {
+        self.check_mirror()?;
+        let conn = self.conn();
+        // Other processes see the entry with its tags and links, or not at all
+        let tx = conn.unchecked_transaction()?;
+        self.insert_entry(entry)?;
+        tx.commit()?;
+        self.update_entries()?;
+        #[cfg(feature = "git")]
+        self.mirror_entry(entry.id, "Create");
+        self.notify(changes::Change::Entry(entry.id));
+        Ok(())
+    }
+
+    /// Stores `entry` and sets its id, leaving the transaction, the entry list, the mirror and the
+    /// subscribers to the caller
+    pub(crate) fn insert_entry(&self, entry: &mut Entry) -> Result<(), rusqlite::Error> {
+        // Held throughout, so the id read back is the one of this insert
+        let conn = self.conn();
         // let conn = Connection::open(&self.filename)?;
-        self.conn.execute(
-            "INSERT INTO entries (entry_title, entry_content)
-            VALUES (?1, ?2)",
-            (&entry.title, &entry.content),
+        conn.execute(
+            "INSERT INTO entries (entry_title, entry_content, entry_created_time, entry_created_offset,
+                entry_updated_time, entry_updated_offset, entry_uuid, entry_pinned, entry_starred, entry_archived)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            (self.seal_text(&entry.title), self.seal_text(&entry.content), &entry.created_time, &entry.created_offset,
+                &entry.updated_time, &entry.updated_offset, revisions::new_uuid(), entry.pinned, entry.starred,
+                entry.archived),
         )?;
-        entry.id = self.conn.last_insert_rowid() as u32;
+        entry.id = conn.last_insert_rowid() as u32;
+        self.save_links(entry.id, &entry.content)?;
         if let Some(tvec) = entry.tags.clone() {
             for mut tag in tvec {
                 // self.conn.execute(
@@ -307,53 +787,54 @@ impl Db {
                 // )?;
                 // let tag_id = self.conn.last_insert_rowid();
                 tag.id = self.create_tag(&tag.tag)?;
-                self.conn.execute(
+                conn.execute(
                     "INSERT INTO entry_tags (entry_id, tag_id) VALUES (?1, ?2)",
                     (&entry.id, &tag.id)
                 )?;
             }
         }
-        self.update_entries()?;
         Ok(())
     }
 
-    pub fn delete_entry(&mut self, entry: &Entry) -> Result<(), rusqlite::Error> {
-        self.conn.execute(
+    pub fn delete_entry(&self, entry: &Entry) -> Result<(), rusqlite::Error> {
+        self.check_mirror()?;
+        #[cfg(feature = "git")]
+        let stored = self.get_entry_by_id(entry.id);
+        let conn = self.conn();
+        // The entry goes with its tags, links, attachments and history
+        let tx = conn.unchecked_transaction()?;
+        conn.execute(
             "DELETE FROM entries WHERE entry_id = ?1",
             (&entry.id,),
         )?;
+        tx.commit()?;
         self.update_entries()?;
+        #[cfg(feature = "git")]
+        if let Some(stored) = stored {
+            self.mirror_delete(&stored);
+        }
+        self.notify(changes::Change::Entry(entry.id));
         Ok(())
     }
 
     pub fn get_entry_by_id(&self, id: u32) -> Option<Entry> {
-        for entry in &self.entries {
-            if entry.id == id {
-                return Some(entry.clone());
-            }
-        }
-        None
+        self.entries.read().iter().find(|e| e.id == id).cloned()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
 
-    fn prep_test(filename: &str) -> Db {
-        match fs::remove_file(filename) {
-            Ok(()) => println!("removed {}", filename),
-            Err(e) => println!("{}", e),
-        }
-        let db = Db::new(filename);
+    fn prep_test() -> Db {
+        let db = Db::new(":memory:");
         db.initialize_db().unwrap();
         db
     }
 
     #[test]
     fn basic_db_function() {
-        let mut db = prep_test("test.db");
+        let db = prep_test();
         let mut entry = Entry::new(
             "Test1".to_string(),
             "Test Content".to_string(),
@@ -361,31 +842,31 @@ mod tests {
         );
         db.create_entry(&mut entry).unwrap();
         db.update_entries().unwrap();
-        assert_eq!(db.entries[0].title, "Test1".to_string());
-        assert_eq!(db.entries[0].content, "Test Content".to_string());
+        assert_eq!(db.entries.read()[0].title, "Test1".to_string());
+        assert_eq!(db.entries.read()[0].content, "Test Content".to_string());
         assert_eq!(
-            db.entries[0].tags.as_ref().unwrap().iter().map(|t| t.clone().tag).collect::<Vec<String>>(), 
+            db.entries.read()[0].tags.as_ref().unwrap().iter().map(|t| t.clone().tag).collect::<Vec<String>>(), 
             entry.tags.unwrap().iter().map(|t| t.clone().tag).collect::<Vec<String>>()
         );
-        db.conn.close().unwrap();
+        db.conn.into_inner().close().unwrap();
         
     }
 
     #[test]
     fn test_insert_tag() {
-        let mut db = prep_test("test2.db");
+        let db = prep_test();
         let x = db.create_tag("foo").unwrap();
         assert_eq!(x, 1);
         let y = db.create_tag("bar").unwrap();
         assert_eq!(y, 2);
         let z = db.create_tag("foo").unwrap();
         assert_eq!(z, 1);
-        db.conn.close().unwrap();
+        db.conn.into_inner().close().unwrap();
     }
 
     #[test]
     fn test_edit_entry() {
-        let mut db = prep_test("test3.db");
+        let db = prep_test();
         let mut entry = Entry::new(
             "Title!!".to_string(), 
             "content!!".to_string(), 
@@ -401,7 +882,7 @@ mod tests {
     
     #[test]
     fn test_delete_entry() {
-        let mut db = prep_test("test4.db");
+        let db = prep_test();
         let mut entry = Entry::new(
             String::from("TITLE"),
             String::from("CONTENT"),
@@ -412,4 +893,234 @@ mod tests {
         db.delete_entry(&entry).unwrap();
         assert_eq!(db.get_entries().len(), 0);
     }
+
+    #[test]
+    #[cfg(not(feature = "git"))]
+    fn mirrored_journal_needs_git() {
+        let db = prep_test();
+        let mut entry = Entry::new("Kept".to_string(), String::new(), None);
+        db.create_entry(&mut entry).unwrap();
+        db.set_setting(MIRROR_SETTING, "/tmp/journal-mirror").unwrap();
+
+        assert!(db.create_entry(&mut Entry::new("Lost".to_string(), String::new(), None)).is_err());
+        entry.set_title("Changed".to_string());
+        assert!(db.edit_entry(&mut entry).is_err());
+        assert!(db.delete_entry(&entry).is_err());
+        assert!(matches!(db.set_passphrase(Some("secret")), Err(crypto::CryptoError::Mirrored)));
+        assert_eq!(db.get_entries().iter().map(Entry::get_title).collect::<Vec<_>>(), vec!["Kept"]);
+    }
+
+    #[test]
+    fn failed_create_leaves_nothing_behind() {
+        let db = prep_test();
+        db.conn().execute_batch(
+            "CREATE TRIGGER refuse_tag BEFORE INSERT ON entry_tags
+            WHEN NEW.tag_id = (SELECT tag_id FROM tags WHERE tag = 'refused')
+            BEGIN SELECT RAISE(ABORT, 'refused'); END;",
+        ).unwrap();
+        let tags = Some(vec![Tag::new("fine".to_string()), Tag::new("refused".to_string())]);
+        let mut entry = Entry::new("Half".to_string(), "see [[Other]]".to_string(), tags);
+        assert!(db.create_entry(&mut entry).is_err());
+        for table in ["entries", "entry_tags", "tags", "entry_links"] {
+            let count: u32 = db.conn().query_row(&format!("SELECT count(*) FROM {}", table), (), |r| r.get(0)).unwrap();
+            assert_eq!(count, 0, "{} has rows", table);
+        }
+        assert!(db.get_entries().is_empty());
+    }
+
+    #[test]
+    fn create_backdated_entry() {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        let created = DateTime::parse_from_rfc3339("2020-05-01T21:00:00-04:00").unwrap();
+        let updated = DateTime::parse_from_rfc3339("2020-05-03T08:30:00+02:00").unwrap();
+        let mut entry = Entry::new("Old".to_string(), "notes".to_string(), None)
+            .with_created_time(created)
+            .with_updated_time(updated);
+        db.create_entry(&mut entry).unwrap();
+        let stored = db.get_entry_by_id(entry.id).unwrap();
+        assert_eq!(stored.get_created_time(), created);
+        assert_eq!(stored.get_created_time().offset(), created.offset());
+        assert_eq!(stored.get_updated_time(), updated);
+        assert_eq!(stored.get_created_date(), chrono::NaiveDate::from_ymd_opt(2020, 5, 1).unwrap());
+    }
+
+    /// Three entries that were all last updated at the same time long ago
+    fn stale_entries() -> (Db, Vec<Entry>) {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        let mut entries = Vec::new();
+        for title in ["one", "two", "three"] {
+            let mut entry = Entry::new(title.to_string(), "content".to_string(), Some(vec![Tag::new("tag".to_string())]))
+                .with_updated_time(DateTime::parse_from_rfc3339("2001-01-01T00:00:00Z").unwrap());
+            db.create_entry(&mut entry).unwrap();
+            entries.push(entry);
+        }
+        (db, entries)
+    }
+
+    fn updated_times(db: &Db) -> Vec<i64> {
+        db.get_entries().iter().map(|e| e.updated_time).collect()
+    }
+
+    #[test]
+    fn edit_only_updates_edited_entry() {
+        let (db, mut entries) = stale_entries();
+        let stale = entries[0].updated_time;
+
+        // Saving without changes keeps the updated time
+        db.edit_entry(&mut entries[1]).unwrap();
+        assert_eq!(updated_times(&db), vec![stale; 3]);
+
+        entries[1].content = "new content".to_string();
+        db.edit_entry(&mut entries[1]).unwrap();
+        let times = updated_times(&db);
+        assert_eq!((times[0], times[2]), (stale, stale));
+        assert!(times[1] > stale);
+        assert_eq!(entries[1].updated_time, times[1]);
+    }
+
+    #[test]
+    fn tag_changes_update_entry() {
+        let (db, mut entries) = stale_entries();
+        let stale = entries[0].updated_time;
+
+        // Listing a tag twice is no change
+        entries[0].tags = Some(vec![Tag::new("tag".to_string()), Tag::new("tag".to_string())]);
+        db.edit_entry(&mut entries[0]).unwrap();
+        assert_eq!(updated_times(&db), vec![stale; 3]);
+
+        entries[2].tags = Some(vec![Tag::new("tag".to_string()), Tag::new("extra".to_string())]);
+        db.edit_entry(&mut entries[2]).unwrap();
+        let times = updated_times(&db);
+        assert_eq!((times[0], times[1]), (stale, stale));
+        assert!(times[2] > stale);
+
+        entries[0].tags = None;
+        db.edit_entry(&mut entries[0]).unwrap();
+        assert!(updated_times(&db)[0] > stale);
+        assert!(db.get_entries()[0].tags.is_none());
+        assert_eq!(db.get_entries()[2].tags.as_ref().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn trigger_and_touch_update_single_entry() {
+        let (db, mut entries) = stale_entries();
+        let stale = entries[0].updated_time;
+
+        db.conn().execute("UPDATE entries SET entry_title = 'changed' WHERE entry_id = ?1", (entries[0].id,)).unwrap();
+        // Writing the same value is no change
+        db.conn().execute("UPDATE entries SET entry_title = 'two' WHERE entry_id = ?1", (entries[1].id,)).unwrap();
+        db.update_entries().unwrap();
+        let times = updated_times(&db);
+        assert!(times[0] > stale);
+        assert_eq!((times[1], times[2]), (stale, stale));
+
+        db.touch(&mut entries[2]).unwrap();
+        let times = updated_times(&db);
+        assert_eq!(times[1], stale);
+        assert!(times[2] > stale);
+        assert_eq!(db.get_entries()[2].get_updated_time(), entries[2].get_updated_time());
+
+        // Missing entries aren't touched, and neither is the caller's copy
+        let mut missing = Entry { id: 99, ..entries[0].clone() };
+        assert!(matches!(db.touch(&mut missing), Err(rusqlite::Error::QueryReturnedNoRows)));
+        assert_eq!(missing.updated_time, entries[0].updated_time);
+    }
+
+    #[test]
+    fn foreign_keys_cascade() {
+        // A journal from before foreign keys were enforced, with rows its triggers missed
+        let db = Db::new(":memory:");
+        db.create_tables().unwrap();
+        db.migrate(8).unwrap();
+        db.conn()
+            .execute_batch(
+                "PRAGMA foreign_keys = OFF;
+                INSERT INTO entries (entry_title, entry_content, entry_uuid) VALUES ('Kept', '[[Gone]]', 'kept');
+                INSERT INTO tags (tag) VALUES ('b'), ('a');
+                INSERT INTO entry_tags (entry_id, tag_id) VALUES (1, 1), (1, 2);
+                INSERT INTO attachments (entry_id, filename, mime, hash, size, data) VALUES (1, 'a', 'text/plain', '', 0, x'');
+                INSERT INTO entry_links (entry_id, position, target) VALUES (1, 0, 'Gone');
+                INSERT INTO entry_tags (entry_id, tag_id) VALUES (42, 1), (1, 99);
+                INSERT INTO attachments (entry_id, filename, mime, hash, size, data) VALUES (42, 'b', 'text/plain', '', 0, x'');
+                INSERT INTO entry_links (entry_id, position, target) VALUES (42, 0, 'Kept');
+                INSERT INTO entry_revisions (entry_id, title, content, tags, updated_time, updated_offset) VALUES (42, '', '', '', 0, 0);
+                PRAGMA foreign_keys = ON;",
+            )
+            .unwrap();
+
+        db.initialize_db().unwrap();
+        db.update_entries().unwrap();
+        let mut kept = db.get_entry_by_id(1).unwrap();
+        assert_eq!(db.check().unwrap(), vec![]);
+        assert_eq!(db.get_entries()[0].tags, Some(vec![Tag::new("b".to_string()), Tag::new("a".to_string())]));
+        assert_eq!(db.get_attachments(kept.id).unwrap().len(), 1);
+        let orphan = Entry::new(String::new(), String::new(), None);
+        assert!(db.add_attachment(42, "c.txt", None, b"c").is_err());
+        assert!(db.save_revision(42, &orphan).is_err());
+
+        kept.set_content("Edited".to_string());
+        db.edit_entry(&mut kept).unwrap();
+        db.delete_entry(&kept).unwrap();
+        for table in ["entry_tags", "tags", "attachments", "entry_links", "entry_revisions"] {
+            let count: u32 = db.conn().query_row(&format!("SELECT count(*) FROM {}", table), (), |r| r.get(0)).unwrap();
+            assert_eq!(count, 0, "{}", table);
+        }
+    }
+
+    #[test]
+    fn opening_other_files_fails() {
+        let path = std::env::temp_dir().join(format!("journaldb-notadb-{}.db", std::process::id()));
+        std::fs::write(&path, "Not a journal, but long enough for SQLite to read a header from it.".repeat(2)).unwrap();
+        let db = Db::new(path.to_str().unwrap());
+        assert!(db.initialize_db().is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn shared_between_threads_and_processes() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Db>();
+
+        let path = std::env::temp_dir().join(format!("journaldb-shared-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        let db = std::sync::Arc::new(Db::new(path));
+        db.initialize_db().unwrap();
+        let mode: String = db.conn().query_row("PRAGMA journal_mode", (), |r| r.get(0)).unwrap();
+        assert_eq!(mode, "wal");
+        // Like journalcli writing while journaltui is open
+        let other = Db::new(path);
+        other.initialize_db().unwrap();
+
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let db = std::sync::Arc::clone(&db);
+                std::thread::spawn(move || {
+                    for i in 0..10 {
+                        let tags = Some(vec![Tag::new(format!("thread{}", t))]);
+                        db.create_entry(&mut Entry::new(format!("{}-{}", t, i), String::new(), tags)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for i in 0..10 {
+            other.create_entry(&mut Entry::new(format!("other-{}", i), String::new(), None)).unwrap();
+        }
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        db.update_entries().unwrap();
+        let entries = db.get_entries();
+        assert_eq!(entries.len(), 50);
+        for entry in entries.iter().filter(|e| !e.title.starts_with("other")) {
+            let thread = entry.title.split('-').next().unwrap();
+            assert_eq!(entry.tags.as_ref().unwrap()[0].tag, format!("thread{}", thread));
+        }
+        drop((db, other));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+        }
+    }
 }
\ No newline at end of file
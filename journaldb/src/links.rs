@@ -0,0 +1,169 @@
+use crate::{Db, Entry};
+
+/// Internal setting telling `update_entries` to rebuild the links of every entry
+pub(crate) const REINDEX_SETTING: &str = "links.reindex";
+
+/// A `[[...]]` reference in the content of an entry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    /// Text between the brackets, `#42` or an entry title
+    pub text: String,
+    /// Entry the link points to, `None` for broken links
+    pub target: Option<u32>,
+}
+
+/// Texts of the `[[...]]` references in `content`, in order and without duplicates
+pub fn parse_links(content: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("]]") else {
+            break;
+        };
+        let inner = &rest[..end];
+        // Links don't span lines, so a stray `[[` can't swallow the rest of the entry.
+        // Keep looking from here, a nested `[[` starts the actual link.
+        if inner.contains('\n') || inner.contains("[[") {
+            continue;
+        }
+        let text = inner.trim();
+        if !text.is_empty() && !links.iter().any(|l| l == text) {
+            links.push(text.to_string());
+        }
+        rest = &rest[end + 2..];
+    }
+    links
+}
+
+/// Finds the entry a link text points to: `#42` by id, anything else by title.
+/// Exact titles win over titles differing in case, older entries over newer ones.
+pub fn resolve_link(text: &str, entries: &[Entry]) -> Option<u32> {
+    if let Some(id) = text.strip_prefix('#').and_then(|id| id.parse::<u32>().ok()) {
+        return entries.iter().find(|e| e.id == id).map(|e| e.id);
+    }
+    let oldest = |matches: &dyn Fn(&Entry) -> bool| entries.iter().filter(|e| matches(e)).map(|e| e.id).min();
+    oldest(&|e| e.title == text).or_else(|| oldest(&|e| e.title.to_lowercase() == text.to_lowercase()))
+}
+
+impl Db {
+    /// Replaces the stored links of an entry with the ones in `content`
+    pub(crate) fn save_links(&self, entry_id: u32, content: &str) -> Result<(), rusqlite::Error> {
+        self.conn().execute("DELETE FROM entry_links WHERE entry_id = ?1", (entry_id,))?;
+        for (position, text) in parse_links(content).iter().enumerate() {
+            self.conn().execute(
+                "INSERT INTO entry_links (entry_id, position, target) VALUES (?1, ?2, ?3)",
+                (entry_id, position, self.seal_text(text)),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the links of all loaded entries
+    pub(crate) fn save_all_links(&self) -> Result<(), rusqlite::Error> {
+        for entry in &self.get_entries() {
+            self.save_links(entry.id, &entry.content)?;
+        }
+        Ok(())
+    }
+
+    fn stored_links(&self, entry_id: u32) -> Result<Vec<String>, rusqlite::Error> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT target FROM entry_links WHERE entry_id = ?1 ORDER BY position")?;
+        let links = stmt
+            .query_map((entry_id,), |r| Ok(self.open_text(r.get(0)?)?))?
+            .collect();
+        links
+    }
+
+    /// Links in the content of an entry, resolved against the current entries
+    pub fn outgoing_links(&self, entry_id: u32) -> Result<Vec<Link>, rusqlite::Error> {
+        Ok(self
+            .stored_links(entry_id)?
+            .into_iter()
+            .map(|text| Link { target: resolve_link(&text, &self.entries.read()), text })
+            .collect())
+    }
+
+    /// Links of an entry that don't point to any entry
+    pub fn broken_links(&self, entry_id: u32) -> Result<Vec<Link>, rusqlite::Error> {
+        Ok(self.outgoing_links(entry_id)?.into_iter().filter(|l| l.target.is_none()).collect())
+    }
+
+    /// Entries linking to an entry, in the order of the entry list
+    pub fn backlinks(&self, entry_id: u32) -> Result<Vec<Entry>, rusqlite::Error> {
+        let mut sources = Vec::new();
+        for entry in &self.get_entries() {
+            if entry.id == entry_id {
+                continue;
+            }
+            if self.outgoing_links(entry.id)?.iter().any(|l| l.target == Some(entry_id)) {
+                sources.push(entry.clone());
+            }
+        }
+        Ok(sources)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_links("See [[Trip to Rome]] and [[#42]], again [[ Trip to Rome ]]. [[]] [[open\nline]] [[a [[last]]"),
+            vec!["Trip to Rome", "#42", "last"]
+        );
+        assert_eq!(parse_links("no links, [[unclosed"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn outgoing_backlinks_and_broken() {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        let mut rome = Entry::new("Trip to Rome".to_string(), "Pasta".to_string(), None);
+        db.create_entry(&mut rome).unwrap();
+        let mut plans = Entry::new(
+            "Plans".to_string(),
+            format!("After [[trip to rome]] and [[#{}]] comes [[Trip to Paris]]", rome.id),
+            None,
+        );
+        db.create_entry(&mut plans).unwrap();
+
+        let links = db.outgoing_links(plans.id).unwrap();
+        assert_eq!(links.iter().map(|l| l.target).collect::<Vec<_>>(), vec![Some(rome.id), Some(rome.id), None]);
+        assert_eq!(db.broken_links(plans.id).unwrap()[0].text, "Trip to Paris");
+        assert_eq!(db.backlinks(rome.id).unwrap().iter().map(|e| e.id).collect::<Vec<_>>(), vec![plans.id]);
+
+        // Creating the missing entry fixes the link, editing the content replaces the links
+        let mut paris = Entry::new("Trip to Paris".to_string(), "Croissants".to_string(), None);
+        db.create_entry(&mut paris).unwrap();
+        assert!(db.broken_links(plans.id).unwrap().is_empty());
+        plans.content = "Only [[Trip to Paris]] now".to_string();
+        db.edit_entry(&mut plans).unwrap();
+        assert!(db.backlinks(rome.id).unwrap().is_empty());
+        assert_eq!(db.backlinks(paris.id).unwrap().len(), 1);
+
+        db.delete_entry(&plans).unwrap();
+        assert!(db.backlinks(paris.id).unwrap().is_empty());
+        let stored: u32 = db.conn().query_row("SELECT count(*) FROM entry_links", (), |r| r.get(0)).unwrap();
+        assert_eq!(stored, 0);
+    }
+
+    #[test]
+    fn links_of_existing_entries_are_indexed() {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        let mut first = Entry::new("First".to_string(), "".to_string(), None);
+        db.create_entry(&mut first).unwrap();
+        let mut second = Entry::new("Second".to_string(), "See [[First]]".to_string(), None);
+        db.create_entry(&mut second).unwrap();
+        // As after the migration adding the table
+        db.conn().execute("DELETE FROM entry_links", ()).unwrap();
+        db.set_setting(REINDEX_SETTING, "1").unwrap();
+        db.update_entries().unwrap();
+        assert_eq!(db.backlinks(first.id).unwrap().len(), 1);
+        assert_eq!(db.get_setting(REINDEX_SETTING).unwrap(), None);
+    }
+}
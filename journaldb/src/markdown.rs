@@ -0,0 +1,415 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, FixedOffset, Local};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    flags::Flag,
+    store::{entry_changed, no_such_entry, tag_names, JournalStore},
+    Entry, Tag,
+};
+
+/// A journal kept as a directory of Markdown files, one per entry:
+///
+/// ```text
+/// ---
+/// id: 3
+/// title: Trip to Rome
+/// created: 2024-01-01T10:00:00+01:00
+/// updated: 2024-01-02T08:00:00+01:00
+/// tags: travel, italy
+/// pinned: true
+/// ---
+///
+/// The content
+/// ```
+///
+/// `pinned`, `starred` and `archived` are only written for entries with the flag. Files without
+/// this header are read with their file name as title and their modification time as timestamps,
+/// and get the header when they are first saved. Other keys in the header, like those of static
+/// site generators, are kept as they are. Files that can't be read are left out and reported by
+/// `warnings`.
+pub struct MarkdownStore {
+    dir: PathBuf,
+    entries: Vec<Entry>,
+    /// File of each entry id
+    paths: HashMap<u32, PathBuf>,
+    /// Header lines of each entry id that aren't ours, written back unchanged
+    other_lines: HashMap<u32, Vec<String>>,
+    /// Files left out by the last `update_entries`, with the reason
+    warnings: Vec<String>,
+}
+
+const FENCE: &str = "---";
+
+/// Header keys the store reads and writes
+const KEYS: [&str; 8] = ["id", "title", "created", "updated", "tags", "pinned", "starred", "archived"];
+
+/// Files without an id of their own, or with one another file has, get an id from here on. It is
+/// made from the file name, so it stays the same when files are added without writing to the file.
+const FILE_NAME_IDS: u32 = 1 << 31;
+
+/// Id for the file named `name`, the next free one when another file has it
+fn file_name_id(name: &str, taken: &HashSet<u32>) -> u32 {
+    let hash = Sha256::digest(name.as_bytes());
+    let mut id = FILE_NAME_IDS | (u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]) & !FILE_NAME_IDS);
+    while taken.contains(&id) {
+        id = FILE_NAME_IDS | (id.wrapping_add(1) & !FILE_NAME_IDS);
+    }
+    id
+}
+
+impl MarkdownStore {
+    /// Opens the directory, creating it when missing
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, Box<dyn Error>> {
+        fs::create_dir_all(&dir)?;
+        let mut store = MarkdownStore {
+            dir: dir.as_ref().to_path_buf(),
+            entries: Vec::new(),
+            paths: HashMap::new(),
+            other_lines: HashMap::new(),
+            warnings: Vec::new(),
+        };
+        store.update_entries()?;
+        Ok(store)
+    }
+
+    pub fn get_dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn write(&self, entry: &Entry, path: &Path) -> Result<(), Box<dyn Error>> {
+        let other_lines = self.other_lines.get(&entry.id).map(Vec::as_slice).unwrap_or_default();
+        fs::write(path, to_markdown_with(entry, other_lines))?;
+        Ok(())
+    }
+
+    /// Path for a new entry, made from its id and title
+    fn new_path(&self, entry: &Entry) -> PathBuf {
+        self.dir.join(file_name(entry))
+    }
+}
+
+/// The file of an entry, header followed by the content, as the git mirror writes it
+#[cfg(feature = "git")]
+pub(crate) fn to_markdown(entry: &Entry) -> String {
+    to_markdown_with(entry, &[])
+}
+
+/// The file of an entry with `other_lines` at the end of the header
+fn to_markdown_with(entry: &Entry, other_lines: &[String]) -> String {
+    let names = tag_names(entry);
+    // Tags with a comma in them only survive as a list
+    let tags = match names.iter().any(|t| t.contains(',')) {
+        false => format!(" {}", names.join(", ")),
+        true => names.iter().map(|t| format!("\n  - {}", t)).collect(),
+    };
+    let flags: String = Flag::ALL.into_iter().filter(|f| entry.has_flag(*f)).map(|f| format!("{}: true\n", f)).collect();
+    let other: String = other_lines.iter().map(|line| format!("{}\n", line)).collect();
+    format!(
+        "{FENCE}\nid: {}\ntitle: {}\ncreated: {}\nupdated: {}\ntags:{}\n{}{}{FENCE}\n\n{}",
+        entry.id,
+        entry.title.replace('\n', " "),
+        entry.get_created_time().to_rfc3339(),
+        entry.get_updated_time().to_rfc3339(),
+        tags.trim_end(),
+        flags,
+        other,
+        entry.content,
+    )
+}
+
+/// File name for a new entry, made from its id and title
+pub(crate) fn file_name(entry: &Entry) -> String {
+    let slug: String = entry
+        .title
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+        .chars()
+        .take(40)
+        .collect();
+    match slug.trim_end_matches('-') {
+        "" => format!("{}.md", entry.id),
+        slug => format!("{}-{}.md", entry.id, slug),
+    }
+}
+
+/// Reads an entry file and the header lines that aren't ours. The id is 0 when the file has none.
+fn parse_file(path: &Path, text: &str) -> Result<(Entry, Vec<String>), Box<dyn Error>> {
+    let invalid = |what: &str| format!("invalid {}", what);
+    // Files saved on Windows end their lines with CRLF, saving writes them back with LF
+    let text = &text.replace("\r\n", "\n");
+    let header = text
+        .strip_prefix(FENCE)
+        .and_then(|rest| rest.strip_prefix('\n'))
+        .and_then(|rest| rest.split_once(&format!("\n{FENCE}\n")).or_else(|| rest.strip_suffix(&format!("\n{FENCE}")).map(|h| (h, ""))));
+    let Some((header, content)) = header else {
+        let modified: DateTime<Local> = fs::metadata(path)?.modified()?.into();
+        let title = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let entry = Entry::new(title, text.to_string(), None)
+            .with_created_time(modified.fixed_offset())
+            .with_updated_time(modified.fixed_offset());
+        return Ok((entry, Vec::new()));
+    };
+
+    // Indented lines belong to the key above them, like the items of a YAML list
+    let mut fields: HashMap<&str, &str> = HashMap::new();
+    let mut tag_items: Vec<&str> = Vec::new();
+    let mut other_lines = Vec::new();
+    let mut key = None;
+    for line in header.lines() {
+        if line.starts_with([' ', '\t']) || line.starts_with("- ") {
+            match key {
+                Some("tags") => tag_items.extend(line.trim().strip_prefix("- ")),
+                Some(_) => {}
+                None => other_lines.push(line.to_string()),
+            }
+            continue;
+        }
+        key = match line.split_once(':').map(|(k, v)| (k.trim(), v.trim())) {
+            Some((k, v)) if KEYS.contains(&k) => {
+                fields.insert(k, v);
+                Some(k)
+            }
+            _ => {
+                other_lines.push(line.to_string());
+                None
+            }
+        };
+    }
+    let time = |key: &str| -> Result<Option<DateTime<FixedOffset>>, Box<dyn Error>> {
+        match fields.get(key) {
+            Some(t) => Ok(Some(DateTime::parse_from_rfc3339(t).map_err(|_| invalid(key))?)),
+            None => Ok(None),
+        }
+    };
+    let inline = fields.get("tags").map(|t| t.trim_start_matches('[').trim_end_matches(']')).unwrap_or_default();
+    let tags: Vec<Tag> = inline
+        .split(',')
+        .chain(tag_items)
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(|t| Tag::new(t.to_string()))
+        .collect();
+    let content = content.strip_prefix('\n').unwrap_or(content);
+    let mut entry = Entry::new(
+        fields.get("title").unwrap_or(&"").to_string(),
+        content.to_string(),
+        Some(tags).filter(|t| !t.is_empty()),
+    );
+    entry.id = match fields.get("id") {
+        Some(id) => id.parse().map_err(|_| invalid("id"))?,
+        None => 0,
+    };
+    if let Some(created) = time("created")? {
+        entry.set_created_time(created);
+    }
+    entry.set_updated_time(time("updated")?.unwrap_or(entry.get_created_time()));
+    for flag in Flag::ALL {
+        match fields.get(flag.name()) {
+            None | Some(&"false") => {}
+            Some(&"true") => entry.set_flag(flag, true),
+            Some(_) => return Err(invalid(flag.name()).into()),
+        }
+    }
+    Ok((entry, other_lines))
+}
+
+impl JournalStore for MarkdownStore {
+    fn update_entries(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut files: Vec<PathBuf> = fs::read_dir(&self.dir)?
+            .map(|f| f.map(|f| f.path()))
+            .collect::<Result<_, _>>()?;
+        files.retain(|f| f.is_file() && f.extension().is_some_and(|e| e == "md"));
+        files.sort();
+
+        let mut entries = Vec::new();
+        let mut warnings = Vec::new();
+        for path in files {
+            match fs::read_to_string(&path).map_err(Box::from).and_then(|text| parse_file(&path, &text)) {
+                Ok((entry, other_lines)) => entries.push((entry, other_lines, path)),
+                Err(e) => warnings.push(format!("skipped {}: {}", path.display(), e)),
+            }
+        }
+        // The first file in file name order keeps an id, the others get one from their file name
+        let mut taken: HashSet<u32> = HashSet::new();
+        let mut without_id = Vec::new();
+        for (i, (entry, _, _)) in entries.iter().enumerate() {
+            if entry.id == 0 || !taken.insert(entry.id) {
+                without_id.push(i);
+            }
+        }
+        for i in without_id {
+            let (entry, _, path) = &mut entries[i];
+            entry.id = file_name_id(&path.file_name().unwrap_or_default().to_string_lossy(), &taken);
+            taken.insert(entry.id);
+        }
+        let mut paths = HashMap::new();
+        let mut other_lines = HashMap::new();
+        let mut entries: Vec<Entry> = entries
+            .into_iter()
+            .map(|(entry, lines, path)| {
+                paths.insert(entry.id, path);
+                other_lines.insert(entry.id, lines);
+                entry
+            })
+            .collect();
+        entries.sort_by_key(|e| e.id);
+        self.entries = entries;
+        self.paths = paths;
+        self.other_lines = other_lines;
+        self.warnings = warnings;
+        Ok(())
+    }
+
+    fn get_entries(&self) -> Vec<Entry> {
+        self.entries.clone()
+    }
+
+    fn create_entry(&mut self, entry: &mut Entry) -> Result<(), Box<dyn Error>> {
+        self.update_entries()?;
+        entry.id = self.entries.iter().map(|e| e.id).filter(|id| *id < FILE_NAME_IDS).max().unwrap_or(0) + 1;
+        let path = self.new_path(entry);
+        if path.exists() {
+            return Err(format!("{} already exists", path.display()).into());
+        }
+        self.write(entry, &path)?;
+        self.update_entries()
+    }
+
+    fn edit_entry(&mut self, entry: &mut Entry) -> Result<(), Box<dyn Error>> {
+        let stored = self.get_entry_by_id(entry.id).ok_or_else(|| no_such_entry(entry.id))?;
+        if !entry_changed(&stored, entry) {
+            return Ok(());
+        }
+        entry.set_updated_time(Local::now().fixed_offset());
+        // Keep the file name, other tools may refer to it
+        self.write(entry, &self.paths[&entry.id])?;
+        self.update_entries()
+    }
+
+    fn delete_entry(&mut self, entry: &Entry) -> Result<(), Box<dyn Error>> {
+        if let Some(path) = self.paths.get(&entry.id) {
+            fs::remove_file(path)?;
+        }
+        self.update_entries()
+    }
+
+    fn set_flag(&mut self, entry_id: u32, flag: Flag, on: bool) -> Result<(), Box<dyn Error>> {
+        let mut entry = self.get_entry_by_id(entry_id).ok_or_else(|| no_such_entry(entry_id))?;
+        entry.set_flag(flag, on);
+        self.write(&entry, &self.paths[&entry_id])?;
+        self.update_entries()
+    }
+
+    fn warnings(&self) -> Vec<String> {
+        self.warnings.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::tests::check_store;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("journaldb-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn markdown_store() {
+        let dir = temp_dir("markdown");
+        let mut store = MarkdownStore::open(&dir).unwrap();
+        check_store(&mut store);
+        let files: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|f| f.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(files, vec!["1-first.md"]);
+        let text = fs::read_to_string(dir.join("1-first.md")).unwrap();
+        assert!(text.starts_with("---\nid: 1\ntitle: First\ncreated: 2024-01-05T21:00:00-05:00\n"));
+        assert!(text.ends_with("tags: home\npinned: true\n---\n\nChanged"));
+
+        // The header is found in files with CRLF line endings too
+        fs::write(dir.join("windows.md"), "---\r\nid: 9\r\ntitle: Windows\r\ntags: pc\r\n---\r\n\r\nLine one\r\nLine two").unwrap();
+        store.update_entries().unwrap();
+        let windows = store.get_entry_by_id(9).unwrap();
+        assert_eq!((windows.title.as_str(), windows.content.as_str()), ("Windows", "Line one\nLine two"));
+        assert_eq!(tag_names(&windows), vec!["pc"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn plain_files_become_entries() {
+        let dir = temp_dir("plain");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("groceries.md"), "- milk\n- bread").unwrap();
+        fs::write(dir.join("notes.txt"), "not an entry").unwrap();
+        fs::write(dir.join("7.md"), "---\nid: 7\ntitle: Seven\n---\n").unwrap();
+        let mut store = MarkdownStore::open(&dir).unwrap();
+        let entries = store.get_entries();
+        let groceries_id = entries[1].id;
+        assert_eq!(entries.iter().map(|e| e.title.as_str()).collect::<Vec<_>>(), vec!["Seven", "groceries"]);
+        assert_eq!((entries[0].id, entries[0].content.as_str()), (7, ""));
+        assert_eq!(entries[1].content, "- milk\n- bread");
+
+        // Reading leaves the file alone, and files added later don't change its id
+        assert_eq!(fs::read_to_string(dir.join("groceries.md")).unwrap(), "- milk\n- bread");
+        fs::write(dir.join("apples.md"), "green").unwrap();
+        store.update_entries().unwrap();
+        assert_eq!(store.get_entry_by_id(groceries_id).unwrap().title, "groceries");
+        let mut first = Entry::new("First".to_string(), String::new(), None);
+        store.create_entry(&mut first).unwrap();
+        assert_eq!(first.id, 8);
+
+        // Saving adds the header
+        let mut groceries = entries[1].clone();
+        groceries.set_content("- milk".to_string());
+        store.edit_entry(&mut groceries).unwrap();
+        assert!(fs::read_to_string(dir.join("groceries.md")).unwrap().starts_with(&format!("---\nid: {}\n", groceries_id)));
+        let mut store = MarkdownStore::open(&dir).unwrap();
+        assert_eq!(store.get_entry_by_id(groceries_id).unwrap().content, "- milk");
+        assert!(store.warnings().is_empty());
+
+        // A broken file doesn't take the others with it
+        fs::write(dir.join("bad.md"), "---\nid: x\n---\n").unwrap();
+        store.update_entries().unwrap();
+        assert_eq!(store.get_entries().len(), 4);
+        assert_eq!(store.warnings(), vec![format!("skipped {}: invalid id", dir.join("bad.md").display())]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_other_header_keys() {
+        let dir = temp_dir("front-matter");
+        fs::create_dir_all(&dir).unwrap();
+        let text = "---\ntitle: Post\nlayout: post\naliases:\n  - /old\n  - /older\ntags: [travel, food]\ndraft: true\n---\nBody";
+        fs::write(dir.join("post.md"), text).unwrap();
+        let mut store = MarkdownStore::open(&dir).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("post.md")).unwrap(), text);
+
+        let mut post = store.get_entries().remove(0);
+        assert_eq!((post.title.as_str(), post.content.as_str()), ("Post", "Body"));
+        assert_eq!(tag_names(&post), vec!["travel", "food"]);
+        post.set_content("Edited".to_string());
+        post.tags = Some(vec![Tag::new("food, drinks".to_string())]);
+        store.edit_entry(&mut post).unwrap();
+        let text = fs::read_to_string(dir.join("post.md")).unwrap();
+        assert!(text.contains("tags:\n  - food, drinks\nlayout: post\naliases:\n  - /old\n  - /older\ndraft: true\n---\n\nEdited"));
+
+        let store = MarkdownStore::open(&dir).unwrap();
+        let post = store.get_entry_by_id(post.id).unwrap();
+        assert_eq!((tag_names(&post), post.content.as_str()), (vec!["food, drinks".to_string()], "Edited"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
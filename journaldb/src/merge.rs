@@ -0,0 +1,282 @@
+use std::{error::Error, fmt};
+
+use crate::{changes::Change, crypto::CryptoError, flags::Flag, revisions::same_version, Db, Entry, Tag};
+
+/// What `Db::merge` did. Entries are given by their id in this journal and their title.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// Entries only the other journal had
+    pub created: Vec<(u32, String)>,
+    /// Entries the other journal had a later version of
+    pub updated: Vec<(u32, String)>,
+    /// Entries edited in both journals. The newer edit won, the other one is in the history.
+    pub conflicts: Vec<(u32, String)>,
+    pub unchanged: usize,
+    /// Earlier versions copied from the other journal's history
+    pub revisions: usize,
+    /// Attachments copied to entries both journals have, those with content already there are left out
+    pub attachments: usize,
+}
+
+impl fmt::Display for MergeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = |n: usize| if n == 1 { "entry" } else { "entries" };
+        for (what, entries) in [("Created", &self.created), ("Updated", &self.updated), ("Conflicting", &self.conflicts)] {
+            if entries.is_empty() {
+                continue;
+            }
+            writeln!(f, "{} {} {}:", what, entries.len(), plural(entries.len()))?;
+            for (id, title) in entries {
+                writeln!(f, "  {}: {}", id, title)?;
+            }
+        }
+        if !self.conflicts.is_empty() {
+            writeln!(f, "Conflicts keep the newer edit, the other one is in the entry's history")?;
+        }
+        writeln!(f, "{} {} unchanged", self.unchanged, plural(self.unchanged))?;
+        writeln!(f, "{} earlier versions copied", self.revisions)?;
+        write!(f, "{} attachments copied", self.attachments)
+    }
+}
+
+/// Tags of both versions, those of `first` first
+fn tag_union(first: &Entry, second: &Entry) -> Option<Vec<Tag>> {
+    let mut tags: Vec<Tag> = Vec::new();
+    for tag in first.tags.iter().flatten().chain(second.tags.iter().flatten()) {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+    Some(tags).filter(|t| !t.is_empty())
+}
+
+/// `entry` with the flags of `other` added. A flag set in either journal stays set, since setting
+/// one doesn't move the updated time that tells which version is newer.
+fn with_flags_of(entry: &Entry, other: &Entry) -> Entry {
+    let mut entry = entry.clone();
+    for flag in Flag::ALL {
+        if other.has_flag(flag) {
+            entry.set_flag(flag, true);
+        }
+    }
+    entry
+}
+
+impl Db {
+    /// Brings the entries of `other`, e.g. a copy of this journal edited on another machine, into
+    /// this one. Entries are matched by UUID and tags by name. Entries missing here are created with
+    /// their attachments, and entries both have get the attachments and flags only the other has. When only one side edited an entry since they last agreed, which the
+    /// history tells, its version is kept. When both did, the newer edit wins with the tags of both,
+    /// and the other edit goes into the history.
+    pub fn merge(&self, other: &Db) -> Result<MergeReport, Box<dyn Error>> {
+        if other.is_locked()? {
+            return Err(CryptoError::Locked.into());
+        }
+        self.check_mirror()?;
+        other.update_entries()?;
+        self.update_entries()?;
+        let mut report = MergeReport::default();
+        // Mirrored and announced to subscribers once the transaction is committed
+        let mut changed: Vec<(u32, &str)> = Vec::new();
+        let conn = self.conn();
+        let tx = conn.unchecked_transaction()?;
+        for theirs in other.get_entries() {
+            let uuid = other.get_uuid(theirs.id)?;
+            let their_history = other.get_revisions(theirs.id)?;
+            let Some(id) = self.find_uuid(&uuid)? else {
+                let mut entry = theirs.clone();
+                self.insert_entry(&mut entry)?;
+                self.set_uuid(entry.id, &uuid)?;
+                for revision in &their_history {
+                    self.save_revision(entry.id, revision)?;
+                }
+                for attachment in other.get_attachments(theirs.id)? {
+                    let data = other.get_attachment_data(attachment.id)?;
+                    self.add_attachment(entry.id, &attachment.filename, Some(&attachment.mime), &data)?;
+                }
+                changed.push((entry.id, "Create"));
+                report.created.push((entry.id, entry.title));
+                continue;
+            };
+
+            let ours = self.get_entry_by_id(id).ok_or("entry vanished while merging")?;
+            let our_hashes: Vec<String> = self.get_attachments(id)?.into_iter().map(|a| a.hash).collect();
+            let mut attached = false;
+            for attachment in other.get_attachments(theirs.id)? {
+                if our_hashes.contains(&attachment.hash) {
+                    continue;
+                }
+                let data = other.get_attachment_data(attachment.id)?;
+                self.add_attachment(id, &attachment.filename, Some(&attachment.mime), &data)?;
+                report.attachments += 1;
+                attached = true;
+            }
+            let our_history = self.get_revisions(id)?;
+            let new_history = their_history.iter().filter(|r| !our_history.iter().any(|o| same_version(o, r)));
+            for revision in new_history {
+                if self.save_revision(id, revision)? {
+                    report.revisions += 1;
+                }
+            }
+            let flagged = with_flags_of(&ours, &theirs);
+            if same_version(&ours, &theirs) || our_history.iter().any(|r| same_version(r, &theirs)) {
+                report.unchanged += 1;
+            } else if their_history.iter().any(|r| same_version(r, &ours)) {
+                self.replace_version(&ours, &with_flags_of(&theirs, &ours))?;
+                changed.push((id, "Merge"));
+                report.updated.push((id, theirs.title));
+            } else {
+                let (newer, older) = if theirs.updated_time > ours.updated_time { (&theirs, &ours) } else { (&ours, &theirs) };
+                let merged = with_flags_of(&Entry { tags: tag_union(newer, older), ..newer.clone() }, older);
+                // Ours goes into the history when it is replaced
+                if !same_version(older, &ours) {
+                    self.save_revision(id, older)?;
+                }
+                if !same_version(newer, &merged) {
+                    // Lets the other journal see the merge as a later version of its own
+                    self.save_revision(id, newer)?;
+                }
+                if !same_version(&ours, &merged) {
+                    self.replace_version(&ours, &merged)?;
+                    changed.push((id, "Merge"));
+                }
+                report.conflicts.push((id, merged.title));
+            }
+            let reflagged = flagged.markers() != ours.markers();
+            if reflagged {
+                self.save_flags(&flagged)?;
+            }
+            if (attached || reflagged) && !changed.iter().any(|(changed_id, _)| *changed_id == id) {
+                changed.push((id, "Merge"));
+            }
+        }
+        tx.commit()?;
+        // Nobody hears of the merge before it is stored
+        self.update_entries()?;
+        for (id, _action) in changed {
+            #[cfg(feature = "git")]
+            self.mirror_entry(id, _action);
+            self.notify(Change::Entry(id));
+        }
+        Ok(report)
+    }
+
+    /// Overwrites the stored entry `ours` with `version`, including its updated time and flags, and
+    /// keeps `ours` in the history. The entry list and the subscribers are left to the caller.
+    fn replace_version(&self, ours: &Entry, version: &Entry) -> Result<(), rusqlite::Error> {
+        self.save_revision(ours.id, ours)?;
+        let entry = Entry { id: ours.id, ..version.clone() };
+        self.conn().execute(
+            "UPDATE entries SET entry_title = ?1, entry_content = ?2, entry_updated_time = ?3, entry_updated_offset = ?4
+            WHERE entry_id = ?5",
+            (self.seal_text(&entry.title), self.seal_text(&entry.content), entry.updated_time, entry.updated_offset, entry.id),
+        )?;
+        self.save_links(entry.id, &entry.content)?;
+        self.save_tags(&entry)?;
+        self.save_flags(&entry)
+    }
+
+    fn save_flags(&self, entry: &Entry) -> Result<(), rusqlite::Error> {
+        self.conn().execute(
+            "UPDATE entries SET entry_pinned = ?1, entry_starred = ?2, entry_archived = ?3 WHERE entry_id = ?4",
+            (entry.pinned, entry.starred, entry.archived, entry.id),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    fn entry(title: &str, content: &str, tags: &[&str], updated: &str) -> Entry {
+        let tags = tags.iter().map(|t| Tag::new(t.to_string())).collect();
+        let time = DateTime::parse_from_rfc3339(updated).unwrap();
+        Entry::new(title.to_string(), content.to_string(), Some(tags)).with_created_time(time).with_updated_time(time)
+    }
+
+    /// Saves a version of `entry` written at `updated`, like an edit on that machine at that time
+    fn edit(db: &Db, entry: &Entry, content: &str, tags: &[&str], updated: &str) {
+        let mut edited = entry.clone();
+        edited.content = content.to_string();
+        edited.tags = Some(tags.iter().map(|t| Tag::new(t.to_string())).collect());
+        edited.set_updated_time(DateTime::parse_from_rfc3339(updated).unwrap());
+        let stored = db.get_entry_by_id(entry.id).unwrap();
+        db.replace_version(&stored, &edited).unwrap();
+        db.update_entries().unwrap();
+    }
+
+    fn tag_names(entry: &Entry) -> Vec<String> {
+        let mut names: Vec<String> = entry.tags.iter().flatten().map(Tag::get_tag).collect();
+        names.sort();
+        names
+    }
+
+    fn copy(db: &Db, path: &std::path::Path) -> Db {
+        let _ = std::fs::remove_file(path);
+        db.conn().execute("VACUUM INTO ?1", (path.to_str().unwrap(),)).unwrap();
+        let copy = Db::new(path.to_str().unwrap());
+        copy.initialize_db().unwrap();
+        copy.update_entries().unwrap();
+        copy
+    }
+
+    #[test]
+    fn merge_copies() {
+        let laptop = Db::new(":memory:");
+        laptop.initialize_db().unwrap();
+        let mut rome = entry("Rome", "Pasta", &["travel"], "2024-03-01T12:00:00+01:00");
+        let mut plans = entry("Plans", "Draft", &["work"], "2024-03-01T12:00:00+01:00");
+        let mut garden = entry("Garden", "Roses", &[], "2024-03-01T12:00:00+01:00");
+        for e in [&mut rome, &mut plans, &mut garden] {
+            laptop.create_entry(e).unwrap();
+        }
+        laptop.add_attachment(garden.id, "rose.txt", None, b"red").unwrap();
+        let path = std::env::temp_dir().join(format!("journaldb-merge-{}.db", std::process::id()));
+        let desktop = copy(&laptop, &path);
+        // Attachments of entries both have come over, unless their content is there already
+        desktop.add_attachment(garden.id, "photo.txt", None, b"green").unwrap();
+        desktop.add_attachment(garden.id, "rose copy.txt", None, b"red").unwrap();
+
+        // Only the desktop edits Rome, both edit Plans, the desktop adds an entry
+        edit(&desktop, &rome, "Pizza", &["travel"], "2024-03-02T09:00:00+01:00");
+        edit(&laptop, &plans, "Laptop plan", &["work", "draft"], "2024-03-02T10:00:00+01:00");
+        edit(&desktop, &plans, "Desktop plan", &["work", "final"], "2024-03-02T11:00:00+01:00");
+        let mut hike = entry("Hike", "Alps", &["travel"], "2024-03-03T08:00:00+01:00");
+        desktop.create_entry(&mut hike).unwrap();
+        desktop.add_attachment(hike.id, "map.txt", Some("text/plain"), b"route").unwrap();
+
+        let changes = laptop.subscribe();
+        let report = laptop.merge(&desktop).unwrap();
+        let changes: Vec<Change> = changes.try_iter().collect();
+        assert_eq!(changes, vec![Change::Entry(rome.id), Change::Entry(plans.id), Change::Entry(garden.id), Change::Entry(4)]);
+        assert_eq!(report.created, vec![(4, "Hike".to_string())]);
+        assert_eq!(report.updated, vec![(rome.id, "Rome".to_string())]);
+        assert_eq!(report.conflicts, vec![(plans.id, "Plans".to_string())]);
+        assert_eq!((report.unchanged, report.revisions, report.attachments), (1, 1, 1));
+        let names: Vec<String> = laptop.get_attachments(garden.id).unwrap().into_iter().map(|a| a.filename).collect();
+        assert_eq!(names, vec!["rose.txt", "photo.txt"]);
+        assert_eq!(report.to_string().lines().next(), Some("Created 1 entry:"));
+
+        assert_eq!(laptop.get_entry_by_id(rome.id).unwrap().content, "Pizza");
+        let merged = laptop.get_entry_by_id(plans.id).unwrap();
+        assert_eq!(merged.content, "Desktop plan");
+        assert_eq!(merged.get_updated_time(), DateTime::parse_from_rfc3339("2024-03-02T11:00:00+01:00").unwrap());
+        assert_eq!(tag_names(&merged), vec!["draft", "final", "work"]);
+        let history: Vec<String> = laptop.get_revisions(plans.id).unwrap().iter().map(Entry::get_content).collect();
+        assert_eq!(history, vec!["Draft", "Laptop plan", "Desktop plan"]);
+        assert_eq!(laptop.get_uuid(4).unwrap(), desktop.get_uuid(hike.id).unwrap());
+        assert_eq!(laptop.get_attachment_data(laptop.get_attachments(4).unwrap()[0].id).unwrap(), b"route");
+
+        // Merging back brings the desktop up to date, after that nothing changes
+        let report = desktop.merge(&laptop).unwrap();
+        assert_eq!((report.created.len(), report.updated.len(), report.conflicts.len()), (0, 1, 0));
+        assert_eq!(tag_names(&desktop.get_entry_by_id(plans.id).unwrap()), tag_names(&merged));
+        let report = laptop.merge(&desktop).unwrap();
+        assert_eq!(report, MergeReport { unchanged: 4, ..MergeReport::default() });
+
+        drop(desktop);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
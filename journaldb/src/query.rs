@@ -0,0 +1,518 @@
+use std::{collections::HashSet, fmt, str::FromStr};
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, TimeZone};
+use rusqlite::{functions::FunctionFlags, types::Value};
+
+use crate::{flags::Flag, time, Db, Entry};
+
+/// A parsed search, e.g. `tag:work -tag:draft after:2024-01-01 "exact phrase" title:retro`.
+///
+/// Terms next to each other must all match, `OR` matches either side and binds weaker than
+/// `AND`, `NOT` or a leading `-` negates a term, parentheses group. Words and `"phrases"` are
+/// looked up in title and content, `title:` only in the title, all ignoring case.
+/// `after:` includes the given day, `before:` doesn't. Both take `2024-01-01` or the days
+/// `time::parse_datetime` understands, like `after:yesterday` or `after:"7 days ago"`.
+/// `is:pinned`, `is:starred` and `is:archived` match flagged entries.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Query {
+    Text(String),
+    Title(String),
+    Tag(String),
+    After(NaiveDate),
+    Before(NaiveDate),
+    Flag(Flag),
+    Not(Box<Query>),
+    /// Matches everything when empty
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+/// Why a query can't be parsed, and where
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryError {
+    /// Offset of the offending character, in characters
+    pub position: usize,
+    pub message: String,
+}
+
+impl QueryError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        QueryError {
+            position,
+            message: message.into(),
+        }
+    }
+
+    /// The query with a `^` under the offending position on the next line
+    pub fn pointer(&self, query: &str) -> String {
+        format!("{}\n{}^ {}", query, " ".repeat(self.position), self.message)
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position + 1)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Term {
+        field: Option<String>,
+        value: String,
+        /// Where the value starts, for pointing at invalid dates
+        value_position: usize,
+    },
+}
+
+/// Reads a `"..."` starting at `start`, returning the text between the quotes and the position after them
+fn quoted(chars: &[char], start: usize) -> Result<(String, usize), QueryError> {
+    let end = chars[start + 1..]
+        .iter()
+        .position(|c| *c == '"')
+        .map(|end| start + 1 + end)
+        .ok_or_else(|| QueryError::new(start, "unclosed quote"))?;
+    Ok((chars[start + 1..end].iter().collect(), end + 1))
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, QueryError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        match chars[i] {
+            c if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push((start, Token::Open));
+                i += 1;
+            }
+            ')' => {
+                tokens.push((start, Token::Close));
+                i += 1;
+            }
+            // Only a negation when attached to what follows, `a - b` searches for `-`
+            '-' if chars.get(i + 1).is_some_and(|c| !c.is_whitespace() && *c != ')') => {
+                tokens.push((start, Token::Not));
+                i += 1;
+            }
+            '"' => {
+                let (value, end) = quoted(&chars, i)?;
+                tokens.push((start, Token::Term { field: None, value, value_position: start + 1 }));
+                i = end;
+            }
+            _ => {
+                while i < chars.len() && !chars[i].is_whitespace() && !matches!(chars[i], '(' | ')' | '"') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                let token = match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => match word.split_once(':') {
+                        Some((field, value)) => {
+                            let value_position = start + field.chars().count() + 1;
+                            let mut value = value.to_string();
+                            if value.is_empty() && chars.get(i) == Some(&'"') {
+                                (value, i) = quoted(&chars, i)?;
+                            }
+                            Token::Term {
+                                field: Some(field.to_lowercase()),
+                                value,
+                                value_position,
+                            }
+                        }
+                        None => Token::Term { field: None, value: word, value_position: start },
+                    },
+                };
+                tokens.push((start, token));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    /// Length of the input, where running out of tokens is reported
+    end: usize,
+    /// Relative dates are counted from here
+    now: DateTime<FixedOffset>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, t)| t)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.next).map(|(p, _)| *p).unwrap_or(self.end)
+    }
+
+    fn or(&mut self) -> Result<Query, QueryError> {
+        let mut parts = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next += 1;
+            parts.push(self.and()?);
+        }
+        Ok(if parts.len() == 1 { parts.remove(0) } else { Query::Or(parts) })
+    }
+
+    fn and(&mut self) -> Result<Query, QueryError> {
+        let mut parts = vec![self.unary()?];
+        loop {
+            match self.peek() {
+                None | Some(Token::Or | Token::Close) => break,
+                Some(Token::And) => self.next += 1,
+                _ => {}
+            }
+            parts.push(self.unary()?);
+        }
+        Ok(if parts.len() == 1 { parts.remove(0) } else { Query::And(parts) })
+    }
+
+    fn unary(&mut self) -> Result<Query, QueryError> {
+        if self.peek() == Some(&Token::Not) {
+            self.next += 1;
+            return Ok(Query::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Query, QueryError> {
+        let position = self.position();
+        let Some((_, token)) = self.tokens.get(self.next).cloned() else {
+            return Err(QueryError::new(position, "expected a search term"));
+        };
+        self.next += 1;
+        match token {
+            Token::Open => {
+                let query = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(QueryError::new(position, "missing closing parenthesis"));
+                }
+                self.next += 1;
+                Ok(query)
+            }
+            Token::Close => Err(QueryError::new(position, "expected a search term before )")),
+            Token::And | Token::Or => Err(QueryError::new(position, "expected a search term before AND/OR")),
+            Token::Not => unreachable!("handled by unary"),
+            Token::Term { field, value, value_position } => {
+                term(position, field.as_deref(), value, value_position, self.now)
+            }
+        }
+    }
+}
+
+fn term(
+    position: usize,
+    field: Option<&str>,
+    value: String,
+    value_position: usize,
+    now: DateTime<FixedOffset>,
+) -> Result<Query, QueryError> {
+    if value.trim().is_empty() {
+        let message = match field {
+            Some(field) => format!("{}: needs a value", field),
+            None => "empty phrase".to_string(),
+        };
+        return Err(QueryError::new(value_position, message));
+    }
+    let date = || {
+        time::parse_datetime(&value, now)
+            .map(|d| d.date_naive())
+            .map_err(|_| QueryError::new(value_position, "expected a date like 2024-01-01 or \"3 days ago\""))
+    };
+    match field {
+        None => Ok(Query::Text(value)),
+        Some("tag") => Ok(Query::Tag(value)),
+        Some("title") => Ok(Query::Title(value)),
+        Some("after") => Ok(Query::After(date()?)),
+        Some("before") => Ok(Query::Before(date()?)),
+        Some("is") => value.parse().map(Query::Flag).map_err(|e: String| QueryError::new(value_position, e)),
+        Some(field) => Err(QueryError::new(
+            position,
+            format!("unknown field {}:, use tag:, title:, is:, after: or before:", field),
+        )),
+    }
+}
+
+/// Pattern matching `text` anywhere, with LIKE wildcards in it escaped
+fn like_pattern(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+impl Query {
+    /// Parses a query with relative dates counted from now
+    pub fn parse(input: &str) -> Result<Query, QueryError> {
+        Query::parse_at(input, Local::now())
+    }
+
+    /// Parses a query with relative dates counted from `now`
+    pub fn parse_at<Z: TimeZone>(input: &str, now: DateTime<Z>) -> Result<Query, QueryError> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            next: 0,
+            end: input.chars().count(),
+            now: now.fixed_offset(),
+        };
+        if parser.tokens.is_empty() {
+            return Ok(Query::And(Vec::new()));
+        }
+        let query = parser.or()?;
+        if parser.peek().is_some() {
+            return Err(QueryError::new(parser.position(), "unmatched closing parenthesis"));
+        }
+        Ok(query)
+    }
+
+    /// Whether `entry` matches, the same way the SQL of `to_sql` decides for stored entries. Case
+    /// is ignored for all letters, not only ASCII ones.
+    pub fn matches(&self, entry: &Entry) -> bool {
+        let contains = |text: &str, part: &str| text.to_lowercase().contains(&part.to_lowercase());
+        match self {
+            Query::Text(text) => contains(&entry.title, text) || contains(&entry.content, text),
+            Query::Title(text) => contains(&entry.title, text),
+            Query::Tag(tag) => entry.tags.iter().flatten().any(|t| t.tag.to_lowercase() == tag.to_lowercase()),
+            Query::After(date) => entry.get_created_date() >= *date,
+            Query::Before(date) => entry.get_created_date() < *date,
+            Query::Flag(flag) => entry.has_flag(*flag),
+            Query::Not(query) => !query.matches(entry),
+            Query::And(parts) => parts.iter().all(|p| p.matches(entry)),
+            Query::Or(parts) => parts.iter().any(|p| p.matches(entry)),
+        }
+    }
+
+    /// Condition on the `entries` table with its parameters. Titles and content are read through the
+    /// `journal_text` function, which `Db::search` provides to decrypt encrypted journals, and
+    /// compared through `journal_lower`, which lowercases like Rust since SQLite's `lower` and
+    /// `LIKE` only know ASCII.
+    pub fn to_sql(&self) -> (String, Vec<Value>) {
+        let mut params = Vec::new();
+        let sql = self.sql(&mut params);
+        (sql, params)
+    }
+
+    fn sql(&self, params: &mut Vec<Value>) -> String {
+        let mut param = |value: Value| {
+            params.push(value);
+            params.len()
+        };
+        match self {
+            Query::Text(text) => {
+                let n = param(Value::Text(like_pattern(&text.to_lowercase())));
+                format!(
+                    "(journal_lower(journal_text(entry_title)) LIKE ?{n} ESCAPE '\\' \
+                    OR journal_lower(journal_text(entry_content)) LIKE ?{n} ESCAPE '\\')"
+                )
+            }
+            Query::Title(text) => {
+                let n = param(Value::Text(like_pattern(&text.to_lowercase())));
+                format!("journal_lower(journal_text(entry_title)) LIKE ?{n} ESCAPE '\\'")
+            }
+            Query::Tag(tag) => {
+                let n = param(Value::Text(tag.to_lowercase()));
+                format!(
+                    "EXISTS (SELECT 1 FROM entry_tags JOIN tags ON tags.tag_id = entry_tags.tag_id
+                    WHERE entry_tags.entry_id = entries.entry_id AND journal_lower(tags.tag) = ?{n})"
+                )
+            }
+            // Compare with the day on the clock where the entry was written
+            Query::After(date) => {
+                let n = param(Value::Text(date.format("%Y-%m-%d").to_string()));
+                format!("date(entry_created_time + entry_created_offset, 'unixepoch') >= ?{n}")
+            }
+            Query::Before(date) => {
+                let n = param(Value::Text(date.format("%Y-%m-%d").to_string()));
+                format!("date(entry_created_time + entry_created_offset, 'unixepoch') < ?{n}")
+            }
+            Query::Flag(flag) => format!("{} = 1", flag.column()),
+            Query::Not(query) => format!("NOT ({})", query.sql(params)),
+            Query::And(parts) if parts.is_empty() => "1".to_string(),
+            Query::Or(parts) if parts.is_empty() => "0".to_string(),
+            Query::And(parts) | Query::Or(parts) => {
+                let joiner = if matches!(self, Query::And(_)) { " AND " } else { " OR " };
+                let parts: Vec<String> = parts.iter().map(|p| format!("({})", p.sql(params))).collect();
+                parts.join(joiner)
+            }
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Query::parse(s)
+    }
+}
+
+impl Db {
+    /// Entries matching `query`, in the order of the entry list
+    pub fn search(&self, query: &Query) -> Result<Vec<Entry>, rusqlite::Error> {
+        self.register_text_function()?;
+        self.register_lower_function()?;
+        let (condition, params) = query.to_sql();
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!("SELECT entry_id FROM entries WHERE {}", condition))?;
+        let ids = stmt
+            .query_map(rusqlite::params_from_iter(params), |r| r.get(0))?
+            .collect::<Result<HashSet<u32>, _>>()?;
+        Ok(self.entries.read().iter().filter(|e| ids.contains(&e.id)).cloned().collect())
+    }
+
+    /// Lets SQL lowercase any letter with `journal_lower(text)`, the way `Query::matches` does.
+    /// NULL and other values without letters are returned as they are, like `lower` does.
+    fn register_lower_function(&self) -> Result<(), rusqlite::Error> {
+        self.conn().create_scalar_function(
+            "journal_lower",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            |ctx| {
+                Ok(match ctx.get::<Value>(0)? {
+                    Value::Text(text) => Value::Text(text.to_lowercase()),
+                    value => value,
+                })
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Tag;
+
+    fn text(t: &str) -> Query {
+        Query::Text(t.to_string())
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Query::parse("  ").unwrap(), Query::And(vec![]));
+        assert_eq!(
+            Query::parse("tag:work -tag:draft after:2024-01-01 \"exact phrase\" title:retro").unwrap(),
+            Query::And(vec![
+                Query::Tag("work".to_string()),
+                Query::Not(Box::new(Query::Tag("draft".to_string()))),
+                Query::After(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()),
+                text("exact phrase"),
+                Query::Title("retro".to_string()),
+            ])
+        );
+        // OR binds weaker than AND, NOT applies to the group
+        assert_eq!(
+            Query::parse("a b OR c AND NOT (d OR title:\"e f\")").unwrap(),
+            Query::Or(vec![
+                Query::And(vec![text("a"), text("b")]),
+                Query::And(vec![
+                    text("c"),
+                    Query::Not(Box::new(Query::Or(vec![text("d"), Query::Title("e f".to_string())]))),
+                ]),
+            ])
+        );
+        assert_eq!(Query::parse("a - b").unwrap(), Query::And(vec![text("a"), text("-"), text("b")]));
+
+        let now = DateTime::parse_from_rfc3339("2024-03-10T08:00:00+01:00").unwrap();
+        assert_eq!(
+            Query::parse_at("after:\"7 days ago\" before:today", now).unwrap(),
+            Query::And(vec![
+                Query::After(NaiveDate::from_ymd_opt(2024, 3, 3).unwrap()),
+                Query::Before(NaiveDate::from_ymd_opt(2024, 3, 10).unwrap()),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |input: &str| Query::parse(input).unwrap_err();
+        assert_eq!(error("a \"open"), QueryError::new(2, "unclosed quote"));
+        assert_eq!(error("(a OR b"), QueryError::new(0, "missing closing parenthesis"));
+        assert_eq!(error("a b)"), QueryError::new(3, "unmatched closing parenthesis"));
+        assert_eq!(error("a OR"), QueryError::new(4, "expected a search term"));
+        assert_eq!(error("OR a"), QueryError::new(0, "expected a search term before AND/OR"));
+        let bad_date = "expected a date like 2024-01-01 or \"3 days ago\"";
+        assert_eq!(error("a after:soon"), QueryError::new(8, bad_date));
+        // Too far back for chrono, which used to panic while typing in the search box
+        assert_eq!(error("after:\"99999999999 days ago\""), QueryError::new(6, bad_date));
+        assert_eq!(error("x tag:"), QueryError::new(6, "tag: needs a value"));
+        assert_eq!(error("x body:y").position, 2);
+        assert_eq!(error("is:done"), QueryError::new(3, "unknown flag done, use pinned, starred or archived"));
+        assert_eq!(error("x:").pointer("x:"), "x:\n  ^ x: needs a value");
+        assert_eq!(error("a after:soon").to_string(), format!("{} at position 9", bad_date));
+    }
+
+    fn ids(db: &Db, query: &str) -> Vec<u32> {
+        let query = Query::parse(query).unwrap();
+        let found: Vec<u32> = db.search(&query).unwrap().iter().map(|e| e.id).collect();
+        // Stores without SQL have to agree
+        let matching: Vec<u32> = db.get_entries().iter().filter(|e| query.matches(e)).map(|e| e.id).collect();
+        assert_eq!(found, matching);
+        found
+    }
+
+    fn add(db: &Db, title: &str, content: &str, tags: &[&str], created: &str) -> u32 {
+        let tags = tags.iter().map(|t| Tag::new(t.to_string())).collect();
+        let mut entry = Entry::new(title.to_string(), content.to_string(), Some(tags))
+            .with_created_time(DateTime::parse_from_rfc3339(created).unwrap());
+        db.create_entry(&mut entry).unwrap();
+        entry.id
+    }
+
+    fn search(db: &Db) {
+        let retro = add(db, "Sprint retro", "What went well: 100% of the tests", &["work"], "2024-01-05T23:30:00-05:00");
+        let draft = add(db, "Plans", "Exact phrase here", &["work", "draft"], "2023-12-31T12:00:00+00:00");
+        let home = add(db, "Garden", "exact  phrase, not quite", &["home"], "2024-01-01T00:30:00+01:00");
+
+        assert_eq!(ids(db, ""), vec![retro, draft, home]);
+        assert_eq!(ids(db, "tag:WORK -tag:draft"), vec![retro]);
+        assert_eq!(ids(db, "\"exact phrase\""), vec![draft]);
+        assert_eq!(ids(db, "title:retro OR garden"), vec![retro, home]);
+        assert_eq!(ids(db, "NOT (tag:work OR tag:home)"), Vec::<u32>::new());
+        // Dates are the days the entries were written on, where they were written
+        assert_eq!(ids(db, "after:2024-01-01"), vec![retro, home]);
+        assert_eq!(ids(db, "before:2024-01-01"), vec![draft]);
+        assert_eq!(ids(db, "after:2024-01-02 before:2024-01-06"), vec![retro]);
+        // LIKE wildcards are taken literally
+        assert_eq!(ids(db, "100%"), vec![retro]);
+        assert_eq!(ids(db, "1_0"), Vec::<u32>::new());
+
+        // Case is ignored beyond ASCII, in SQL as well as in `matches`
+        let school = add(db, "École", "", &["Ünterwegs"], "2024-01-07T12:00:00+00:00");
+        assert_eq!(ids(db, "title:ÉCOLE"), vec![school]);
+        assert_eq!(ids(db, "title:éCOLE"), vec![school]);
+        assert_eq!(ids(db, "tag:ünterwegs"), vec![school]);
+    }
+
+    #[test]
+    fn search_entries() {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        search(&db);
+
+        // Tags without a name, which the schema allows, don't fail the search
+        db.conn().execute_batch("INSERT INTO tags (tag) VALUES (NULL);
+            INSERT INTO entry_tags (entry_id, tag_id) VALUES (1, last_insert_rowid());").unwrap();
+        assert!(db.search(&Query::parse("tag:nothing").unwrap()).unwrap().is_empty());
+        let found = db.search(&Query::parse("-tag:home tag:work").unwrap()).unwrap();
+        assert_eq!(found.iter().map(|e| e.id).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn search_encrypted_entries() {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        db.set_passphrase(Some("secret")).unwrap();
+        search(&db);
+    }
+}
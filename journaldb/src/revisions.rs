@@ -0,0 +1,165 @@
+use rusqlite::OptionalExtension;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{Db, Entry, Tag};
+
+/// A random id for a new entry
+pub(crate) fn new_uuid() -> String {
+    Uuid::new_v4().to_string()
+}
+
+/// Id of an entry from before UUIDs, derived from its id and creation time. Copies of the journal
+/// made back then derive the same one, so merging them matches their entries.
+fn derived_uuid(entry_id: u32, created_time: i64) -> String {
+    let hash = Sha256::digest(format!("{}:{}", entry_id, created_time));
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    Uuid::new_v8(bytes).to_string()
+}
+
+/// Whether two versions of an entry have the same title, content, tags and updated time
+pub(crate) fn same_version(a: &Entry, b: &Entry) -> bool {
+    let tags = |e: &Entry| {
+        let mut names: Vec<String> = e.tags.iter().flatten().map(Tag::get_tag).collect();
+        names.sort();
+        names.dedup();
+        names
+    };
+    a.updated_time == b.updated_time && a.title == b.title && a.content == b.content && tags(a) == tags(b)
+}
+
+impl Db {
+    /// Id of an entry that stays the same in every copy of the journal
+    pub fn get_uuid(&self, entry_id: u32) -> Result<String, rusqlite::Error> {
+        self.conn()
+            .query_row("SELECT entry_uuid FROM entries WHERE entry_id = ?1", (entry_id,), |r| r.get(0))
+    }
+
+    pub fn find_uuid(&self, uuid: &str) -> Result<Option<u32>, rusqlite::Error> {
+        self.conn()
+            .query_row("SELECT entry_id FROM entries WHERE entry_uuid = ?1", (uuid,), |r| r.get(0))
+            .optional()
+    }
+
+    pub(crate) fn set_uuid(&self, entry_id: u32, uuid: &str) -> Result<(), rusqlite::Error> {
+        self.conn().execute("UPDATE entries SET entry_uuid = ?1 WHERE entry_id = ?2", (uuid, entry_id))?;
+        Ok(())
+    }
+
+    /// Gives entries written before UUIDs, or by an older journalcli, their id
+    pub(crate) fn assign_uuids(&self) -> Result<(), rusqlite::Error> {
+        let conn = self.conn();
+        let missing: Vec<(u32, i64)> = conn
+            .prepare("SELECT entry_id, entry_created_time FROM entries WHERE entry_uuid IS NULL")?
+            .query_map((), |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        for (entry_id, created_time) in missing {
+            self.set_uuid(entry_id, &derived_uuid(entry_id, created_time))?;
+        }
+        Ok(())
+    }
+
+    /// Earlier versions of an entry, oldest first. Their updated time is when they were written.
+    pub fn get_revisions(&self, entry_id: u32) -> Result<Vec<Entry>, rusqlite::Error> {
+        let Some(entry) = self.get_entry_by_id(entry_id) else {
+            return Ok(Vec::new());
+        };
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT title, content, tags, updated_time, updated_offset FROM entry_revisions
+            WHERE entry_id = ?1 ORDER BY updated_time, revision_id",
+        )?;
+        let revisions = stmt.query_map((entry_id,), |r| self.revision(r, &entry))?.collect();
+        revisions
+    }
+
+    /// A row of `entry_revisions`, with the fields it doesn't store taken from `entry`
+    fn revision(&self, r: &rusqlite::Row, entry: &Entry) -> Result<Entry, rusqlite::Error> {
+        let tags: String = r.get(2)?;
+        let tags: Vec<Tag> = tags.split(',').filter(|t| !t.is_empty()).map(|t| Tag::new(t.to_string())).collect();
+        Ok(Entry {
+            title: self.open_text(r.get(0)?)?,
+            content: self.open_text(r.get(1)?)?,
+            tags: Some(tags).filter(|t| !t.is_empty()),
+            updated_time: r.get(3)?,
+            updated_offset: r.get(4)?,
+            ..entry.clone()
+        })
+    }
+
+    /// Keeps `version` in the history of the entry, unless it is the last one added already.
+    /// Returns whether it was added.
+    pub(crate) fn save_revision(&self, entry_id: u32, version: &Entry) -> Result<bool, rusqlite::Error> {
+        let latest = self
+            .conn()
+            .query_row(
+                "SELECT title, content, tags, updated_time, updated_offset FROM entry_revisions
+                WHERE entry_id = ?1 ORDER BY revision_id DESC LIMIT 1",
+                (entry_id,),
+                |r| self.revision(r, version),
+            )
+            .optional()?;
+        if latest.is_some_and(|latest| same_version(&latest, version)) {
+            return Ok(false);
+        }
+        let tags: Vec<String> = version.tags.iter().flatten().map(Tag::get_tag).collect();
+        self.conn().execute(
+            "INSERT INTO entry_revisions (entry_id, title, content, tags, updated_time, updated_offset)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            (
+                entry_id,
+                self.seal_text(&version.title),
+                self.seal_text(&version.content),
+                tags.join(","),
+                version.updated_time,
+                version.updated_offset,
+            ),
+        )?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edits_keep_history() {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        let mut entry = Entry::new("Title".to_string(), "First".to_string(), Some(vec![Tag::new("a".to_string())]))
+            .with_updated_time(chrono::DateTime::parse_from_rfc3339("2024-01-01T10:00:00+01:00").unwrap());
+        db.create_entry(&mut entry).unwrap();
+        let uuid = db.get_uuid(entry.id).unwrap();
+        assert_eq!(Uuid::parse_str(&uuid).unwrap().get_version_num(), 4);
+        assert_eq!(db.find_uuid(&uuid).unwrap(), Some(entry.id));
+
+        let original = db.get_entry_by_id(entry.id).unwrap();
+        entry.set_content("Second".to_string());
+        db.edit_entry(&mut entry).unwrap();
+        db.edit_entry(&mut entry).unwrap();
+        entry.set_tags(None);
+        db.edit_entry(&mut entry).unwrap();
+        let revisions = db.get_revisions(entry.id).unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert!(same_version(&revisions[0], &original));
+        assert_eq!((revisions[1].get_content(), revisions[1].get_tags()), ("Second".to_string(), original.get_tags()));
+
+        db.delete_entry(&entry).unwrap();
+        let count: u32 = db.conn().query_row("SELECT count(*) FROM entry_revisions", (), |r| r.get(0)).unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn old_entries_get_the_same_uuid_everywhere() {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        db.create_entry(&mut Entry::new("Old".to_string(), String::new(), None)).unwrap();
+        db.conn().execute("UPDATE entries SET entry_uuid = NULL", ()).unwrap();
+        db.initialize_db().unwrap();
+        let entry = &db.get_entries()[0];
+        assert_eq!(db.get_uuid(entry.id).unwrap(), derived_uuid(entry.id, entry.created_time));
+        assert_ne!(derived_uuid(entry.id, entry.created_time), derived_uuid(entry.id + 1, entry.created_time));
+    }
+}
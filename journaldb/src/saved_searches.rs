@@ -0,0 +1,120 @@
+use std::error::Error;
+
+use rusqlite::OptionalExtension;
+
+use crate::{query::Query, Db, Entry};
+
+/// A named query, like "open retros" for `tag:retro -tag:done`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SavedSearch {
+    pub name: String,
+    /// Query text, parsed again on each run so relative dates stay current
+    pub query: String,
+}
+
+/// Number of entries matching a saved search, or why its query can't run
+pub type SearchCount = Result<usize, String>;
+
+impl SavedSearch {
+    pub fn parse(&self) -> Result<Query, Box<dyn Error>> {
+        Ok(Query::parse(&self.query)?)
+    }
+}
+
+impl Db {
+    /// Adds a saved search or replaces the one with the same name. Fails for queries that don't parse.
+    pub fn save_search(&self, name: &str, query: &str) -> Result<SavedSearch, Box<dyn Error>> {
+        Query::parse(query)?;
+        self.conn().execute(
+            "INSERT OR REPLACE INTO saved_searches (name, query) VALUES (?1, ?2)",
+            (name, query),
+        )?;
+        Ok(SavedSearch {
+            name: name.to_string(),
+            query: query.to_string(),
+        })
+    }
+
+    pub fn get_saved_search(&self, name: &str) -> Result<Option<SavedSearch>, rusqlite::Error> {
+        self.conn()
+            .query_row("SELECT name, query FROM saved_searches WHERE name = ?1", (name,), |r| {
+                Ok(SavedSearch { name: r.get(0)?, query: r.get(1)? })
+            })
+            .optional()
+    }
+
+    /// All saved searches ordered by name
+    pub fn get_saved_searches(&self) -> Result<Vec<SavedSearch>, rusqlite::Error> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT name, query FROM saved_searches ORDER BY name")?;
+        let searches = stmt
+            .query_map((), |r| Ok(SavedSearch { name: r.get(0)?, query: r.get(1)? }))?
+            .collect();
+        searches
+    }
+
+    /// All saved searches with the number of entries matching them, or why their query can't run.
+    /// A query saved by an older version that no longer parses doesn't hide the others.
+    pub fn count_saved_searches(&self) -> Result<Vec<(SavedSearch, SearchCount)>, rusqlite::Error> {
+        let searches = self.get_saved_searches()?;
+        Ok(searches
+            .into_iter()
+            .map(|search| {
+                let count = search.parse().and_then(|query| Ok(self.search(&query)?.len()));
+                (search, count.map_err(|e| e.to_string()))
+            })
+            .collect())
+    }
+
+    /// Returns whether the saved search existed
+    pub fn remove_saved_search(&self, name: &str) -> Result<bool, rusqlite::Error> {
+        Ok(self.conn().execute("DELETE FROM saved_searches WHERE name = ?1", (name,))? > 0)
+    }
+
+    /// Entries matching the saved search with that name
+    pub fn run_saved_search(&self, name: &str) -> Result<Vec<Entry>, Box<dyn Error>> {
+        let search = self
+            .get_saved_search(name)?
+            .ok_or_else(|| format!("no saved search {}", name))?;
+        Ok(self.search(&search.parse()?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_run() {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        let mut retro = Entry::new("Retro".to_string(), "".to_string(), Some(vec!["retro".to_string().into()]));
+        db.create_entry(&mut retro).unwrap();
+        let tags = vec!["retro".to_string().into(), "done".to_string().into()];
+        let mut done = Entry::new("Old retro".to_string(), "".to_string(), Some(tags));
+        db.create_entry(&mut done).unwrap();
+
+        assert!(db.save_search("broken", "tag:retro (").is_err());
+        db.save_search("open retros", "tag:retro").unwrap();
+        db.save_search("open retros", "tag:retro -tag:done").unwrap();
+        db.save_search("recent", "after:\"7 days ago\"").unwrap();
+        let names: Vec<String> = db.get_saved_searches().unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["open retros", "recent"]);
+
+        let ids = |name: &str| -> Vec<u32> { db.run_saved_search(name).unwrap().iter().map(|e| e.id).collect() };
+        assert_eq!(ids("open retros"), vec![retro.id]);
+        assert_eq!(ids("recent"), vec![retro.id, done.id]);
+        assert!(db.run_saved_search("missing").is_err());
+
+        assert!(db.remove_saved_search("recent").unwrap());
+        assert!(!db.remove_saved_search("recent").unwrap());
+        assert_eq!(db.get_saved_search("recent").unwrap(), None);
+
+        // Queries that stopped parsing are reported next to the others
+        db.conn().execute("INSERT INTO saved_searches (name, query) VALUES ('broken', 'tag:')", ()).unwrap();
+        let counts = db.count_saved_searches().unwrap();
+        assert_eq!(counts[0].0.name, "broken");
+        assert!(counts[0].1.as_ref().unwrap_err().contains("tag: needs a value"));
+        assert_eq!(counts[1].1, Ok(1));
+    }
+}
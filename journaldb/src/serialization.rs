@@ -0,0 +1,97 @@
+use chrono::{DateTime, FixedOffset, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::{Entry, Tag};
+
+/// Shape of a serialized `Entry`. Missing fields are read as empty, except `created` which
+/// defaults to now and `updated` which defaults to `created`.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct EntryRecord {
+    #[serde(default)]
+    id: u32,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    tags: Vec<Tag>,
+    created: Option<DateTime<FixedOffset>>,
+    updated: Option<DateTime<FixedOffset>>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pinned: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    starred: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    archived: bool,
+}
+
+fn is_false(flag: &bool) -> bool {
+    !flag
+}
+
+impl From<Entry> for EntryRecord {
+    fn from(entry: Entry) -> Self {
+        EntryRecord {
+            id: entry.id,
+            created: Some(entry.get_created_time()),
+            updated: Some(entry.get_updated_time()),
+            title: entry.title,
+            content: entry.content,
+            tags: entry.tags.unwrap_or_default(),
+            pinned: entry.pinned,
+            starred: entry.starred,
+            archived: entry.archived,
+        }
+    }
+}
+
+impl From<EntryRecord> for Entry {
+    fn from(record: EntryRecord) -> Self {
+        let created = record.created.unwrap_or_else(|| Local::now().fixed_offset());
+        let tags = Some(record.tags).filter(|t| !t.is_empty());
+        let mut entry = Entry::new(record.title, record.content, tags)
+            .with_created_time(created)
+            .with_updated_time(record.updated.unwrap_or(created));
+        entry.id = record.id;
+        entry.pinned = record.pinned;
+        entry.starred = record.starred;
+        entry.archived = record.archived;
+        entry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn round_trip() {
+        let created = DateTime::parse_from_rfc3339("2024-03-01T12:00:00+01:00").unwrap();
+        let entry = Entry::new("Rome".to_string(), "Pasta".to_string(), Some(vec!["travel".to_string().into()]))
+            .with_created_time(created)
+            .with_updated_time(created);
+        let value = serde_json::to_value(&entry).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "id": 0,
+                "title": "Rome",
+                "content": "Pasta",
+                "tags": ["travel"],
+                "created": "2024-03-01T12:00:00+01:00",
+                "updated": "2024-03-01T12:00:00+01:00",
+            })
+        );
+        assert_eq!(serde_json::from_value::<Entry>(value).unwrap(), entry);
+
+        let starred: Entry = serde_json::from_value(json!({ "title": "a", "starred": true })).unwrap();
+        assert!(starred.starred && !starred.pinned);
+        assert_eq!(serde_json::to_value(&starred).unwrap()["starred"], json!(true));
+
+        let minimal: Entry = serde_json::from_value(json!({ "title": "a", "created": "2024-03-01T12:00:00+01:00" })).unwrap();
+        assert_eq!((minimal.get_content(), minimal.get_tags()), (String::new(), None));
+        assert_eq!(minimal.get_updated_time(), created);
+        assert!(serde_json::from_value::<Entry>(json!({ "title": "a", "content": "b", "created": "yesterday" })).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::{Datelike, Duration, NaiveDate};
+
+use crate::{time::TimeFormat, Db, Entry};
+
+/// Writing statistics over a set of entries
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub total_entries: usize,
+    pub total_words: usize,
+    /// Words of each entry, oldest entry first
+    pub words_per_entry: Vec<u64>,
+    /// Consecutive days with at least one entry, ending today or yesterday
+    pub current_streak: u32,
+    pub longest_streak: u32,
+    /// Entries written on each weekday, Monday first
+    pub entries_per_weekday: [u64; 7],
+    /// Entries written in each month of the year, January first
+    pub entries_per_month: [u64; 12],
+    /// Most used tags with their number of entries, most used first
+    pub top_tags: Vec<(String, usize)>,
+}
+
+impl Stats {
+    /// Computes the statistics of `entries`, with streaks counted up to `today`. Entries count for
+    /// the day they were written on in the display zone of `time_format`, like calendars show them.
+    pub fn compute(entries: &[Entry], time_format: &TimeFormat, today: NaiveDate, top_tags: usize) -> Self {
+        let mut stats = Stats {
+            total_entries: entries.len(),
+            ..Default::default()
+        };
+
+        let mut sorted: Vec<&Entry> = entries.iter().collect();
+        sorted.sort_by_key(|e| (e.created_time, e.id));
+        let mut days = BTreeSet::new();
+        let mut tags: HashMap<String, usize> = HashMap::new();
+        for entry in sorted {
+            let words = word_count(&entry.content);
+            stats.total_words += words;
+            stats.words_per_entry.push(words as u64);
+
+            let date = time_format.date(&entry.get_created_time());
+            days.insert(date);
+            stats.entries_per_weekday[date.weekday().num_days_from_monday() as usize] += 1;
+            stats.entries_per_month[date.month0() as usize] += 1;
+
+            for tag in entry.tags.iter().flatten() {
+                *tags.entry(tag.tag.clone()).or_insert(0) += 1;
+            }
+        }
+
+        let (current, longest) = streaks(&days, today);
+        stats.current_streak = current;
+        stats.longest_streak = longest;
+
+        let mut tags: Vec<(String, usize)> = tags.into_iter().collect();
+        tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        tags.truncate(top_tags);
+        stats.top_tags = tags;
+        stats
+    }
+
+    pub fn average_words(&self) -> f64 {
+        match self.total_entries {
+            0 => 0.0,
+            n => self.total_words as f64 / n as f64,
+        }
+    }
+}
+
+pub fn word_count(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+/// Current and longest run of consecutive days in `days`.
+/// The current streak survives until a full day passes without an entry.
+fn streaks(days: &BTreeSet<NaiveDate>, today: NaiveDate) -> (u32, u32) {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for day in days.iter().copied().filter(|d| *d <= today) {
+        run = match previous {
+            Some(p) if day - p == Duration::days(1) => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        previous = Some(day);
+    }
+    let current = match previous {
+        Some(last) if today - last <= Duration::days(1) => run,
+        _ => 0,
+    };
+    (current, longest)
+}
+
+impl Db {
+    /// Statistics over every entry in the configured display zone, with streaks counted up to today
+    /// there
+    pub fn stats(&self, top_tags: usize) -> Result<Stats, rusqlite::Error> {
+        let time_format = self.time_format()?;
+        let today = time_format.date(&chrono::Local::now().fixed_offset());
+        Ok(Stats::compute(&self.entries.read(), &time_format, today, top_tags))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::DisplayZone;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, 3, d).unwrap()
+    }
+
+    #[test]
+    fn streaks_end_today_or_yesterday() {
+        let days: BTreeSet<NaiveDate> = [1, 2, 3, 4, 7, 8, 9].into_iter().map(day).collect();
+        assert_eq!(streaks(&days, day(9)), (3, 4));
+        assert_eq!(streaks(&days, day(10)), (3, 4));
+        assert_eq!(streaks(&days, day(11)), (0, 4));
+        // Days after `today` are not counted
+        assert_eq!(streaks(&days, day(3)), (3, 3));
+        assert_eq!(streaks(&BTreeSet::new(), day(3)), (0, 0));
+    }
+
+    #[test]
+    fn compute_stats() {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        let entries = [
+            ("2023-03-06 10:00:00", "one two three", vec!["work", "retro"]),
+            ("2023-03-07 10:00:00", "four five", vec!["work"]),
+            ("2023-04-03 10:00:00", "six", vec![]),
+        ];
+        for (created, content, tags) in entries {
+            let tags = Some(tags.into_iter().map(|t| crate::Tag::new(t.to_string())).collect());
+            let mut entry = Entry::new("title".to_string(), content.to_string(), tags);
+            db.create_entry(&mut entry).unwrap();
+            db.conn()
+                .execute(
+                    "UPDATE entries SET entry_created_time = strftime('%s', ?1), entry_created_offset = 0
+                    WHERE entry_id = ?2",
+                    (created, entry.id),
+                )
+                .unwrap();
+        }
+        db.update_entries().unwrap();
+
+        let stats = Stats::compute(&db.get_entries(), &TimeFormat::new(DisplayZone::Utc, "%F"), day(8), 1);
+        assert_eq!(stats.total_entries, 3);
+        assert_eq!(stats.total_words, 6);
+        assert_eq!(stats.average_words(), 2.0);
+        assert_eq!(stats.words_per_entry, vec![3, 2, 1]);
+        assert_eq!((stats.current_streak, stats.longest_streak), (2, 2));
+        // 2023-03-06 and 2023-04-03 were Mondays
+        assert_eq!(stats.entries_per_weekday, [2, 1, 0, 0, 0, 0, 0]);
+        assert_eq!(&stats.entries_per_month[..4], &[0, 0, 2, 1]);
+        assert_eq!(stats.top_tags, vec![("work".to_string(), 2)]);
+    }
+
+    #[test]
+    fn days_in_the_display_zone() {
+        // Tuesday evening in New York, Wednesday in UTC
+        let written = chrono::DateTime::parse_from_rfc3339("2023-03-07T23:30:00-05:00").unwrap();
+        let entries = [Entry::new("late".to_string(), String::new(), None).with_created_time(written)];
+
+        let original = Stats::compute(&entries, &TimeFormat::new(DisplayZone::Original, "%F"), day(8), 0);
+        assert_eq!(original.entries_per_weekday, [0, 1, 0, 0, 0, 0, 0]);
+        assert_eq!(original.current_streak, 1);
+
+        let utc = Stats::compute(&entries, &TimeFormat::new(DisplayZone::Utc, "%F"), day(9), 0);
+        assert_eq!(utc.entries_per_weekday, [0, 0, 1, 0, 0, 0, 0]);
+        // The streak only ended a day before in UTC
+        assert_eq!(utc.current_streak, 1);
+        assert_eq!(Stats::compute(&entries, &TimeFormat::new(DisplayZone::Original, "%F"), day(9), 0).current_streak, 0);
+    }
+}
@@ -0,0 +1,266 @@
+use std::{error::Error, path::Path};
+
+use chrono::Local;
+
+use crate::{flags::Flag, markdown::MarkdownStore, query::Query, Db, Entry};
+
+/// Where entries are kept. Besides the SQLite `Db` there are `MemoryStore` for tests and embedding
+/// and `MarkdownStore` for journals kept as plain files.
+///
+/// Attachments, encryption, links, templates, settings and saved searches need the SQLite journal,
+/// frontends reach it with `as_db`.
+pub trait JournalStore {
+    /// Reads the entries again, to pick up changes made by others
+    fn update_entries(&mut self) -> Result<(), Box<dyn Error>>;
+
+    /// Entries ordered by id
+    fn get_entries(&self) -> Vec<Entry>;
+
+    fn get_entry_by_id(&self, id: u32) -> Option<Entry> {
+        self.get_entries().into_iter().find(|e| e.id == id)
+    }
+
+    /// Stores a new entry and sets its id
+    fn create_entry(&mut self, entry: &mut Entry) -> Result<(), Box<dyn Error>>;
+
+    /// Saves the title, content and tags of `entry`.
+    /// The updated time only moves when one of them actually changed.
+    fn edit_entry(&mut self, entry: &mut Entry) -> Result<(), Box<dyn Error>>;
+
+    fn delete_entry(&mut self, entry: &Entry) -> Result<(), Box<dyn Error>>;
+
+    /// Sets or clears `flag` on a stored entry, without moving its updated time
+    fn set_flag(&mut self, entry_id: u32, flag: Flag, on: bool) -> Result<(), Box<dyn Error>>;
+
+    /// Tags used by any entry, sorted
+    fn tag_names(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self.get_entries().iter().flat_map(tag_names).collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    /// Entries matching `query`, in the order of `get_entries`
+    fn search(&self, query: &Query) -> Result<Vec<Entry>, Box<dyn Error>> {
+        Ok(self.get_entries().into_iter().filter(|e| query.matches(e)).collect())
+    }
+
+    /// The SQLite journal, if this is one
+    fn as_db(&self) -> Option<&Db> {
+        None
+    }
+
+    /// What the last `update_entries` had to leave out or couldn't save, e.g. unreadable files
+    fn warnings(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// Opens the journal at `path`: a directory of Markdown files when `path` is a directory or ends
+/// with a `/`, a SQLite database otherwise
+pub fn open(path: &str) -> Result<Box<dyn JournalStore>, Box<dyn Error>> {
+    if path.ends_with('/') || Path::new(path).is_dir() {
+        return Ok(Box::new(MarkdownStore::open(path)?));
+    }
+    let db = Db::new(path);
+    db.initialize_db()?;
+    Ok(Box::new(db))
+}
+
+/// Names of the tags of an entry, without duplicates
+pub(crate) fn tag_names(entry: &Entry) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for tag in entry.tags.iter().flatten() {
+        if !names.contains(&tag.tag) {
+            names.push(tag.tag.clone());
+        }
+    }
+    names
+}
+
+/// Whether saving `new` over `old` changes what `edit_entry` stores
+pub(crate) fn entry_changed(old: &Entry, new: &Entry) -> bool {
+    old.title != new.title || old.content != new.content || tag_names(old) != tag_names(new)
+}
+
+pub(crate) fn no_such_entry(id: u32) -> Box<dyn Error> {
+    format!("no entry with id {}", id).into()
+}
+
+impl JournalStore for Db {
+    fn update_entries(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(Db::update_entries(self)?)
+    }
+
+    fn get_entries(&self) -> Vec<Entry> {
+        Db::get_entries(self)
+    }
+
+    fn get_entry_by_id(&self, id: u32) -> Option<Entry> {
+        Db::get_entry_by_id(self, id)
+    }
+
+    fn create_entry(&mut self, entry: &mut Entry) -> Result<(), Box<dyn Error>> {
+        Ok(Db::create_entry(self, entry)?)
+    }
+
+    fn edit_entry(&mut self, entry: &mut Entry) -> Result<(), Box<dyn Error>> {
+        Ok(Db::edit_entry(self, entry)?)
+    }
+
+    fn delete_entry(&mut self, entry: &Entry) -> Result<(), Box<dyn Error>> {
+        Ok(Db::delete_entry(self, entry)?)
+    }
+
+    fn set_flag(&mut self, entry_id: u32, flag: Flag, on: bool) -> Result<(), Box<dyn Error>> {
+        match Db::set_flag(self, entry_id, flag, on) {
+            Err(rusqlite::Error::QueryReturnedNoRows) => Err(no_such_entry(entry_id)),
+            result => Ok(result?),
+        }
+    }
+
+    fn search(&self, query: &Query) -> Result<Vec<Entry>, Box<dyn Error>> {
+        Ok(Db::search(self, query)?)
+    }
+
+    fn as_db(&self) -> Option<&Db> {
+        Some(self)
+    }
+}
+
+/// Entries kept in memory only
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    entries: Vec<Entry>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl JournalStore for MemoryStore {
+    fn update_entries(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn get_entries(&self) -> Vec<Entry> {
+        self.entries.clone()
+    }
+
+    fn create_entry(&mut self, entry: &mut Entry) -> Result<(), Box<dyn Error>> {
+        entry.id = self.entries.iter().map(|e| e.id).max().unwrap_or(0) + 1;
+        self.entries.push(entry.clone());
+        Ok(())
+    }
+
+    fn edit_entry(&mut self, entry: &mut Entry) -> Result<(), Box<dyn Error>> {
+        let stored = self
+            .entries
+            .iter_mut()
+            .find(|e| e.id == entry.id)
+            .ok_or_else(|| no_such_entry(entry.id))?;
+        if entry_changed(stored, entry) {
+            entry.set_updated_time(Local::now().fixed_offset());
+            stored.title = entry.title.clone();
+            stored.content = entry.content.clone();
+            stored.tags = entry.tags.clone();
+            stored.updated_time = entry.updated_time;
+            stored.updated_offset = entry.updated_offset;
+        }
+        Ok(())
+    }
+
+    fn delete_entry(&mut self, entry: &Entry) -> Result<(), Box<dyn Error>> {
+        self.entries.retain(|e| e.id != entry.id);
+        Ok(())
+    }
+
+    fn set_flag(&mut self, entry_id: u32, flag: Flag, on: bool) -> Result<(), Box<dyn Error>> {
+        let stored = self.entries.iter_mut().find(|e| e.id == entry_id).ok_or_else(|| no_such_entry(entry_id))?;
+        stored.set_flag(flag, on);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::Tag;
+    use chrono::DateTime;
+
+    fn tags(names: &[&str]) -> Option<Vec<Tag>> {
+        Some(names.iter().map(|t| Tag::new(t.to_string())).collect())
+    }
+
+    /// Behavior every store has to share
+    pub(crate) fn check_store(store: &mut dyn JournalStore) {
+        let created = DateTime::parse_from_rfc3339("2024-01-05T21:00:00-05:00").unwrap();
+        let mut first = Entry::new("First".to_string(), "Some: text\n---\nmore".to_string(), tags(&["work", "b"]))
+            .with_created_time(created)
+            .with_updated_time(created);
+        store.create_entry(&mut first).unwrap();
+        let mut second = Entry::new("Second".to_string(), String::new(), None);
+        store.create_entry(&mut second).unwrap();
+        assert_ne!(first.id, second.id);
+        store.update_entries().unwrap();
+
+        let stored = store.get_entry_by_id(first.id).unwrap();
+        assert_eq!(stored.title, "First");
+        assert_eq!(stored.content, "Some: text\n---\nmore");
+        assert_eq!(stored.get_created_time(), created);
+        assert_eq!(tag_names(&stored), vec!["work", "b"]);
+        assert_eq!(store.tag_names(), vec!["b", "work"]);
+        assert_eq!(store.get_entries().iter().map(|e| e.id).collect::<Vec<_>>(), vec![first.id, second.id]);
+
+        // Saving without changes keeps the updated time
+        let mut unchanged = stored.clone();
+        store.edit_entry(&mut unchanged).unwrap();
+        assert_eq!(store.get_entry_by_id(first.id).unwrap().get_updated_time(), created);
+        let mut edited = stored;
+        edited.set_content("Changed".to_string());
+        edited.set_tags(tags(&["home"]));
+        store.edit_entry(&mut edited).unwrap();
+        store.update_entries().unwrap();
+        let stored = store.get_entry_by_id(first.id).unwrap();
+        assert_eq!((stored.content.as_str(), tag_names(&stored)), ("Changed", vec!["home".to_string()]));
+        assert!(stored.get_updated_time() > created);
+
+        let found = store.search(&Query::parse("tag:home OR second").unwrap()).unwrap();
+        assert_eq!(found.iter().map(|e| e.id).collect::<Vec<_>>(), vec![first.id, second.id]);
+
+        // Flags are kept without moving the updated time
+        let updated = stored.get_updated_time();
+        store.set_flag(first.id, Flag::Pinned, true).unwrap();
+        store.set_flag(first.id, Flag::Archived, true).unwrap();
+        store.set_flag(first.id, Flag::Archived, false).unwrap();
+        assert!(store.set_flag(second.id + 100, Flag::Starred, true).is_err());
+        store.update_entries().unwrap();
+        let stored = store.get_entry_by_id(first.id).unwrap();
+        assert_eq!((stored.markers(), stored.get_updated_time()), ("^".to_string(), updated));
+        let found = store.search(&Query::parse("is:pinned").unwrap()).unwrap();
+        assert_eq!(found.iter().map(|e| e.id).collect::<Vec<_>>(), vec![first.id]);
+
+        store.delete_entry(&second).unwrap();
+        store.update_entries().unwrap();
+        assert_eq!(store.get_entries().len(), 1);
+        assert!(store.get_entry_by_id(second.id).is_none());
+    }
+
+    #[test]
+    fn sqlite_store() {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        let mut store: Box<dyn JournalStore> = Box::new(db);
+        check_store(store.as_mut());
+        assert!(store.as_db().is_some());
+    }
+
+    #[test]
+    fn memory_store() {
+        let mut store = MemoryStore::new();
+        check_store(&mut store);
+        assert!(store.as_db().is_none());
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use rusqlite::OptionalExtension;
+
+use crate::{Db, Entry, Tag};
+
+/// Named skeleton of an entry. Title, content and tags may contain variables:
+/// `{{date}}`, `{{weekday}}`, `{{week}}` and `{{prompt:Question}}`, answered when the template is used.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    pub name: String,
+    pub title: String,
+    pub content: String,
+    pub tags: Vec<String>,
+}
+
+impl Template {
+    /// Questions of the `{{prompt:...}}` variables, in order of appearance and without duplicates
+    pub fn prompts(&self) -> Vec<String> {
+        let mut prompts: Vec<String> = Vec::new();
+        let texts = [&self.title, &self.content].into_iter().chain(&self.tags);
+        for text in texts {
+            for variable in variables(text) {
+                if let Some(question) = variable.strip_prefix("prompt:") {
+                    let question = question.trim();
+                    if !prompts.iter().any(|p| p == question) {
+                        prompts.push(question.to_string());
+                    }
+                }
+            }
+        }
+        prompts
+    }
+
+    /// New entry with the variables filled in. `answers` maps the questions of `prompts` to the replies,
+    /// unanswered questions become empty.
+    pub fn render(&self, date: NaiveDate, answers: &HashMap<String, String>) -> Entry {
+        let fill = |text: &str| fill_variables(text, date, answers);
+        let tags: Vec<Tag> = self
+            .tags
+            .iter()
+            .map(|t| fill(t).trim().to_string())
+            .filter(|t| !t.is_empty())
+            .map(Tag::new)
+            .collect();
+        Entry::new(fill(&self.title), fill(&self.content), Some(tags).filter(|t| !t.is_empty()))
+    }
+}
+
+/// Names between `{{` and `}}` in `text`
+fn variables(text: &str) -> impl Iterator<Item = &str> {
+    text.split("{{").skip(1).filter_map(|part| part.split_once("}}").map(|(name, _)| name.trim()))
+}
+
+fn fill_variables(text: &str, date: NaiveDate, answers: &HashMap<String, String>) -> String {
+    let mut filled = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}").map(|end| start + end) else {
+            break;
+        };
+        let name = rest[start + 2..end].trim();
+        let value = match name {
+            "date" => Some(date.format("%Y-%m-%d").to_string()),
+            "weekday" => Some(date.format("%A").to_string()),
+            "week" => Some(date.format("%V").to_string()),
+            _ => name
+                .strip_prefix("prompt:")
+                .map(|q| answers.get(q.trim()).cloned().unwrap_or_default()),
+        };
+        filled.push_str(&rest[..start]);
+        match value {
+            Some(value) => filled.push_str(&value),
+            // Unknown variables are kept as written
+            None => filled.push_str(&rest[start..end + 2]),
+        }
+        rest = &rest[end + 2..];
+    }
+    filled.push_str(rest);
+    filled
+}
+
+impl Db {
+    /// Adds a template or replaces the one with the same name
+    pub fn save_template(&self, template: &Template) -> Result<(), rusqlite::Error> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO templates (name, title, content, tags) VALUES (?1, ?2, ?3, ?4)",
+            (&template.name, &template.title, &template.content, template.tags.join(",")),
+        )?;
+        Ok(())
+    }
+
+    pub fn get_template(&self, name: &str) -> Result<Option<Template>, rusqlite::Error> {
+        self.conn()
+            .query_row(
+                "SELECT name, title, content, tags FROM templates WHERE name = ?1",
+                (name,),
+                template_from_row,
+            )
+            .optional()
+    }
+
+    /// All templates ordered by name
+    pub fn get_templates(&self) -> Result<Vec<Template>, rusqlite::Error> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT name, title, content, tags FROM templates ORDER BY name")?;
+        let templates = stmt.query_map((), template_from_row)?.collect();
+        templates
+    }
+
+    /// Returns whether the template existed
+    pub fn remove_template(&self, name: &str) -> Result<bool, rusqlite::Error> {
+        Ok(self.conn().execute("DELETE FROM templates WHERE name = ?1", (name,))? > 0)
+    }
+}
+
+fn template_from_row(r: &rusqlite::Row) -> Result<Template, rusqlite::Error> {
+    let tags: String = r.get(3)?;
+    Ok(Template {
+        name: r.get(0)?,
+        title: r.get(1)?,
+        content: r.get(2)?,
+        tags: tags.split(',').filter(|t| !t.is_empty()).map(str::to_string).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standup() -> Template {
+        Template {
+            name: "standup".to_string(),
+            title: "{{date}} Standup".to_string(),
+            content: "{{weekday}}, week {{week}}\nYesterday: {{prompt:What did you do?}}\n\
+                Blocked: {{ prompt: Anything blocking? }}\nAgain: {{prompt:What did you do?}} {{unknown}} {{open"
+                .to_string(),
+            tags: vec!["work".to_string(), "{{prompt:Project}}".to_string()],
+        }
+    }
+
+    #[test]
+    fn prompts_and_render() {
+        let template = standup();
+        assert_eq!(template.prompts(), vec!["What did you do?", "Anything blocking?", "Project"]);
+
+        let answers = HashMap::from([
+            ("What did you do?".to_string(), "Reviews".to_string()),
+            ("Project".to_string(), "journal".to_string()),
+        ]);
+        let entry = template.render(NaiveDate::from_ymd_opt(2023, 3, 1).unwrap(), &answers);
+        assert_eq!(entry.get_title(), "2023-03-01 Standup");
+        assert_eq!(
+            entry.get_content(),
+            "Wednesday, week 09\nYesterday: Reviews\nBlocked: \nAgain: Reviews {{unknown}} {{open"
+        );
+        let tags: Vec<String> = entry.get_tags().unwrap().iter().map(|t| t.get_tag()).collect();
+        assert_eq!(tags, vec!["work", "journal"]);
+    }
+
+    #[test]
+    fn store_templates() {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        let mut template = standup();
+        db.save_template(&template).unwrap();
+        template.tags.clear();
+        db.save_template(&template).unwrap();
+        let review = Template {
+            name: "review".to_string(),
+            title: "Week {{week}}".to_string(),
+            content: String::new(),
+            tags: vec!["review".to_string()],
+        };
+        db.save_template(&review).unwrap();
+
+        assert_eq!(db.get_templates().unwrap(), vec![review.clone(), template.clone()]);
+        assert_eq!(db.get_template("standup").unwrap(), Some(template));
+        assert!(db.remove_template("standup").unwrap());
+        assert!(!db.remove_template("standup").unwrap());
+        assert_eq!(db.get_templates().unwrap(), vec![review]);
+    }
+}
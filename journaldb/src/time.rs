@@ -0,0 +1,349 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Utc,
+    Weekday,
+};
+use chrono_tz::Tz;
+
+/// Settings key of the display time zone
+pub const ZONE_SETTING: &str = "time.zone";
+/// Settings key of the display format, a chrono `strftime` string
+pub const FORMAT_SETTING: &str = "time.format";
+
+pub const DEFAULT_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Time zone timestamps are shown in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum DisplayZone {
+    /// Zone of the machine showing the entry
+    #[default]
+    Local,
+    Utc,
+    /// Offset the entry was written in
+    Original,
+    Fixed(FixedOffset),
+    Named(Tz),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TimeError {
+    UnknownZone(String),
+    InvalidFormat(String),
+    InvalidDate(String),
+}
+
+impl fmt::Display for TimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeError::UnknownZone(zone) => write!(
+                f,
+                "unknown time zone '{}', expected local, utc, original, an offset like +02:00 or a name like Europe/Berlin",
+                zone
+            ),
+            TimeError::InvalidFormat(format) => write!(f, "invalid time format '{}'", format),
+            TimeError::InvalidDate(date) => write!(
+                f,
+                "can't read date '{}', expected e.g. 2023-03-01, 2023-03-01 21:00, yesterday 9pm or 3 days ago",
+                date
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TimeError {}
+
+impl FromStr for DisplayZone {
+    type Err = TimeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "local" => return Ok(DisplayZone::Local),
+            "utc" | "z" => return Ok(DisplayZone::Utc),
+            "original" => return Ok(DisplayZone::Original),
+            _ => {}
+        }
+        if let Some(offset) = parse_offset(s) {
+            return Ok(DisplayZone::Fixed(offset));
+        }
+        s.parse::<Tz>()
+            .map(DisplayZone::Named)
+            .map_err(|_| TimeError::UnknownZone(s.to_string()))
+    }
+}
+
+impl fmt::Display for DisplayZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisplayZone::Local => write!(f, "local"),
+            DisplayZone::Utc => write!(f, "utc"),
+            DisplayZone::Original => write!(f, "original"),
+            DisplayZone::Fixed(offset) => write!(f, "{}", offset),
+            DisplayZone::Named(tz) => write!(f, "{}", tz.name()),
+        }
+    }
+}
+
+/// Parses offsets written as `+02:00`, `-0530` or `+2`
+fn parse_offset(s: &str) -> Option<FixedOffset> {
+    let sign = match s.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits: String = s[1..].chars().filter(|c| *c != ':').collect();
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        1 | 2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (digits[..2].parse::<i32>().ok()?, digits[2..].parse::<i32>().ok()?),
+        _ => return None,
+    };
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// How the frontends present entry timestamps
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimeFormat {
+    pub zone: DisplayZone,
+    pub format: String,
+}
+
+impl Default for TimeFormat {
+    fn default() -> Self {
+        TimeFormat {
+            zone: DisplayZone::default(),
+            format: DEFAULT_FORMAT.to_string(),
+        }
+    }
+}
+
+impl TimeFormat {
+    pub fn new(zone: DisplayZone, format: &str) -> Self {
+        TimeFormat {
+            zone,
+            format: format.to_string(),
+        }
+    }
+
+    /// Builds a format from user input, rejecting zones and `strftime` strings chrono can't use
+    pub fn parse(zone: &str, format: &str) -> Result<Self, TimeError> {
+        check_format(format)?;
+        Ok(TimeFormat::new(zone.parse()?, format))
+    }
+
+    /// Moves `time` into the display zone
+    pub fn convert(&self, time: &DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        match self.zone {
+            DisplayZone::Local => time.with_timezone(&Local).fixed_offset(),
+            DisplayZone::Utc => time.with_timezone(&Utc).fixed_offset(),
+            DisplayZone::Original => *time,
+            DisplayZone::Fixed(offset) => time.with_timezone(&offset),
+            DisplayZone::Named(tz) => time.with_timezone(&tz).fixed_offset(),
+        }
+    }
+
+    pub fn format(&self, time: &DateTime<FixedOffset>) -> String {
+        self.convert(time).format(&self.format).to_string()
+    }
+
+    /// Calendar day of `time` in the display zone
+    pub fn date(&self, time: &DateTime<FixedOffset>) -> NaiveDate {
+        self.convert(time).date_naive()
+    }
+}
+
+/// Fails if chrono can't format times with `format`
+pub fn check_format(format: &str) -> Result<(), TimeError> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(TimeError::InvalidFormat(format.to_string()));
+    }
+    Ok(())
+}
+
+/// Reads a point in time typed by a user, relative to `now` and in its time zone.
+///
+/// Accepts RFC 3339 (`2023-03-01T21:00:00+01:00`), `2023-03-01 21:00`, `2023-03-01` and phrases
+/// made of a day (`now`, `today`, `yesterday`, `tomorrow`, `3 days ago`, `monday`, `last friday`)
+/// optionally followed by a time of day (`9pm`, `9:30am`, `21:00`, `noon`, `midnight`).
+/// Days without a time of day are placed at noon, so they stay on the same date when shown in
+/// nearby time zones.
+pub fn parse_datetime<Z: TimeZone>(input: &str, now: DateTime<Z>) -> Result<DateTime<FixedOffset>, TimeError> {
+    let invalid = || TimeError::InvalidDate(input.to_string());
+    let input = input.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+        return Ok(time);
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(input, format) {
+            return resolve(&now.timezone(), naive).ok_or_else(invalid);
+        }
+    }
+
+    let lower = input.to_lowercase();
+    let words: Vec<&str> = lower.split_whitespace().collect();
+    if words.is_empty() {
+        return Err(invalid());
+    }
+    if words == ["now"] {
+        return Ok(now.fixed_offset());
+    }
+    let today = now.date_naive();
+    // Days too far back or ahead for chrono are invalid rather than a panic
+    let days_back = |n: i64| TimeDelta::try_days(n).and_then(|d| today.checked_sub_signed(d)).ok_or_else(invalid);
+    let (date, rest) = match words.as_slice() {
+        [date, rest @ ..] if NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok() => {
+            (NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(), rest)
+        }
+        ["today", rest @ ..] => (today, rest),
+        ["yesterday", rest @ ..] => (days_back(1)?, rest),
+        ["tomorrow", rest @ ..] => (days_back(-1)?, rest),
+        [n, "day" | "days", "ago", rest @ ..] => {
+            let n: i64 = n.parse().map_err(|_| invalid())?;
+            (days_back(n)?, rest)
+        }
+        ["last", day, rest @ ..] => {
+            let weekday = parse_weekday(day).ok_or_else(invalid)?;
+            (last_weekday(today, weekday).ok_or_else(invalid)?, rest)
+        }
+        [day, rest @ ..] if parse_weekday(day).is_some() => {
+            (last_weekday(today, parse_weekday(day).unwrap()).ok_or_else(invalid)?, rest)
+        }
+        // A bare time of day means today
+        _ => (today, words.as_slice()),
+    };
+    let time = match rest {
+        [] => NaiveTime::from_hms_opt(12, 0, 0).unwrap(),
+        ["at", time] | [time] => parse_time_of_day(time).ok_or_else(invalid)?,
+        _ => return Err(invalid()),
+    };
+    resolve(&now.timezone(), date.and_time(time)).ok_or_else(invalid)
+}
+
+/// Places a wall clock time in `zone`, taking the earlier one when clocks are turned back
+fn resolve<Z: TimeZone>(zone: &Z, naive: NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+    zone.from_local_datetime(&naive).earliest().map(|t| t.fixed_offset())
+}
+
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    match s {
+        "mon" | "monday" => Some(Weekday::Mon),
+        "tue" | "tuesday" => Some(Weekday::Tue),
+        "wed" | "wednesday" => Some(Weekday::Wed),
+        "thu" | "thursday" => Some(Weekday::Thu),
+        "fri" | "friday" => Some(Weekday::Fri),
+        "sat" | "saturday" => Some(Weekday::Sat),
+        "sun" | "sunday" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+/// Most recent `weekday` before `today`, `None` before the earliest date chrono knows
+fn last_weekday(today: NaiveDate, weekday: Weekday) -> Option<NaiveDate> {
+    let back = (today.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday() - 1) % 7 + 1;
+    today.checked_sub_signed(TimeDelta::try_days(back as i64)?)
+}
+
+/// Reads `21:00`, `9pm`, `9:30am`, `noon` and `midnight`
+fn parse_time_of_day(s: &str) -> Option<NaiveTime> {
+    match s {
+        "noon" => return NaiveTime::from_hms_opt(12, 0, 0),
+        "midnight" => return NaiveTime::from_hms_opt(0, 0, 0),
+        _ => {}
+    }
+    let (clock, meridiem) = if let Some(clock) = s.strip_suffix("am") {
+        (clock, Some(0))
+    } else if let Some(clock) = s.strip_suffix("pm") {
+        (clock, Some(12))
+    } else {
+        (s, None)
+    };
+    let (hour, minute) = match clock.split_once(':') {
+        Some((h, m)) => (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?),
+        None if meridiem.is_some() => (clock.parse::<u32>().ok()?, 0),
+        None => return None,
+    };
+    let hour = match meridiem {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some(add) => hour % 12 + add,
+        None => hour,
+    };
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+/// Builds a timestamp from seconds since the epoch and the offset it was recorded in. Timestamps
+/// out of chrono's range, which only a damaged journal holds, are clamped to the nearest end of it.
+pub(crate) fn from_parts(timestamp: i64, offset: i32) -> DateTime<FixedOffset> {
+    let offset = FixedOffset::east_opt(offset).unwrap_or(FixedOffset::east_opt(0).unwrap());
+    offset.timestamp_opt(timestamp, 0).single().unwrap_or_else(|| {
+        let edge = if timestamp < 0 { DateTime::<Utc>::MIN_UTC } else { DateTime::<Utc>::MAX_UTC };
+        edge.fixed_offset()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_zones() {
+        assert_eq!("local".parse(), Ok(DisplayZone::Local));
+        assert_eq!("UTC".parse(), Ok(DisplayZone::Utc));
+        assert_eq!("+02:00".parse(), Ok(DisplayZone::Fixed(FixedOffset::east_opt(7200).unwrap())));
+        assert_eq!("-0530".parse(), Ok(DisplayZone::Fixed(FixedOffset::west_opt(19800).unwrap())));
+        assert_eq!("Europe/Berlin".parse(), Ok(DisplayZone::Named(chrono_tz::Europe::Berlin)));
+        assert!("Mars/Olympus".parse::<DisplayZone>().is_err());
+        assert!("+25:00".parse::<DisplayZone>().is_err());
+        assert!(TimeFormat::parse("utc", "%Y-%m-%d").is_ok());
+        assert_eq!(TimeFormat::parse("utc", "%Q"), Err(TimeError::InvalidFormat("%Q".to_string())));
+    }
+
+    #[test]
+    fn format_in_zone() {
+        // Written at 23:33 in New York, which is already the next day in UTC
+        let time = from_parts(1_700_022_800, -5 * 3600);
+        let original = TimeFormat::new(DisplayZone::Original, "%Y-%m-%d %H:%M %:z");
+        assert_eq!(original.format(&time), "2023-11-14 23:33 -05:00");
+        let utc = TimeFormat::new(DisplayZone::Utc, "%Y-%m-%d %H:%M");
+        assert_eq!(utc.format(&time), "2023-11-15 04:33");
+        assert_eq!(utc.date(&time), NaiveDate::from_ymd_opt(2023, 11, 15).unwrap());
+        let tokyo = TimeFormat::new("Asia/Tokyo".parse().unwrap(), "%H:%M");
+        assert_eq!(tokyo.format(&time), "13:33");
+        assert_eq!(from_parts(i64::MAX, 3600), DateTime::<Utc>::MAX_UTC);
+        assert_eq!(from_parts(i64::MIN, 0), DateTime::<Utc>::MIN_UTC);
+    }
+
+    #[test]
+    fn parse_dates() {
+        // Wednesday 2023-03-15 10:00 at +01:00
+        let zone = FixedOffset::east_opt(3600).unwrap();
+        let now = zone.with_ymd_and_hms(2023, 3, 15, 10, 0, 0).unwrap();
+        let parse = |s: &str| parse_datetime(s, now).map(|t| t.format("%Y-%m-%d %H:%M %:z").to_string());
+        assert_eq!(parse("2023-03-01T21:00:00Z").unwrap(), "2023-03-01 21:00 +00:00");
+        assert_eq!(parse("2023-03-01 21:30").unwrap(), "2023-03-01 21:30 +01:00");
+        assert_eq!(parse("2023-03-01").unwrap(), "2023-03-01 12:00 +01:00");
+        assert_eq!(parse("2023-03-01 9am").unwrap(), "2023-03-01 09:00 +01:00");
+        assert_eq!(parse("now").unwrap(), "2023-03-15 10:00 +01:00");
+        assert_eq!(parse("yesterday 9pm").unwrap(), "2023-03-14 21:00 +01:00");
+        assert_eq!(parse("Yesterday at 9:30PM").unwrap(), "2023-03-14 21:30 +01:00");
+        assert_eq!(parse("tomorrow noon").unwrap(), "2023-03-16 12:00 +01:00");
+        assert_eq!(parse("today midnight").unwrap(), "2023-03-15 00:00 +01:00");
+        assert_eq!(parse("3 days ago").unwrap(), "2023-03-12 12:00 +01:00");
+        assert_eq!(parse("monday 8:15").unwrap(), "2023-03-13 08:15 +01:00");
+        // The weekday of today means a week ago
+        assert_eq!(parse("last wednesday").unwrap(), "2023-03-08 12:00 +01:00");
+        assert_eq!(parse("12am").unwrap(), "2023-03-15 00:00 +01:00");
+        for invalid in ["", "someday", "yesterday 25:00", "13pm", "last week", "2023-02-30"] {
+            assert_eq!(parse(invalid), Err(TimeError::InvalidDate(invalid.to_string())));
+        }
+        // Too far away for chrono, or for a `TimeDelta`
+        for invalid in ["99999999999 days ago", "-99999999999 days ago", "9223372036854775807 days ago"] {
+            assert_eq!(parse(invalid), Err(TimeError::InvalidDate(invalid.to_string())));
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    io::Read,
+    net::ToSocketAddrs,
+};
+
+use chrono::DateTime;
+use journaldb::{export::Export, query::Query, Db, Entry, Tag};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response};
+
+pub mod openapi;
+
+/// Environment variable holding the token clients have to send
+pub const TOKEN_VAR: &str = "JOURNAL_SERVER_TOKEN";
+
+/// Largest request body read, larger ones are answered with 413 Payload Too Large
+const MAX_BODY: u64 = 1 << 20;
+
+/// A failed request, answered with `{"error": message}`
+#[derive(Debug)]
+struct ApiError {
+    status: u16,
+    message: String,
+    /// Where a search query stopped parsing
+    position: Option<usize>,
+}
+
+impl ApiError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        ApiError { status, message: message.into(), position: None }
+    }
+
+    fn bad_request(message: impl Into<String>) -> Self {
+        ApiError::new(400, message)
+    }
+
+    fn not_found() -> Self {
+        ApiError::new(404, "not found")
+    }
+
+    fn to_json(&self) -> Value {
+        match self.position {
+            Some(position) => json!({ "error": self.message, "position": position }),
+            None => json!({ "error": self.message }),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.status, self.message)
+    }
+}
+
+impl Error for ApiError {}
+
+/// Errors of the journal itself are answered with 500 Internal Server Error
+fn internal(e: impl fmt::Display) -> ApiError {
+    ApiError::new(500, e.to_string())
+}
+
+/// Requests answered at the same time, so a client sending its body slowly doesn't hold up the others
+const WORKERS: usize = 8;
+
+/// Serves the journal over HTTP, `WORKERS` requests at a time
+pub struct Server {
+    http: tiny_http::Server,
+    db: Db,
+    token: String,
+}
+
+/// Status and JSON body of an answered request, `None` for 204 No Content
+type Reply = (u16, Option<Value>);
+
+impl Server {
+    /// Listens on `address`, e.g. `127.0.0.1:7878`. Port 0 picks a free port.
+    pub fn bind(address: &str, db: Db, token: String) -> Result<Server, Box<dyn Error + Send + Sync>> {
+        if token.is_empty() {
+            return Err("the token must not be empty".into());
+        }
+        let http = tiny_http::Server::http(address)?;
+        Ok(Server { http, db, token })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.http.server_addr().to_ip().map(|a| a.port()).unwrap_or_default()
+    }
+
+    /// Answers requests until the listener fails
+    pub fn run(self) {
+        std::thread::scope(|scope| {
+            for _ in 0..WORKERS {
+                scope.spawn(|| self.serve());
+            }
+        });
+    }
+
+    /// Answers requests one after the other, next to the other workers
+    fn serve(&self) {
+        while let Ok(mut request) = self.http.recv() {
+            let (status, body) = match self.handle(&mut request) {
+                Ok(reply) => reply,
+                Err(e) => (e.status, Some(e.to_json())),
+            };
+            let _ = request.respond(response(status, body));
+        }
+    }
+
+    fn handle(&self, request: &mut Request) -> Result<Reply, ApiError> {
+        let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+        let (path, params): (String, HashMap<String, String>) =
+            (path.to_string(), form_urlencoded::parse(query.as_bytes()).into_owned().collect());
+        if request.method() == &Method::Get && path == "/openapi.json" {
+            return Ok((200, Some(openapi::spec())));
+        }
+        if !self.authorized(request) {
+            return Err(ApiError::new(401, "missing or wrong bearer token"));
+        }
+        let too_large = || ApiError::new(413, "the request body is larger than 1 MiB");
+        if request.body_length().is_some_and(|length| length as u64 > MAX_BODY) {
+            return Err(too_large());
+        }
+        // Bodies without a length are cut off one byte past the limit to tell they are too large
+        let mut body = String::new();
+        request
+            .as_reader()
+            .take(MAX_BODY + 1)
+            .read_to_string(&mut body)
+            .map_err(|e| ApiError::bad_request(e.to_string()))?;
+        if body.len() as u64 > MAX_BODY {
+            return Err(too_large());
+        }
+        let method = request.method().clone();
+        self.route(&method, &path, &params, &body)
+    }
+
+    fn authorized(&self, request: &Request) -> bool {
+        request
+            .headers()
+            .iter()
+            .filter(|h| h.field.equiv("Authorization"))
+            .filter_map(|h| h.value.as_str().strip_prefix("Bearer "))
+            .any(|token| constant_time_eq(token.as_bytes(), self.token.as_bytes()))
+    }
+
+    fn route(
+        &self,
+        method: &Method,
+        path: &str,
+        params: &HashMap<String, String>,
+        body: &str,
+    ) -> Result<Reply, ApiError> {
+        // Pick up changes made by journalcli or journaltui since the last request. A passphrase they
+        // set, changed or removed makes the key of this server useless.
+        if self.db.recheck_key().map_err(internal)? {
+            return Err(ApiError::new(423, "another program set or changed the passphrase of the journal, restart the server with it"));
+        }
+        self.db.update_entries().map_err(internal)?;
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        match (method, segments.as_slice()) {
+            (Method::Get, ["entries"]) => Ok((200, Some(entries_json(&self.db.get_entries())))),
+            (Method::Post, ["entries"]) => self.create_entry(body),
+            (Method::Get, ["entries", id]) => {
+                let entry = self.entry(id)?;
+                Ok((200, Some(entry_json(&entry))))
+            }
+            (Method::Put, ["entries", id]) => self.edit_entry(id, body),
+            (Method::Delete, ["entries", id]) => {
+                let entry = self.entry(id)?;
+                self.db.delete_entry(&entry).map_err(internal)?;
+                Ok((204, None))
+            }
+            (Method::Get, ["tags"]) => Ok((200, Some(self.tags()))),
+            (Method::Get, ["search"]) => self.search(params),
+            (Method::Get, ["export"]) => Ok((200, Some(self.export()?))),
+            (_, ["entries"] | ["entries", _] | ["tags"] | ["search"] | ["export"]) => {
+                Err(ApiError::new(405, "method not allowed"))
+            }
+            _ => Err(ApiError::not_found()),
+        }
+    }
+
+    fn entry(&self, id: &str) -> Result<Entry, ApiError> {
+        id.parse::<u32>()
+            .ok()
+            .and_then(|id| self.db.get_entry_by_id(id))
+            .ok_or_else(ApiError::not_found)
+    }
+
+    fn create_entry(&self, body: &str) -> Result<Reply, ApiError> {
+        let input = parse_body(body)?;
+        let mut entry = Entry::new(
+            string_field(&input, "title")?.unwrap_or_default(),
+            string_field(&input, "content")?.unwrap_or_default(),
+            tags_field(&input)?.flatten(),
+        );
+        if let Some(created) = string_field(&input, "created")? {
+            let created = DateTime::parse_from_rfc3339(&created)
+                .map_err(|e| ApiError::bad_request(format!("invalid created time: {}", e)))?;
+            entry.set_created_time(created);
+            entry.set_updated_time(created);
+        }
+        self.db.create_entry(&mut entry).map_err(internal)?;
+        Ok((201, Some(entry_json(&self.entry(&entry.get_id().to_string())?))))
+    }
+
+    /// Changes the fields given in the body, keeping the others
+    fn edit_entry(&self, id: &str, body: &str) -> Result<Reply, ApiError> {
+        let mut entry = self.entry(id)?;
+        let input = parse_body(body)?;
+        if let Some(title) = string_field(&input, "title")? {
+            entry.set_title(title);
+        }
+        if let Some(content) = string_field(&input, "content")? {
+            entry.set_content(content);
+        }
+        if let Some(tags) = tags_field(&input)? {
+            entry.set_tags(tags);
+        }
+        self.db.edit_entry(&mut entry).map_err(internal)?;
+        Ok((200, Some(entry_json(&self.entry(id)?))))
+    }
+
+    /// Tags with the number of entries using them, ordered by name
+    fn tags(&self) -> Value {
+        let mut counts: Vec<(String, usize)> = Vec::new();
+        for entry in self.db.get_entries() {
+            let mut names: Vec<String> = entry.get_tags().unwrap_or_default().iter().map(Tag::get_tag).collect();
+            names.sort();
+            names.dedup();
+            for name in names {
+                match counts.iter_mut().find(|(tag, _)| *tag == name) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((name, 1)),
+                }
+            }
+        }
+        counts.sort();
+        Value::Array(counts.into_iter().map(|(tag, entries)| json!({ "tag": tag, "entries": entries })).collect())
+    }
+
+    fn search(&self, params: &HashMap<String, String>) -> Result<Reply, ApiError> {
+        let q = params.get("q").ok_or_else(|| ApiError::bad_request("missing parameter q"))?;
+        let query = Query::parse(q).map_err(|e| ApiError {
+            status: 400,
+            message: e.message.clone(),
+            position: Some(e.position),
+        })?;
+        let entries = self.db.search(&query).map_err(internal)?;
+        Ok((200, Some(entries_json(&entries))))
+    }
+
+    /// Entries with their attachments and the saved searches, in the format `journalcli import` reads
+    fn export(&self) -> Result<Value, ApiError> {
+        let export = Export::of(&self.db).map_err(internal)?;
+        Ok(serde_json::to_value(export).expect("exports serialize to JSON"))
+    }
+}
+
+fn entry_json(entry: &Entry) -> Value {
+    serde_json::to_value(entry).expect("entries serialize to JSON")
+}
+
+fn entries_json(entries: &[Entry]) -> Value {
+    serde_json::to_value(entries).expect("entries serialize to JSON")
+}
+
+fn parse_body(body: &str) -> Result<Value, ApiError> {
+    let input: Value = serde_json::from_str(body).map_err(|e| ApiError::bad_request(format!("invalid JSON: {}", e)))?;
+    if !input.is_object() {
+        return Err(ApiError::bad_request("expected a JSON object"));
+    }
+    Ok(input)
+}
+
+fn string_field(input: &Value, key: &str) -> Result<Option<String>, ApiError> {
+    match input.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(ApiError::bad_request(format!("{} must be a string", key))),
+    }
+}
+
+/// `Some(None)` when the body clears the tags
+fn tags_field(input: &Value) -> Result<Option<Option<Vec<Tag>>>, ApiError> {
+    let Some(tags) = input.get("tags") else {
+        return Ok(None);
+    };
+    let tags = tags
+        .as_array()
+        .and_then(|tags| tags.iter().map(|t| t.as_str()).collect::<Option<Vec<&str>>>())
+        .ok_or_else(|| ApiError::bad_request("tags must be a list of strings"))?;
+    let tags: Vec<Tag> = tags.into_iter().filter(|t| !t.is_empty()).map(|t| Tag::new(t.to_string())).collect();
+    Ok(Some(Some(tags).filter(|t| !t.is_empty())))
+}
+
+fn response(status: u16, body: Option<Value>) -> Response<std::io::Cursor<Vec<u8>>> {
+    let data = body.map(|b| b.to_string().into_bytes()).unwrap_or_default();
+    let mut response = Response::from_data(data).with_status_code(status);
+    if status != 204 {
+        let header = Header::from_bytes("Content-Type", "application/json").expect("valid header");
+        response.add_header(header);
+    }
+    response
+}
+
+/// Compares without stopping at the first difference, so response times don't reveal the token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Whether `address` only reaches this machine, e.g. `127.0.0.1:7878` or `localhost:7878`
+pub fn is_loopback(address: &str) -> bool {
+    address
+        .to_socket_addrs()
+        .map(|addrs| addrs.collect::<Vec<_>>())
+        .is_ok_and(|addrs| !addrs.is_empty() && addrs.iter().all(|a| a.ip().is_loopback()))
+}
+
+/// A random token for servers started without one
+pub fn generate_token() -> Result<String, Box<dyn Error>> {
+    let mut bytes = [0u8; 24];
+    getrandom::getrandom(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
@@ -0,0 +1,45 @@
+use std::error::Error;
+
+use clap::{arg, command};
+
+use journaldb::Db;
+use journalserver::{generate_token, is_loopback, Server, TOKEN_VAR};
+
+/// Environment variable holding the passphrase of an encrypted journal
+const PASSPHRASE_VAR: &str = "JOURNAL_PASSPHRASE";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let matches = command!()
+        .about("Serve the journal as JSON over HTTP, see /openapi.json")
+        .arg(arg!(--journal <PATH> "SQLite journal file").default_value("journal.db"))
+        .arg(arg!(--address <ADDRESS> "Address to listen on, keep it on localhost").default_value("127.0.0.1:7878"))
+        .arg(arg!(--token <TOKEN> "Bearer token clients have to send, defaults to $JOURNAL_SERVER_TOKEN or a random one"))
+        .arg(arg!(--"allow-remote" "Allow an address other machines can reach, the journal goes over plain HTTP"))
+        .get_matches();
+
+    let address = matches.get_one::<String>("address").unwrap();
+    if !matches.get_flag("allow-remote") && !is_loopback(address) {
+        return Err(format!("{} can be reached from other machines, pass --allow-remote to listen on it anyway", address).into());
+    }
+
+    let db = Db::new(matches.get_one::<String>("journal").unwrap());
+    db.initialize_db()?;
+    if db.is_locked()? {
+        let passphrase = std::env::var(PASSPHRASE_VAR)
+            .map_err(|_| format!("the journal is encrypted, set {}", PASSPHRASE_VAR))?;
+        db.unlock(&passphrase)?;
+    }
+
+    let token = match matches.get_one::<String>("token").cloned().or_else(|| std::env::var(TOKEN_VAR).ok()) {
+        Some(token) => token,
+        None => {
+            let token = generate_token()?;
+            println!("Token: {}", token);
+            token
+        }
+    };
+    let server = Server::bind(address, db, token).map_err(|e| e.to_string())?;
+    println!("Listening on http://{}", address.rsplit_once(':').map(|(host, _)| format!("{}:{}", host, server.port())).unwrap_or_default());
+    server.run();
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use serde_json::{json, Value};
+
+/// OpenAPI 3 description of the API, served at `/openapi.json`
+pub fn spec() -> Value {
+    let entry_ref = json!({ "$ref": "#/components/schemas/Entry" });
+    let entries = json!({
+        "description": "Entries ordered by id",
+        "content": { "application/json": { "schema": { "type": "array", "items": entry_ref } } },
+    });
+    let entry = json!({
+        "description": "The entry",
+        "content": { "application/json": { "schema": entry_ref } },
+    });
+    let error = |description: &str| {
+        json!({
+            "description": description,
+            "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } },
+        })
+    };
+    let id = json!({ "name": "id", "in": "path", "required": true, "schema": { "type": "integer" } });
+    let input = |description: &str| {
+        json!({
+            "description": description,
+            "required": true,
+            "content": { "application/json": { "schema": { "$ref": "#/components/schemas/EntryInput" } } },
+        })
+    };
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "journalserver",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Entries, tags, search and export of a journal. Every path except /openapi.json needs an `Authorization: Bearer <token>` header, and answers 423 once another program sets or changes the passphrase of the journal.",
+        },
+        "security": [{ "token": [] }],
+        "paths": {
+            "/entries": {
+                "get": {
+                    "summary": "List all entries",
+                    "responses": { "200": entries, "401": error("Missing or wrong token") },
+                },
+                "post": {
+                    "summary": "Create an entry",
+                    "requestBody": input("The new entry, `created` defaults to now"),
+                    "responses": { "201": entry, "400": error("Invalid body"), "401": error("Missing or wrong token"), "413": error("Body larger than 1 MiB") },
+                },
+            },
+            "/entries/{id}": {
+                "parameters": [id],
+                "get": {
+                    "summary": "Get an entry",
+                    "responses": { "200": entry, "404": error("No such entry") },
+                },
+                "put": {
+                    "summary": "Change the title, content or tags of an entry, fields left out are kept",
+                    "requestBody": input("Fields to change, `created` is ignored"),
+                    "responses": { "200": entry, "400": error("Invalid body"), "404": error("No such entry"), "413": error("Body larger than 1 MiB") },
+                },
+                "delete": {
+                    "summary": "Delete an entry",
+                    "responses": { "204": { "description": "Deleted" }, "404": error("No such entry") },
+                },
+            },
+            "/tags": {
+                "get": {
+                    "summary": "List the tags with the number of entries using them",
+                    "responses": {
+                        "200": {
+                            "description": "Tags ordered by name",
+                            "content": { "application/json": { "schema": {
+                                "type": "array",
+                                "items": {
+                                    "type": "object",
+                                    "properties": { "tag": { "type": "string" }, "entries": { "type": "integer" } },
+                                },
+                            } } },
+                        },
+                    },
+                },
+            },
+            "/search": {
+                "get": {
+                    "summary": "Find entries with the query language of `journalcli list --query`",
+                    "parameters": [{
+                        "name": "q",
+                        "in": "query",
+                        "required": true,
+                        "schema": { "type": "string" },
+                        "example": "tag:work -tag:draft after:2024-01-01",
+                    }],
+                    "responses": { "200": entries, "400": error("The query doesn't parse, `position` tells where") },
+                },
+            },
+            "/export": {
+                "get": {
+                    "summary": "Export entries with attachments and saved searches, in the format `journalcli import` reads",
+                    "responses": { "200": { "description": "The export", "content": { "application/json": {} } } },
+                },
+            },
+            "/openapi.json": {
+                "get": {
+                    "summary": "This description",
+                    "security": [],
+                    "responses": { "200": { "description": "OpenAPI 3 document" } },
+                },
+            },
+        },
+        "components": {
+            "securitySchemes": { "token": { "type": "http", "scheme": "bearer" } },
+            "schemas": {
+                "Entry": {
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "integer" },
+                        "title": { "type": "string" },
+                        "content": { "type": "string" },
+                        "tags": { "type": "array", "items": { "type": "string" } },
+                        "created": { "type": "string", "format": "date-time" },
+                        "updated": { "type": "string", "format": "date-time" },
+                    },
+                },
+                "EntryInput": {
+                    "type": "object",
+                    "properties": {
+                        "title": { "type": "string" },
+                        "content": { "type": "string" },
+                        "tags": { "type": "array", "items": { "type": "string" } },
+                        "created": { "type": "string", "format": "date-time" },
+                    },
+                },
+                "Error": {
+                    "type": "object",
+                    "properties": { "error": { "type": "string" }, "position": { "type": "integer" } },
+                    "required": ["error"],
+                },
+            },
+        },
+    })
+}
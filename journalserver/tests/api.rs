@@ -0,0 +1,171 @@
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use journaldb::Db;
+use journalserver::{is_loopback, Server};
+use serde_json::{json, Value};
+
+const TOKEN: &str = "secret";
+
+/// Starts a server on a free port over an empty journal
+fn start() -> u16 {
+    let db = Db::new(":memory:");
+    db.initialize_db().unwrap();
+    let server = Server::bind("127.0.0.1:0", db, TOKEN.to_string()).unwrap();
+    let port = server.port();
+    std::thread::spawn(move || server.run());
+    port
+}
+
+/// Sends a request and returns the status with the parsed body, `Value::Null` when empty
+fn request(port: u16, method: &str, path: &str, token: Option<&str>, body: Option<Value>) -> (u16, Value) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    let body = body.map(|b| b.to_string()).unwrap_or_default();
+    let auth = token.map(|t| format!("Authorization: Bearer {}\r\n", t)).unwrap_or_default();
+    write!(
+        stream,
+        "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{auth}Content-Length: {}\r\n\r\n{body}",
+        body.len()
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    let body = if body.is_empty() { Value::Null } else { serde_json::from_str(body).unwrap() };
+    (status, body)
+}
+
+fn get(port: u16, path: &str) -> (u16, Value) {
+    request(port, "GET", path, Some(TOKEN), None)
+}
+
+#[test]
+fn requires_token() {
+    let port = start();
+    assert_eq!(request(port, "GET", "/entries", None, None).0, 401);
+    let (status, body) = request(port, "GET", "/entries", Some("wrong"), None);
+    assert_eq!((status, body["error"].as_str()), (401, Some("missing or wrong bearer token")));
+    assert_eq!(get(port, "/entries"), (200, json!([])));
+
+    // The description is public so tools can discover the API
+    let (status, spec) = request(port, "GET", "/openapi.json", None, None);
+    assert_eq!(status, 200);
+    assert_eq!(spec["openapi"], "3.0.3");
+    for path in ["/entries", "/entries/{id}", "/tags", "/search", "/export"] {
+        assert!(spec["paths"][path].is_object(), "{} is not described", path);
+    }
+}
+
+#[test]
+fn entries() {
+    let port = start();
+    let new = json!({ "title": "Rome", "content": "Pasta", "tags": ["travel", "food"], "created": "2024-03-01T12:00:00+01:00" });
+    let (status, created) = request(port, "POST", "/entries", Some(TOKEN), Some(new));
+    assert_eq!(status, 201);
+    assert_eq!(created["title"], "Rome");
+    assert_eq!(created["tags"], json!(["travel", "food"]));
+    assert_eq!(created["created"], "2024-03-01T12:00:00+01:00");
+    let path = format!("/entries/{}", created["id"]);
+    assert_eq!(get(port, &path), (200, created.clone()));
+
+    let change = json!({ "content": "Pizza", "tags": [] });
+    let (status, edited) = request(port, "PUT", &path, Some(TOKEN), Some(change));
+    assert_eq!(status, 200);
+    assert_eq!((&edited["title"], &edited["content"], &edited["tags"]), (&json!("Rome"), &json!("Pizza"), &json!([])));
+    assert_ne!(edited["updated"], created["updated"]);
+
+    let (status, error) = request(port, "PUT", &path, Some(TOKEN), Some(json!({ "tags": "travel" })));
+    assert_eq!((status, error["error"].as_str()), (400, Some("tags must be a list of strings")));
+    assert_eq!(request(port, "POST", "/entries", Some(TOKEN), None).0, 400);
+    assert_eq!(request(port, "PATCH", &path, Some(TOKEN), None).0, 405);
+
+    assert_eq!(request(port, "DELETE", &path, Some(TOKEN), None), (204, Value::Null));
+    assert_eq!(get(port, &path).0, 404);
+    assert_eq!(get(port, "/entries/abc").0, 404);
+    assert_eq!(get(port, "/nowhere").0, 404);
+}
+
+#[test]
+fn tags_search_and_export() {
+    let port = start();
+    for (title, tags) in [("Standup", json!(["work"])), ("Retro", json!(["work", "retro"])), ("Hike", json!([]))] {
+        let entry = json!({ "title": title, "content": "", "tags": tags });
+        assert_eq!(request(port, "POST", "/entries", Some(TOKEN), Some(entry)).0, 201);
+    }
+    assert_eq!(
+        get(port, "/tags"),
+        (200, json!([{ "tag": "retro", "entries": 1 }, { "tag": "work", "entries": 2 }]))
+    );
+
+    let (status, found) = get(port, "/search?q=tag%3Awork+-title%3Aretro");
+    assert_eq!(status, 200);
+    assert_eq!(found.as_array().unwrap().iter().map(|e| e["title"].clone()).collect::<Vec<_>>(), vec!["Standup"]);
+    let (status, error) = get(port, "/search?q=tag%3Awork+%28");
+    assert_eq!(status, 400);
+    assert_eq!(error["position"], 10);
+    assert_eq!(get(port, "/search").0, 400);
+
+    let (status, export) = get(port, "/export");
+    assert_eq!(status, 200);
+    assert_eq!(export["entries"].as_array().unwrap().len(), 3);
+    assert_eq!(export["entries"][0]["attachments"], json!([]));
+    assert_eq!(export["saved_searches"], json!([]));
+}
+
+#[test]
+fn rejects_large_bodies() {
+    let port = start();
+    let (status, body) = request(port, "POST", "/entries", Some(TOKEN), Some(json!("x".repeat(1 << 20))));
+    assert_eq!((status, body["error"].as_str()), (413, Some("the request body is larger than 1 MiB")));
+    assert_eq!(get(port, "/entries"), (200, json!([])));
+}
+
+#[test]
+fn slow_bodies_dont_block_other_requests() {
+    let port = start();
+    let mut slow = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    write!(slow, "POST /entries HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {TOKEN}\r\nContent-Length: 100000\r\n\r\n").unwrap();
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || sender.send(get(port, "/entries")));
+    assert_eq!(receiver.recv_timeout(Duration::from_secs(5)).unwrap(), (200, json!([])));
+    drop(slow);
+}
+
+#[test]
+fn passphrase_changed_elsewhere() {
+    let path = std::env::temp_dir().join(format!("journalserver-passphrase-{}.db", std::process::id()));
+    let path = path.to_str().unwrap().to_string();
+    let db = Db::new(&path);
+    db.initialize_db().unwrap();
+    let server = Server::bind("127.0.0.1:0", db, TOKEN.to_string()).unwrap();
+    let port = server.port();
+    std::thread::spawn(move || server.run());
+    assert_eq!(get(port, "/entries"), (200, json!([])));
+
+    let other = Db::new(&path);
+    other.initialize_db().unwrap();
+    other.set_passphrase(Some("secret")).unwrap();
+    let (status, _) = request(port, "POST", "/entries", Some(TOKEN), Some(json!({ "title": "Plain" })));
+    assert_eq!(status, 423);
+    assert_eq!(get(port, "/entries").0, 423);
+    other.update_entries().unwrap();
+    assert!(other.get_entries().is_empty());
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+    }
+}
+
+#[test]
+fn loopback_addresses() {
+    for address in ["127.0.0.1:7878", "localhost:0", "[::1]:7878"] {
+        assert!(is_loopback(address), "{}", address);
+    }
+    for address in ["0.0.0.0:7878", "[::]:7878", "192.168.1.2:7878", "no port"] {
+        assert!(!is_loopback(address), "{}", address);
+    }
+}
@@ -1,7 +1,29 @@
-use crossterm::event::{Event, self, KeyCode};
-use tui::{backend::Backend, Terminal, widgets::ListState};
-use journaldb::Db;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::mpsc::Receiver,
+};
 
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, TimeZone};
+use crossterm::event::{Event, KeyCode, KeyEvent, MouseButton, MouseEvent, MouseEventKind};
+use tui::{backend::Backend, layout::Rect, Terminal, widgets::ListState};
+use journaldb::{
+    Db, Entry, Tag,
+    changes::Change,
+    crypto::CryptoError,
+    flags::{self, Flag},
+    links::Link,
+    query::{Query, QueryError},
+    saved_searches::{SavedSearch, SearchCount},
+    store::JournalStore,
+    templates::Template,
+    time::TimeFormat,
+};
+
+use crate::calendar::{Intensity, CALENDAR_WIDTH};
+use crate::config::{Action, Config};
+#[cfg(test)]
+use crate::config::Theme;
+use crate::events::{EventSource, Input};
 use crate::ui::ui;
 
 pub enum InputMode {
@@ -24,6 +46,9 @@ impl<T> StatefulList<T> {
     }
 
     fn next(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i >= self.items.len() - 1 {
@@ -38,6 +63,9 @@ impl<T> StatefulList<T> {
     }
 
     fn previous(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -51,68 +79,1405 @@ impl<T> StatefulList<T> {
         self.state.select(Some(i));
     }
 
-    fn unselect(&mut self) {
-        self.state.select(None);
+    fn set_items(&mut self, items: Vec<T>) {
+        self.items = items;
+        match self.state.selected() {
+            _ if self.items.is_empty() => self.state.select(None),
+            Some(i) if i >= self.items.len() => self.state.select(Some(self.items.len() - 1)),
+            _ => {}
+        }
     }
 }
 
+/// Fields of the entry editor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Title,
+    Content,
+    Tags,
+}
+
+impl Field {
+    fn next(self) -> Field {
+        match self {
+            Field::Title => Field::Content,
+            Field::Content => Field::Tags,
+            Field::Tags => Field::Title,
+        }
+    }
+}
+
+/// Top level views of the app
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tab {
+    Journal,
+    Dashboard,
+}
+
+impl Tab {
+    pub const ALL: [Tab; 2] = [Tab::Journal, Tab::Dashboard];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Tab::Journal => "Journal",
+            Tab::Dashboard => "Dashboard",
+        }
+    }
+
+    fn next(self) -> Tab {
+        match self {
+            Tab::Journal => Tab::Dashboard,
+            Tab::Dashboard => Tab::Journal,
+        }
+    }
+}
+
+/// What a typed passphrase is for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PassphraseStep {
+    /// Opening an encrypted journal
+    Unlock,
+    /// First entry of a new passphrase, empty to remove the encryption
+    New,
+    /// Repeating the new passphrase
+    Confirm(String),
+}
+
+/// Popup asking for a passphrase, typed characters are not shown
+pub struct PassphrasePrompt {
+    pub step: PassphraseStep,
+    pub input: String,
+    /// Why the last attempt failed
+    pub error: Option<String>,
+}
+
+impl PassphrasePrompt {
+    fn new(step: PassphraseStep) -> Self {
+        PassphrasePrompt {
+            step,
+            input: String::new(),
+            error: None,
+        }
+    }
+}
+
+/// Popup choosing which link of an entry to follow
+pub struct LinkPicker {
+    pub links: Vec<Link>,
+    pub selected: usize,
+}
+
+/// Popup choosing a template for a new entry, then asking its `{{prompt:...}}` questions
+pub struct TemplatePicker {
+    pub templates: Vec<Template>,
+    pub selected: usize,
+    /// Answers given so far, `None` while the template is being chosen
+    pub answers: Option<Vec<String>>,
+    /// Answer being typed
+    pub input: String,
+    /// Whether the answers are for creating today's daily entry
+    pub daily: bool,
+}
+
+impl TemplatePicker {
+    pub fn template(&self) -> Option<&Template> {
+        self.templates.get(self.selected)
+    }
+
+    /// Question being answered
+    pub fn question(&self) -> Option<String> {
+        let answered = self.answers.as_ref()?.len();
+        self.template()?.prompts().into_iter().nth(answered)
+    }
+}
+
+/// Search being typed, the entry list follows it while it parses
+pub struct SearchBox {
+    pub input: String,
+    pub error: Option<QueryError>,
+}
+
+/// Screen areas of the last drawn frame, used to route mouse events
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Areas {
+    pub main: Rect,
+    pub entries: Rect,
+    pub title: Rect,
+    pub content: Rect,
+    pub tags: Rect,
+    pub saved_searches: Rect,
+}
+
+/// Narrowest the sidebar can be dragged, wide enough for the calendar
+pub const MIN_SIDEBAR_WIDTH: u16 = CALENDAR_WIDTH + 2;
+/// Narrowest the editor can get while dragging the split
+const MIN_EDITOR_WIDTH: u16 = 20;
+
 /// App holds the state of the application
 pub struct App {
-    /// Current value of the input box
-    pub input: String,
+    pub tab: Tab,
     /// Current input mode
     pub input_mode: InputMode,
-    /// History of recorded messages
-    pub messages: Vec<String>,
-    // pub entries: Vec<Entry>,
-    pub db: Db,
+    /// Editor field receiving typed text
+    pub focus: Field,
+    /// Unsaved title, content and comma separated tags of the selected entry
+    pub title: String,
+    pub content: String,
+    pub tags: String,
+    /// First visible line of the content pane
+    pub content_scroll: u16,
+    pub entries: StatefulList<Entry>,
+    /// First visible row of the entry list
+    pub list_offset: usize,
+    /// Where entries are kept. Passphrases, links, templates, daily entries and saved searches
+    /// need a SQLite journal.
+    pub store: Box<dyn JournalStore>,
+    /// Keymap and theme
+    pub config: Config,
+    /// Whether the keymap help overlay is shown
+    pub show_help: bool,
+    /// First row of the help overlay, for screens too small to show all actions
+    pub help_scroll: usize,
+    /// Open passphrase popup, which receives all keys
+    pub passphrase_prompt: Option<PassphrasePrompt>,
+    /// Open link popup, for entries with more than one link
+    pub link_picker: Option<LinkPicker>,
+    /// Open template popup
+    pub template_picker: Option<TemplatePicker>,
+    pub today: NaiveDate,
+    /// Day the entry list is restricted to, picked on the calendar
+    pub date_filter: Option<NaiveDate>,
+    /// Query the entry list is restricted to, empty for all entries
+    pub search: String,
+    /// Ids of the entries matching `search`
+    matches: Option<HashSet<u32>>,
+    /// Open search popup
+    pub search_box: Option<SearchBox>,
+    /// Saved searches with the number of entries matching them, or why they can't run
+    pub saved_searches: Vec<(SavedSearch, SearchCount)>,
+    /// Zone and format timestamps are shown in
+    pub time_format: TimeFormat,
+    /// Width of the calendar and entry list column
+    pub sidebar_width: u16,
+    /// Whether the split between sidebar and editor is being dragged
+    pub resizing: bool,
+    pub areas: Areas,
+    /// Changes to the journal, for reloading when another process writes to it
+    changes: Option<Receiver<Change>>,
+    /// Why the last action failed, shown until the next key
+    pub status: Option<String>,
 }
 
-impl Default for App {
-    fn default() -> App {
+impl App {
+    pub fn new(store: Box<dyn JournalStore>, config: Config) -> App {
         App {
-            input: String::new(),
+            tab: Tab::Journal,
             input_mode: InputMode::Normal,
-            messages: Vec::new(),
-            db: Db::new("journal.db"),
+            focus: Field::Title,
+            title: String::new(),
+            content: String::new(),
+            tags: String::new(),
+            content_scroll: 0,
+            entries: StatefulList::with_items(Vec::new()),
+            list_offset: 0,
+            store,
+            config,
+            show_help: false,
+            help_scroll: 0,
+            passphrase_prompt: None,
+            link_picker: None,
+            template_picker: None,
+            today: chrono::Local::now().date_naive(),
+            date_filter: None,
+            search: String::new(),
+            matches: None,
+            search_box: None,
+            saved_searches: Vec::new(),
+            time_format: TimeFormat::default(),
+            sidebar_width: 30,
+            resizing: false,
+            areas: Areas::default(),
+            changes: None,
+            status: None,
         }
     }
-}
 
+    fn refresh_entries(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.store.update_entries()?;
+        if !self.search.is_empty() {
+            let query = Query::parse(&self.search)?;
+            self.matches = Some(self.store.search(&query)?.iter().map(Entry::get_id).collect());
+        }
+        self.saved_searches = match self.store.as_db() {
+            Some(db) => db.count_saved_searches()?,
+            None => Vec::new(),
+        };
+        self.entries.set_items(self.filtered_entries());
+        Ok(())
+    }
 
-pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<(), Box<dyn std::error::Error>> {
-    app.db.initialize_db()?;
-    loop {
-        terminal.draw(|f| ui(f, &mut app))?;
+    /// Starts listening for changes, only SQLite journals report them
+    fn watch_changes(&mut self) {
+        self.changes = self.store.as_db().map(Db::subscribe);
+    }
 
-        if let Event::Key(key) = event::read()? {
-            match app.input_mode {
-                InputMode::Normal => match key.code {
-                    KeyCode::Char('e') => {
-                        app.input_mode = InputMode::Editing;
-                    }
-                    KeyCode::Char('q') => {
-                        return Ok(());
+    /// Reloads the entries if the journal changed, e.g. through journalcli, keeping the selected
+    /// entry. Text being edited is left alone.
+    fn reload_changes(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.passphrase_prompt.is_some() {
+            return Ok(());
+        }
+        let (Some(db), Some(changes)) = (self.store.as_db(), &self.changes) else {
+            return Ok(());
+        };
+        db.poll_changes()?;
+        let changes: Vec<Change> = changes.try_iter().collect();
+        if changes.is_empty() {
+            return Ok(());
+        }
+        // Another process may have encrypted the journal or changed its passphrase
+        if changes.contains(&Change::External) && db.recheck_key()? {
+            self.passphrase_prompt = Some(PassphrasePrompt::new(PassphraseStep::Unlock));
+            return Ok(());
+        }
+        let selected = self.selected_entry().map(Entry::get_id);
+        self.refresh_entries()?;
+        self.reselect(selected);
+        Ok(())
+    }
+
+    /// Selects the entry with id `selected` again after the list changed, or the first one when it
+    /// is gone. Text being edited is left alone.
+    fn reselect(&mut self, selected: Option<u32>) {
+        let index = selected.and_then(|id| self.entries.items.iter().position(|e| e.get_id() == id));
+        match self.input_mode {
+            InputMode::Editing => self.entries.state.select(index),
+            InputMode::Normal if index.is_none() && !self.entries.items.is_empty() => self.select_entry(Some(0)),
+            InputMode::Normal => self.select_entry(index),
+        }
+    }
+
+    /// Pinned entries first, archived ones only while searching
+    fn filtered_entries(&self) -> Vec<Entry> {
+        let entries = self
+            .store
+            .get_entries()
+            .into_iter()
+            .filter(|e| self.date_filter.is_none_or(|d| self.entry_date(e) == d))
+            .filter(|e| self.matches.as_ref().is_none_or(|m| m.contains(&e.get_id())))
+            .collect();
+        flags::arrange(entries, self.matches.is_some())
+    }
+
+    /// Sets or clears `flag` on the selected entry
+    fn toggle_flag(&mut self, flag: Flag) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(entry) = self.selected_entry() else {
+            return Ok(());
+        };
+        let (id, on) = (entry.get_id(), !entry.has_flag(flag));
+        self.store.set_flag(id, flag, on)?;
+        self.refresh_entries()?;
+        self.reselect(Some(id));
+        Ok(())
+    }
+
+    fn set_date_filter(&mut self, date: Option<NaiveDate>) {
+        self.date_filter = date;
+        self.entries.set_items(self.filtered_entries());
+        let first = if self.entries.items.is_empty() { None } else { Some(0) };
+        self.select_entry(first);
+    }
+
+    fn move_date_filter(&mut self, days: i64) {
+        let date = self.date_filter.unwrap_or(self.today) + Duration::days(days);
+        self.set_date_filter(Some(date));
+    }
+
+    /// Amount written per day, for shading the calendar
+    pub fn daily_amounts(&self) -> HashMap<NaiveDate, usize> {
+        let mut amounts = HashMap::new();
+        for entry in self.store.get_entries() {
+            let amount = match self.config.intensity {
+                Intensity::Count => 1,
+                Intensity::Words => entry.get_content().split_whitespace().count(),
+            };
+            *amounts.entry(self.entry_date(&entry)).or_insert(0) += amount;
+        }
+        amounts
+    }
+
+    /// Day an entry was written on, in the display time zone
+    fn entry_date(&self, entry: &Entry) -> NaiveDate {
+        self.time_format.date(&entry.get_created_time())
+    }
+
+    pub fn selected_entry(&self) -> Option<&Entry> {
+        self.entries.state.selected().and_then(|i| self.entries.items.get(i))
+    }
+
+    /// Selects an entry of the list and loads it into the editor
+    fn select_entry(&mut self, index: Option<usize>) {
+        self.entries.state.select(index);
+        self.load_selected();
+    }
+
+    fn load_selected(&mut self) {
+        let (title, content, tags) = match self.selected_entry() {
+            Some(entry) => (
+                entry.get_title(),
+                entry.get_content(),
+                entry
+                    .get_tags()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|t| t.get_tag())
+                    .collect::<Vec<String>>()
+                    .join(","),
+            ),
+            None => (String::new(), String::new(), String::new()),
+        };
+        self.title = title;
+        self.content = content;
+        self.tags = tags;
+        self.content_scroll = 0;
+    }
+
+    fn next_entry(&mut self) {
+        self.entries.next();
+        self.load_selected();
+    }
+
+    fn previous_entry(&mut self) {
+        self.entries.previous();
+        self.load_selected();
+    }
+
+    /// Clears the editor so saving creates a new entry
+    fn new_entry(&mut self) {
+        self.select_entry(None);
+        self.focus = Field::Title;
+        self.input_mode = InputMode::Editing;
+    }
+
+    fn focused_field(&mut self) -> &mut String {
+        match self.focus {
+            Field::Title => &mut self.title,
+            Field::Content => &mut self.content,
+            Field::Tags => &mut self.tags,
+        }
+    }
+
+    /// Writes the editor back to the selected entry, or creates one if none is selected
+    fn save(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let tags: Vec<Tag> = self
+            .tags
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(|t| Tag::new(t.to_string()))
+            .collect();
+        let tags = Some(tags).filter(|t| !t.is_empty());
+        let entry = match self.selected_entry() {
+            Some(entry) => {
+                let mut entry = entry.clone();
+                entry.set_title(self.title.clone());
+                entry.set_content(self.content.clone());
+                entry.set_tags(tags);
+                self.store.edit_entry(&mut entry)?;
+                entry
+            }
+            None => {
+                let mut entry = Entry::new(self.title.clone(), self.content.clone(), tags);
+                self.store.create_entry(&mut entry)?;
+                entry
+            }
+        };
+        self.refresh_entries()?;
+        // Clears the search and date filter when they hide the saved entry
+        self.open_entry(entry.get_id());
+        Ok(())
+    }
+
+    fn scroll_content(&mut self, up: bool) {
+        self.content_scroll = if up {
+            self.content_scroll.saturating_sub(1)
+        } else {
+            let lines = self.content.lines().count() as u16;
+            (self.content_scroll + 1).min(lines.saturating_sub(1))
+        };
+    }
+
+    /// Selects an entry by id, showing all entries if the date filter or search hides it
+    fn open_entry(&mut self, id: u32) {
+        let find = |app: &App| app.entries.items.iter().position(|e| e.get_id() == id);
+        if find(self).is_none() {
+            self.date_filter = None;
+            self.search.clear();
+            self.matches = None;
+            self.entries.set_items(self.filtered_entries());
+        }
+        let index = find(self);
+        if index.is_some() {
+            self.select_entry(index);
+        }
+    }
+
+    /// Follows the only link of the selected entry, or lets the user pick one
+    fn follow_link(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let (Some(entry), Some(db)) = (self.selected_entry(), self.store.as_db()) else {
+            return Ok(());
+        };
+        let links = db.outgoing_links(entry.get_id())?;
+        match links.as_slice() {
+            [] => {}
+            [link] => {
+                if let Some(target) = link.target {
+                    self.open_entry(target);
+                }
+            }
+            _ => self.link_picker = Some(LinkPicker { links, selected: 0 }),
+        }
+        Ok(())
+    }
+
+    fn on_link_picker_key(&mut self, key: KeyEvent, action: Option<Action>) {
+        let Some(picker) = self.link_picker.as_mut() else {
+            return;
+        };
+        match (action, key.code) {
+            (Some(Action::NextEntry), _) | (_, KeyCode::Down) => {
+                picker.selected = (picker.selected + 1) % picker.links.len();
+            }
+            (Some(Action::PreviousEntry), _) | (_, KeyCode::Up) => {
+                picker.selected = (picker.selected + picker.links.len() - 1) % picker.links.len();
+            }
+            (_, KeyCode::Enter) => {
+                // Broken links have nowhere to go, keep the popup open
+                if let Some(target) = picker.links[picker.selected].target {
+                    self.link_picker = None;
+                    self.open_entry(target);
+                }
+            }
+            _ => self.link_picker = None,
+        }
+    }
+
+    /// Restricts the entry list to entries matching `search`, or shows all for an empty one
+    fn apply_search(&mut self, search: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let query = Query::parse(search)?;
+        self.matches = match search.trim() {
+            "" => None,
+            _ => Some(self.store.search(&query)?.iter().map(Entry::get_id).collect()),
+        };
+        self.search = search.trim().to_string();
+        let selected = self.selected_entry().map(Entry::get_id);
+        self.entries.set_items(self.filtered_entries());
+        let index = self.entries.items.iter().position(|e| Some(e.get_id()) == selected);
+        self.select_entry(index.or(if self.entries.items.is_empty() { None } else { Some(0) }));
+        Ok(())
+    }
+
+    /// Saved search the entry list is restricted to
+    pub fn active_saved_search(&self) -> Option<usize> {
+        self.saved_searches.iter().position(|(s, _)| !self.search.is_empty() && s.query.trim() == self.search)
+    }
+
+    /// Applies the saved search after the active one, skipping those that can't run. After the last
+    /// one all entries are shown again.
+    fn next_saved_search(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let next = self.active_saved_search().map_or(0, |i| i + 1);
+        let query = self.saved_searches[next.min(self.saved_searches.len())..]
+            .iter()
+            .find(|(_, count)| count.is_ok())
+            .map(|(s, _)| s.query.clone())
+            .unwrap_or_default();
+        self.apply_search(&query)
+    }
+
+    fn on_search_key(&mut self, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(search_box) = self.search_box.as_mut() else {
+            return Ok(());
+        };
+        match key.code {
+            KeyCode::Char(c) => search_box.input.push(c),
+            KeyCode::Backspace => {
+                search_box.input.pop();
+            }
+            KeyCode::Enter if search_box.error.is_none() => {
+                self.search_box = None;
+                return Ok(());
+            }
+            KeyCode::Esc => {
+                self.search_box = None;
+                return self.apply_search("");
+            }
+            _ => return Ok(()),
+        }
+        // Keep showing the last results while the query doesn't parse
+        let input = search_box.input.clone();
+        search_box.error = Query::parse(&input).err();
+        if search_box.error.is_none() {
+            self.apply_search(&input)?;
+        }
+        Ok(())
+    }
+
+    fn open_template_picker(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(db) = self.store.as_db() else {
+            return Ok(());
+        };
+        self.template_picker = Some(TemplatePicker {
+            templates: db.get_templates()?,
+            selected: 0,
+            answers: None,
+            input: String::new(),
+            daily: false,
+        });
+        Ok(())
+    }
+
+    fn on_template_picker_key(&mut self, key: KeyEvent, action: Option<Action>) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(picker) = self.template_picker.as_mut() else {
+            return Ok(());
+        };
+        let count = picker.templates.len();
+        match (&mut picker.answers, action, key.code) {
+            (None, Some(Action::NextEntry), _) | (None, _, KeyCode::Down) if count > 0 => {
+                picker.selected = (picker.selected + 1) % count;
+            }
+            (None, Some(Action::PreviousEntry), _) | (None, _, KeyCode::Up) if count > 0 => {
+                picker.selected = (picker.selected + count - 1) % count;
+            }
+            (None, _, KeyCode::Enter) if count > 0 => picker.answers = Some(Vec::new()),
+            (Some(_), _, KeyCode::Char(c)) => picker.input.push(c),
+            (Some(_), _, KeyCode::Backspace) => {
+                picker.input.pop();
+            }
+            (Some(answers), _, KeyCode::Enter) => answers.push(std::mem::take(&mut picker.input)),
+            _ => {
+                self.template_picker = None;
+                return Ok(());
+            }
+        }
+        if picker.answers.is_some() && picker.question().is_none() {
+            match self.template_picker.take() {
+                Some(picker) if picker.daily => {
+                    let answers = picker.template().map(|t| t.prompts()).unwrap_or_default();
+                    let answers = answers.into_iter().zip(picker.answers.unwrap_or_default()).collect();
+                    self.open_daily_entry(&answers)?;
+                }
+                Some(picker) => self.new_entry_from_template(picker),
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Current time, on the day the app considers today
+    fn now(&self) -> DateTime<FixedOffset> {
+        let now = chrono::Local::now().fixed_offset();
+        now.offset().from_local_datetime(&self.today.and_time(now.time())).single().unwrap_or(now)
+    }
+
+    /// Opens today's daily entry for editing, asking the questions of the daily template when it's created
+    fn open_today(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(db) = self.store.as_db() else {
+            return Ok(());
+        };
+        if db.find_daily_entry(self.today)?.is_none() {
+            if let Some(template) = db.daily_template()?.filter(|t| !t.prompts().is_empty()) {
+                self.template_picker = Some(TemplatePicker {
+                    templates: vec![template],
+                    selected: 0,
+                    answers: Some(Vec::new()),
+                    input: String::new(),
+                    daily: true,
+                });
+                return Ok(());
+            }
+        }
+        self.open_daily_entry(&HashMap::new())
+    }
+
+    fn open_daily_entry(&mut self, answers: &HashMap<String, String>) -> Result<(), Box<dyn std::error::Error>> {
+        let now = self.now();
+        let Some(db) = self.store.as_db() else {
+            return Ok(());
+        };
+        let entry = db.open_daily_entry(now, answers)?;
+        self.refresh_entries()?;
+        self.open_entry(entry.get_id());
+        self.focus = Field::Content;
+        self.input_mode = InputMode::Editing;
+        Ok(())
+    }
+
+    /// Starts a new entry filled in from the chosen template
+    fn new_entry_from_template(&mut self, picker: TemplatePicker) {
+        let Some(template) = picker.template() else {
+            return;
+        };
+        let answers: HashMap<String, String> =
+            template.prompts().into_iter().zip(picker.answers.clone().unwrap_or_default()).collect();
+        let entry = template.render(self.today, &answers);
+        self.new_entry();
+        self.title = entry.get_title();
+        self.content = entry.get_content();
+        self.tags = entry
+            .get_tags()
+            .unwrap_or_default()
+            .iter()
+            .map(|t| t.get_tag())
+            .collect::<Vec<String>>()
+            .join(",");
+        self.focus = Field::Content;
+    }
+
+    /// Reads the entries and selects the first one
+    fn load_entries(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.refresh_entries()?;
+        self.select_entry(if self.entries.items.is_empty() { None } else { Some(0) });
+        Ok(())
+    }
+
+    /// Handles a key while the passphrase popup is open. Returns whether to quit.
+    fn on_passphrase_key(&mut self, key: KeyEvent) -> Result<bool, Box<dyn std::error::Error>> {
+        let Some(prompt) = self.passphrase_prompt.as_mut() else {
+            return Ok(false);
+        };
+        match key.code {
+            KeyCode::Char(c) => prompt.input.push(c),
+            KeyCode::Backspace => {
+                prompt.input.pop();
+            }
+            // Without the passphrase there is nothing to show
+            KeyCode::Esc if prompt.step == PassphraseStep::Unlock => return Ok(true),
+            KeyCode::Esc => self.passphrase_prompt = None,
+            KeyCode::Enter => {
+                let input = std::mem::take(&mut prompt.input);
+                match prompt.step.clone() {
+                    PassphraseStep::Unlock => match self.store.as_db().map(|db| db.unlock(&input)) {
+                        None | Some(Ok(())) => {
+                            self.passphrase_prompt = None;
+                            // Keeps text being edited when the journal was locked from elsewhere
+                            let selected = self.selected_entry().map(Entry::get_id);
+                            self.refresh_entries()?;
+                            self.reselect(selected);
+                        }
+                        Some(Err(CryptoError::WrongPassphrase)) => prompt.error = Some("Wrong passphrase".to_string()),
+                        Some(Err(e)) => prompt.error = Some(e.to_string()),
+                    },
+                    PassphraseStep::New => {
+                        prompt.step = PassphraseStep::Confirm(input);
+                        prompt.error = None;
                     }
-                    _ => {}
-                },
-                InputMode::Editing => match key.code {
-                    KeyCode::Enter => {
-                        app.messages.push(app.input.drain(..).collect());
+                    PassphraseStep::Confirm(first) if first != input => {
+                        prompt.step = PassphraseStep::New;
+                        prompt.error = Some("Passphrases don't match".to_string());
                     }
-                    KeyCode::Char(c) => {
-                        app.input.push(c);
+                    PassphraseStep::Confirm(_) => {
+                        self.passphrase_prompt = None;
+                        if let Some(db) = self.store.as_db() {
+                            db.set_passphrase(Some(input.as_str()).filter(|p| !p.is_empty()))?;
+                        }
                     }
-                    KeyCode::Backspace => {
-                        app.input.pop();
+                }
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    fn on_key(&mut self, key: KeyEvent) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.passphrase_prompt.is_some() {
+            return self.on_passphrase_key(key);
+        }
+        if self.search_box.is_some() {
+            self.on_search_key(key)?;
+            return Ok(false);
+        }
+        let editing = matches!(self.input_mode, InputMode::Editing);
+        let action = self.config.keymap.action_for(&key, editing);
+        if self.show_help {
+            // Entry navigation scrolls the help overlay, any other key closes it except quit which still exits
+            match action {
+                Some(Action::NextEntry) => self.help_scroll += 1,
+                Some(Action::PreviousEntry) => self.help_scroll = self.help_scroll.saturating_sub(1),
+                Some(Action::Quit) => return Ok(true),
+                _ => self.show_help = false,
+            }
+            return Ok(false);
+        }
+        if self.link_picker.is_some() {
+            self.on_link_picker_key(key, action);
+            return Ok(false);
+        }
+        if self.template_picker.is_some() {
+            self.on_template_picker_key(key, action)?;
+            return Ok(false);
+        }
+        if self.tab == Tab::Dashboard
+            && !matches!(action, Some(Action::Quit | Action::NextTab | Action::ToggleHelp | Action::ChangePassphrase))
+        {
+            return Ok(false);
+        }
+        match self.input_mode {
+            InputMode::Normal => match action {
+                Some(Action::StartEditing) => {
+                    self.focus = Field::Title;
+                    self.input_mode = InputMode::Editing;
+                }
+                Some(Action::Quit) => {
+                    return Ok(true);
+                }
+                Some(Action::NewEntry) => self.new_entry(),
+                Some(Action::NextEntry) => self.next_entry(),
+                Some(Action::PreviousEntry) => self.previous_entry(),
+                Some(Action::NextDay) => self.move_date_filter(1),
+                Some(Action::PreviousDay) => self.move_date_filter(-1),
+                Some(Action::NextWeek) => self.move_date_filter(7),
+                Some(Action::PreviousWeek) => self.move_date_filter(-7),
+                Some(Action::JumpToToday) => self.set_date_filter(Some(self.today)),
+                Some(Action::ClearDateFilter) => self.set_date_filter(None),
+                Some(Action::NextTab) => {
+                    self.tab = self.tab.next();
+                }
+                Some(Action::ToggleHelp) => {
+                    self.show_help = true;
+                    self.help_scroll = 0;
+                }
+                Some(Action::ChangePassphrase) if self.store.as_db().is_some() => {
+                    self.passphrase_prompt = Some(PassphrasePrompt::new(PassphraseStep::New));
+                }
+                Some(Action::FollowLink) => self.follow_link()?,
+                Some(Action::NewFromTemplate) => self.open_template_picker()?,
+                Some(Action::OpenToday) => self.open_today()?,
+                Some(Action::NextSavedSearch) => self.next_saved_search()?,
+                Some(Action::TogglePin) => self.toggle_flag(Flag::Pinned)?,
+                Some(Action::ToggleStar) => self.toggle_flag(Flag::Starred)?,
+                Some(Action::ToggleArchive) => self.toggle_flag(Flag::Archived)?,
+                Some(Action::Search) => {
+                    self.search_box = Some(SearchBox {
+                        input: self.search.clone(),
+                        error: None,
+                    });
+                }
+                _ => {}
+            },
+            InputMode::Editing => match (action, key.code) {
+                (Some(Action::Submit), _) => {
+                    self.save()?;
+                    self.input_mode = InputMode::Normal;
+                }
+                (Some(Action::StopEditing), _) => {
+                    self.input_mode = InputMode::Normal;
+                }
+                (Some(Action::NextField), _) => {
+                    self.focus = self.focus.next();
+                }
+                (_, KeyCode::Enter) if self.focus == Field::Content => {
+                    self.content.push('\n');
+                }
+                (_, KeyCode::Enter) => {
+                    self.focus = self.focus.next();
+                }
+                (_, KeyCode::Char(c)) => {
+                    self.focused_field().push(c);
+                }
+                (_, KeyCode::Backspace) => {
+                    self.focused_field().pop();
+                }
+                _ => {}
+            },
+        }
+        Ok(false)
+    }
+
+    fn on_mouse(&mut self, mouse: MouseEvent) -> Result<(), Box<dyn std::error::Error>> {
+        // The dashboard has nothing to click on
+        if self.tab != Tab::Journal || self.passphrase_prompt.is_some()
+            || self.link_picker.is_some()
+            || self.template_picker.is_some()
+            || self.search_box.is_some()
+        {
+            return Ok(());
+        }
+        let hit = |area: Rect| contains(area, mouse.column, mouse.row);
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if self.is_on_split(mouse.column, mouse.row) {
+                    self.resizing = true;
+                } else if hit(self.areas.saved_searches) {
+                    // Clicking the active saved search again shows all entries
+                    let index = mouse.row.saturating_sub(self.areas.saved_searches.y + 1) as usize;
+                    let query = match self.saved_searches.get(index) {
+                        Some(_) if self.active_saved_search() == Some(index) => String::new(),
+                        Some((search, Ok(_))) => search.query.clone(),
+                        Some((_, Err(_))) | None => return Ok(()),
+                    };
+                    self.apply_search(&query)?;
+                } else if hit(self.areas.entries) {
+                    // Rows between the borders map onto the visible part of the list
+                    let list = self.areas.entries;
+                    let index = self.list_offset + mouse.row.saturating_sub(list.y + 1) as usize;
+                    let inside = mouse.row > list.y && mouse.row + 1 < list.y + list.height;
+                    if inside && index < self.entries.items.len() {
+                        self.select_entry(Some(index));
                     }
-                    KeyCode::Esc => {
-                        app.input_mode = InputMode::Normal;
+                } else {
+                    let field = [
+                        (self.areas.title, Field::Title),
+                        (self.areas.content, Field::Content),
+                        (self.areas.tags, Field::Tags),
+                    ]
+                    .into_iter()
+                    .find(|(area, _)| hit(*area));
+                    if let Some((_, field)) = field {
+                        self.focus = field;
+                        self.input_mode = InputMode::Editing;
                     }
-                    _ => {}
-                },
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) if self.resizing => {
+                let main = self.areas.main;
+                let max = main.width.saturating_sub(MIN_EDITOR_WIDTH).max(MIN_SIDEBAR_WIDTH);
+                self.sidebar_width = (mouse.column.saturating_sub(main.x) + 1).clamp(MIN_SIDEBAR_WIDTH, max);
             }
+            MouseEventKind::Up(MouseButton::Left) => {
+                self.resizing = false;
+            }
+            MouseEventKind::ScrollDown if hit(self.areas.entries) => self.next_entry(),
+            MouseEventKind::ScrollUp if hit(self.areas.entries) => self.previous_entry(),
+            MouseEventKind::ScrollDown if hit(self.areas.content) => self.scroll_content(false),
+            MouseEventKind::ScrollUp if hit(self.areas.content) => self.scroll_content(true),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The split is the right border of the sidebar
+    fn is_on_split(&self, column: u16, row: u16) -> bool {
+        let main = self.areas.main;
+        column == main.x + self.sidebar_width.min(main.width).saturating_sub(1)
+            && row >= main.y
+            && row < main.y + main.height
+    }
+}
+
+fn contains(area: Rect, column: u16, row: u16) -> bool {
+    column >= area.x && column < area.x + area.width && row >= area.y && row < area.y + area.height
+}
+
+impl Default for App {
+    fn default() -> App {
+        App::new(Box::new(Db::new("journal.db")), Config::default())
+    }
+}
+
+
+pub fn run_app<B: Backend, E: EventSource>(
+    terminal: &mut Terminal<B>,
+    mut app: App,
+    events: &mut E,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let time_format = match app.store.as_db() {
+        Some(db) => {
+            db.initialize_db()?;
+            db.time_format()?
+        }
+        None => TimeFormat::default(),
+    };
+    app.time_format = app.config.apply_time_overrides(time_format);
+    if app.store.as_db().map(Db::is_locked).transpose()?.unwrap_or(false) {
+        app.passphrase_prompt = Some(PassphrasePrompt::new(PassphraseStep::Unlock));
+    } else {
+        app.load_entries()?;
+    }
+    app.watch_changes();
+    loop {
+        terminal.draw(|f| ui(f, &mut app))?;
+
+        // Errors of the journal are shown instead of closing the editor with the text in it
+        let result = match events.next_event()? {
+            None => return Ok(()),
+            Some(Input::Terminal(Event::Key(key))) => {
+                app.status = None;
+                match app.on_key(key) {
+                    Ok(true) => return Ok(()),
+                    result => result.map(|_| ()),
+                }
+            }
+            Some(Input::Terminal(Event::Mouse(mouse))) => app.on_mouse(mouse),
+            Some(Input::Idle) => app.reload_changes(),
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            app.status = Some(e.to_string());
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::ScriptedEvents;
+    use journaldb::store::MemoryStore;
+    use crossterm::event::KeyModifiers;
+    use std::{env, fs, path::PathBuf};
+    use tui::{backend::TestBackend, buffer::Buffer};
+
+    const WIDTH: u16 = 80;
+    const HEIGHT: u16 = 24;
+
+    fn test_app(config: Config) -> App {
+        test_app_with_db(Db::new(":memory:"), config)
+    }
+
+    fn test_app_with_db(db: impl JournalStore + 'static, mut config: Config) -> App {
+        // Entries are written at the time the tests run, keep that out of the snapshots
+        config.time_format = Some("<time>".to_string());
+        let mut app = App::new(Box::new(db), config);
+        app.today = NaiveDate::from_ymd_opt(2001, 2, 15).unwrap();
+        app
+    }
+
+    fn key(code: KeyCode) -> Event {
+        Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn ctrl(c: char) -> Event {
+        Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL))
+    }
+
+    fn typed(text: &str) -> Vec<Event> {
+        text.chars().map(|c| key(KeyCode::Char(c))).collect()
+    }
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16) -> Event {
+        Event::Mouse(MouseEvent { kind, column, row, modifiers: KeyModifiers::NONE })
+    }
+
+    /// Events that write a new entry through the editor with the default keymap
+    fn write_entry(title: &str, content: &str, tags: &str) -> Vec<Event> {
+        let mut events = vec![key(KeyCode::Char('n'))];
+        events.extend(typed(title));
+        events.push(key(KeyCode::Tab));
+        events.extend(typed(content));
+        events.push(key(KeyCode::Tab));
+        events.extend(typed(tags));
+        events.push(ctrl('s'));
+        events
+    }
+
+    fn run(app: App, events: Vec<Event>) -> Buffer {
+        let mut terminal = Terminal::new(TestBackend::new(WIDTH, HEIGHT)).unwrap();
+        run_app(&mut terminal, app, &mut ScriptedEvents::new(events)).unwrap();
+        terminal.backend().buffer().clone()
+    }
+
+    fn render_text(buffer: &Buffer) -> String {
+        let mut text = String::new();
+        for y in 0..buffer.area.height {
+            let line: String = (0..buffer.area.width)
+                .map(|x| buffer.get(x, y).symbol.as_str())
+                .collect();
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Compares the rendered buffer with `snapshots/<name>.txt`.
+    /// Run with `UPDATE_SNAPSHOTS=1` to accept a changed rendering.
+    fn assert_snapshot(name: &str, buffer: &Buffer) {
+        let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "snapshots", &format!("{}.txt", name)]
+            .iter()
+            .collect();
+        let actual = render_text(buffer);
+        if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, &actual).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&path)
+            .unwrap_or_else(|_| panic!("missing snapshot {}, run with UPDATE_SNAPSHOTS=1", path.display()));
+        assert_eq!(actual, expected, "rendering differs from snapshot {}", name);
+    }
+
+    #[test]
+    fn empty_journal() {
+        let buffer = run(test_app(Config::default()), vec![]);
+        assert_snapshot("empty_journal", &buffer);
+    }
+
+    #[test]
+    fn write_entries() {
+        let mut events = write_entry("First", "Dear diary", "foo,bar");
+        events.extend(write_entry("Second", "line one\nline two", ""));
+        let buffer = run(test_app(Config::default()), events);
+        assert_snapshot("write_entries", &buffer);
+    }
+
+    #[test]
+    fn editing_highlights_focused_field() {
+        let mut events = write_entry("First", "Dear diary", "");
+        events.push(key(KeyCode::Char('e')));
+        events.push(key(KeyCode::Tab));
+        let buffer = run(test_app(Config::default()), events);
+        let theme = Theme::default();
+        // The content field is focused, the title is not
+        assert_eq!(buffer.get(31, 6).fg, theme.editing);
+        assert_ne!(buffer.get(31, 3).fg, theme.editing);
+        assert_snapshot("editing_content", &buffer);
+    }
+
+    #[test]
+    fn encrypt_and_unlock() {
+        let path = env::temp_dir().join(format!("journaltui-encrypted-{}.db", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let open = || test_app_with_db(Db::new(path.to_str().unwrap()), Config::default());
+
+        let mut events = write_entry("Secret", "hidden", "");
+        events.push(key(KeyCode::Char('P')));
+        events.extend(typed("pw"));
+        events.push(key(KeyCode::Enter));
+        events.extend(typed("pw"));
+        events.push(key(KeyCode::Enter));
+        run(open(), events);
+
+        let mut events = typed("nope");
+        events.push(key(KeyCode::Enter));
+        events.extend(typed("pw"));
+        let buffer = run(open(), events.clone());
+        assert!(!render_text(&buffer).contains("Secret"));
+        assert_snapshot("passphrase_wrong", &buffer);
+
+        events.push(key(KeyCode::Enter));
+        let buffer = run(open(), events);
+        assert!(render_text(&buffer).contains("1: Secret"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    /// A journal that can't be written to, e.g. because another process holds the lock
+    struct ReadOnlyStore;
+
+    impl JournalStore for ReadOnlyStore {
+        fn update_entries(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+            Ok(())
+        }
+
+        fn get_entries(&self) -> Vec<Entry> {
+            Vec::new()
+        }
+
+        fn create_entry(&mut self, _: &mut Entry) -> Result<(), Box<dyn std::error::Error>> {
+            Err("the journal is read-only".into())
+        }
+
+        fn edit_entry(&mut self, _: &mut Entry) -> Result<(), Box<dyn std::error::Error>> {
+            Err("the journal is read-only".into())
+        }
+
+        fn delete_entry(&mut self, _: &Entry) -> Result<(), Box<dyn std::error::Error>> {
+            Err("the journal is read-only".into())
+        }
+
+        fn set_flag(&mut self, _: u32, _: Flag, _: bool) -> Result<(), Box<dyn std::error::Error>> {
+            Err("the journal is read-only".into())
+        }
+    }
+
+    #[test]
+    fn errors_keep_the_editor_open() {
+        let buffer = run(test_app_with_db(ReadOnlyStore, Config::default()), write_entry("Draft", "unsaved", ""));
+        let text = render_text(&buffer);
+        assert!(text.contains("Error: the journal is read-only"));
+        assert!(text.contains("unsaved"));
+        assert_snapshot("save_error", &buffer);
+    }
+
+    #[test]
+    fn memory_store() {
+        let mut events = write_entry("First", "Dear diary", "foo");
+        events.push(key(KeyCode::Char('p')));
+        // Passphrases and templates need a SQLite journal
+        events.push(key(KeyCode::Char('P')));
+        events.push(key(KeyCode::Char('T')));
+        let buffer = run(test_app_with_db(MemoryStore::new(), Config::default()), events);
+        let text = render_text(&buffer);
+        assert!(text.contains("> 1: ^ First"));
+        assert!(text.contains("Dear diary"));
+        assert!(!text.contains("passphrase"));
+    }
+
+    #[test]
+    fn passphrases_must_match() {
+        let mut events = vec![key(KeyCode::Char('P'))];
+        events.extend(typed("one"));
+        events.push(key(KeyCode::Enter));
+        events.extend(typed("two"));
+        events.push(key(KeyCode::Enter));
+        let buffer = run(test_app(Config::default()), events);
+        let text = render_text(&buffer);
+        assert!(text.contains("New passphrase"));
+        assert!(text.contains("Passphrases don't match"));
+    }
+
+    #[test]
+    fn follow_links() {
+        let mut events = write_entry("Target", "x", "");
+        events.extend(write_entry("Source", "see [[Target]] and [[Nowhere]]", ""));
+        events.extend(write_entry("Single", "only [[Source]]", ""));
+        // Jump from Single to Source, which has two links to choose from
+        events.push(key(KeyCode::Enter));
+        events.push(key(KeyCode::Enter));
+        let buffer = run(test_app(Config::default()), events.clone());
+        assert_snapshot("link_picker", &buffer);
+
+        events.push(key(KeyCode::Enter));
+        let buffer = run(test_app(Config::default()), events);
+        assert!(render_text(&buffer).contains("│Target "));
+    }
+
+    #[test]
+    fn new_entry_from_template() {
+        let app = || {
+            let db = Db::new(":memory:");
+            db.initialize_db().unwrap();
+            for (name, title) in [("review", "Week {{week}}"), ("standup", "{{date}} Standup")] {
+                db.save_template(&Template {
+                    name: name.to_string(),
+                    title: title.to_string(),
+                    content: "{{weekday}}: {{prompt:What did you do?}}".to_string(),
+                    tags: vec!["work".to_string()],
+                })
+                .unwrap();
+            }
+            test_app_with_db(db, Config::default())
+        };
+        let mut events = vec![key(KeyCode::Char('T')), key(KeyCode::Down), key(KeyCode::Enter)];
+        events.extend(typed("Reviews"));
+        let buffer = run(app(), events.clone());
+        assert_snapshot("template_question", &buffer);
+
+        events.push(key(KeyCode::Enter));
+        events.push(ctrl('s'));
+        let text = render_text(&run(app(), events));
+        assert!(text.contains("│2001-02-15 Standup "));
+        assert!(text.contains("│Thursday: Reviews "));
+        assert!(text.contains("│work "));
+    }
+
+    #[test]
+    fn open_today() {
+        let db = Db::new(":memory:");
+        db.initialize_db().unwrap();
+        db.save_template(&Template {
+            name: "daily".to_string(),
+            title: String::new(),
+            content: "Mood: {{prompt:Mood?}}".to_string(),
+            tags: Vec::new(),
+        })
+        .unwrap();
+        db.set_setting(journaldb::daily::TEMPLATE_SETTING, "daily").unwrap();
+        db.set_setting(journaldb::daily::TITLE_SETTING, "Day %d.%m.").unwrap();
+        let mut events = vec![key(KeyCode::Char('d'))];
+        events.extend(typed("fine"));
+        events.extend([key(KeyCode::Enter), key(KeyCode::Char('!')), key(KeyCode::Esc)]);
+        // Opening it again finds the same entry instead of asking again, unsaved changes are gone
+        events.extend([key(KeyCode::Char('d')), key(KeyCode::Char('?')), ctrl('s')]);
+        let text = render_text(&run(test_app_with_db(db, Config::default()), events));
+        assert!(text.contains("│> 1: Day 15.02."));
+        assert!(!text.contains(" 2: "));
+        assert!(text.contains("│Mood: fine? "));
+    }
+
+    #[test]
+    fn search_entries() {
+        let mut events = write_entry("Retro", "went well", "work");
+        events.extend(write_entry("Garden", "roses", "home"));
+        events.extend(write_entry("Standup", "went ok", "work,draft"));
+        events.push(key(KeyCode::Char('/')));
+        events.extend(typed("went -tag:draft OR (roses"));
+        let buffer = run(test_app(Config::default()), events.clone());
+        assert_snapshot("search_error", &buffer);
+
+        // Fixing the query updates the list, Enter keeps it
+        events.extend([key(KeyCode::Char(')')), key(KeyCode::Enter)]);
+        let text = render_text(&run(test_app(Config::default()), events.clone()));
+        assert!(text.contains("┌Entries matching went -tag:d"));
+        assert!(text.contains("1: Retro") && text.contains("2: Garden") && !text.contains("3: Standup"));
+
+        // Esc in the search box shows all entries again
+        events.extend([key(KeyCode::Char('/')), key(KeyCode::Esc)]);
+        let text = render_text(&run(test_app(Config::default()), events.clone()));
+        assert!(text.contains("3: Standup"));
+
+        // Saving an entry the search hides shows all entries again
+        events.extend([key(KeyCode::Char('/')), key(KeyCode::Char('x')), key(KeyCode::Enter)]);
+        events.extend(write_entry("Lunch", "soup", ""));
+        let text = render_text(&run(test_app(Config::default()), events));
+        assert!(text.contains("┌Entries─") && text.contains("> 4: Lunch") && text.contains("soup"));
+    }
+
+    #[test]
+    fn help_overlay_lists_vim_keymap() {
+        let config = Config::parse("preset = \"vim\"").unwrap();
+        let buffer = run(test_app(config.clone()), vec![key(KeyCode::Char('?'))]);
+        assert_snapshot("help_vim", &buffer);
+
+        // Actions that don't fit are reached by scrolling
+        let events = vec![key(KeyCode::Char('?')), key(KeyCode::Char('j')), key(KeyCode::Char('j'))];
+        let text = render_text(&run(test_app(config), events));
+        assert!(text.contains("apply the next saved search"));
+        assert!(!text.contains("│q "));
+    }
+
+    #[test]
+    fn saved_searches_sidebar() {
+        let app = || {
+            let db = Db::new(":memory:");
+            db.initialize_db().unwrap();
+            db.save_search("work", "tag:work").unwrap();
+            db.save_search("not work", "-tag:work").unwrap();
+            test_app_with_db(db, Config::default())
+        };
+        let mut events = write_entry("Retro", "went well", "work");
+        events.extend(write_entry("Garden", "roses", "home"));
+        events.extend(write_entry("Standup", "went ok", "work"));
+        events.extend([key(KeyCode::Char('s')), key(KeyCode::Char('s'))]);
+        assert_snapshot("saved_searches", &run(app(), events.clone()));
+
+        // Past the last saved search all entries are shown again
+        events.push(key(KeyCode::Char('s')));
+        let text = render_text(&run(app(), events.clone()));
+        assert!(text.contains("┌Entries─") && text.contains("3: Standup"));
+
+        // Clicking a saved search applies it, clicking it again shows all entries
+        events.push(mouse(MouseEventKind::Down(MouseButton::Left), 5, 20));
+        let text = render_text(&run(app(), events.clone()));
+        assert!(text.contains("┌Entries matching -tag:work") && text.contains("> not work (1)"));
+        events.push(mouse(MouseEventKind::Down(MouseButton::Left), 5, 20));
+        assert!(render_text(&run(app(), events)).contains("┌Entries─"));
+    }
+
+    #[test]
+    fn flags_mark_and_move_entries() {
+        let app = || {
+            let db = Db::new(":memory:");
+            db.initialize_db().unwrap();
+            test_app_with_db(db, Config::default())
+        };
+        let mut events = write_entry("Retro", "went well", "work");
+        events.extend(write_entry("Garden", "roses", "home"));
+        events.extend(write_entry("Standup", "went ok", "work"));
+        events.extend([key(KeyCode::Char('p')), key(KeyCode::Char('*'))]);
+        let text = render_text(&run(app(), events.clone()));
+        let line = |text: &str, entry: &str| text.lines().position(|l| l.contains(entry));
+        assert!(line(&text, "> 3: ^* Standup") < line(&text, "1: Retro"));
+
+        // Archived entries leave the list, searching brings them back
+        events.extend([key(KeyCode::Down), key(KeyCode::Char('a'))]);
+        let text = render_text(&run(app(), events.clone()));
+        assert!(!text.contains("Retro") && text.contains("> 3: ^* Standup"));
+        events.push(key(KeyCode::Char('/')));
+        events.extend(typed("is:archived"));
+        events.push(key(KeyCode::Enter));
+        assert!(render_text(&run(app(), events)).contains("1: ~ Retro"));
+    }
+
+    #[test]
+    fn vim_navigation() {
+        let config = Config::parse("preset = \"vim\"").unwrap();
+        let mut events = vec![];
+        for title in ["One", "Two", "Three"] {
+            events.push(key(KeyCode::Char('o')));
+            events.extend(typed(title));
+            events.push(ctrl('s'));
+        }
+        events.extend(typed("kkj"));
+        let buffer = run(test_app(config), events);
+        assert_snapshot("vim_navigation", &buffer);
+    }
+
+    #[test]
+    fn calendar_filters_by_day() {
+        let events = vec![
+            key(KeyCode::Char('t')),
+            key(KeyCode::Right),
+            key(KeyCode::Char(']')),
+        ];
+        let buffer = run(test_app(Config::default()), events);
+        assert_snapshot("calendar_day", &buffer);
+    }
+
+    #[test]
+    fn dashboard_tab() {
+        let buffer = run(test_app(Config::default()), vec![key(KeyCode::Tab)]);
+        assert_snapshot("dashboard_empty", &buffer);
+    }
+
+    #[test]
+    fn mouse_selects_and_resizes() {
+        let mut events = write_entry("First", "", "");
+        events.extend(write_entry("Second", "", ""));
+        events.extend([
+            // Click the first row of the entry list
+            mouse(MouseEventKind::Down(MouseButton::Left), 3, 13),
+            mouse(MouseEventKind::Up(MouseButton::Left), 3, 13),
+            // Drag the split between sidebar and editor to the right
+            mouse(MouseEventKind::Down(MouseButton::Left), 29, 5),
+            mouse(MouseEventKind::Drag(MouseButton::Left), 40, 5),
+            mouse(MouseEventKind::Up(MouseButton::Left), 40, 5),
+        ]);
+        let buffer = run(test_app(Config::default()), events);
+        assert_snapshot("mouse_resize", &buffer);
+    }
+
+    #[test]
+    fn reloads_changes_from_other_processes() {
+        let path = env::temp_dir().join(format!("journaltui-changes-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let other = Db::new(path);
+        other.initialize_db().unwrap();
+        for title in ["First", "Second"] {
+            other.create_entry(&mut Entry::new(title.to_string(), String::new(), None)).unwrap();
+        }
+        let mut app = test_app_with_db(Db::new(path), Config::default());
+        app.load_entries().unwrap();
+        app.watch_changes();
+        app.next_entry();
+
+        other.create_entry(&mut Entry::new("Third".to_string(), String::new(), None)).unwrap();
+        app.reload_changes().unwrap();
+        let titles: Vec<String> = app.entries.items.iter().map(Entry::get_title).collect();
+        assert_eq!(titles, vec!["First", "Second", "Third"]);
+        assert_eq!(app.selected_entry().map(Entry::get_title).as_deref(), Some("Second"));
+
+        let mut second = other.get_entry_by_id(app.selected_entry().unwrap().get_id()).unwrap();
+        second.set_content("Written elsewhere".to_string());
+        other.edit_entry(&mut second).unwrap();
+        app.reload_changes().unwrap();
+        assert_eq!(app.content, "Written elsewhere");
+
+        // Encrypting elsewhere locks the journal here too, keeping the text being edited
+        app.input_mode = InputMode::Editing;
+        app.content = "Not saved yet".to_string();
+        other.set_passphrase(Some("pw")).unwrap();
+        app.reload_changes().unwrap();
+        assert!(app.passphrase_prompt.is_some());
+        for event in typed("pw").into_iter().chain([key(KeyCode::Enter)]) {
+            let Event::Key(key) = event else { unreachable!() };
+            app.on_key(key).unwrap();
+        }
+        assert!(app.passphrase_prompt.is_none());
+        assert_eq!(app.content, "Not saved yet");
+        assert_eq!(app.entries.items.len(), 3);
+
+        drop((app, other));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path, suffix));
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use chrono::{Datelike, Duration, NaiveDate};
+use tui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    widgets::{Block, Widget},
+};
+
+/// Width of a day cell, including the separating space
+const CELL_WIDTH: u16 = 3;
+
+/// Rows needed to draw any month: the month name, the weekday header and six weeks
+pub const CALENDAR_HEIGHT: u16 = 8;
+pub const CALENDAR_WIDTH: u16 = 7 * CELL_WIDTH;
+
+/// What the shading of a day represents
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Intensity {
+    Count,
+    Words,
+}
+
+/// A month view that shades each day by how much was written on it
+pub struct Calendar<'a> {
+    /// Day the month is shown for, highlighted unless the calendar has no selection
+    cursor: NaiveDate,
+    selected: bool,
+    today: NaiveDate,
+    /// Amount written per day, entries or words depending on the intensity mode
+    amounts: &'a HashMap<NaiveDate, usize>,
+    heat: [Color; 3],
+    block: Option<Block<'a>>,
+}
+
+impl<'a> Calendar<'a> {
+    pub fn new(cursor: NaiveDate, today: NaiveDate, amounts: &'a HashMap<NaiveDate, usize>) -> Self {
+        Calendar {
+            cursor,
+            selected: false,
+            today,
+            amounts,
+            heat: [Color::DarkGray, Color::Green, Color::LightGreen],
+            block: None,
+        }
+    }
+
+    pub fn selected(mut self, selected: bool) -> Self {
+        self.selected = selected;
+        self
+    }
+
+    pub fn heat(mut self, heat: [Color; 3]) -> Self {
+        self.heat = heat;
+        self
+    }
+
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// Shading of a day relative to the busiest day of the shown month
+    fn heat_level(&self, amount: usize, max: usize) -> Option<usize> {
+        match amount {
+            0 => None,
+            _ => Some((amount * 3).div_ceil(max).clamp(1, 3) - 1),
+        }
+    }
+}
+
+/// First day of the month containing `date`
+pub fn month_start(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).unwrap()
+}
+
+fn days_in_month(date: NaiveDate) -> u32 {
+    let start = month_start(date);
+    let next = if start.month() == 12 {
+        NaiveDate::from_ymd_opt(start.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(start.year(), start.month() + 1, 1)
+    };
+    (next.unwrap() - start).num_days() as u32
+}
+
+impl<'a> Widget for Calendar<'a> {
+    fn render(mut self, area: Rect, buf: &mut Buffer) {
+        let area = match self.block.take() {
+            Some(block) => {
+                let inner = block.inner(area);
+                block.render(area, buf);
+                inner
+            }
+            None => area,
+        };
+        if area.width < CALENDAR_WIDTH || area.height < 2 {
+            return;
+        }
+
+        let header = self.cursor.format("%B %Y").to_string();
+        let header_x = area.x + (area.width.saturating_sub(header.len() as u16)) / 2;
+        buf.set_string(header_x, area.y, header, Style::default().add_modifier(Modifier::BOLD));
+        buf.set_string(area.x, area.y + 1, "Mo Tu We Th Fr Sa Su", Style::default().fg(Color::Gray));
+
+        let start = month_start(self.cursor);
+        let days = days_in_month(self.cursor);
+        let max = (0..days)
+            .filter_map(|d| self.amounts.get(&(start + Duration::days(d as i64))))
+            .copied()
+            .max()
+            .unwrap_or(0);
+        let offset = start.weekday().num_days_from_monday();
+        for d in 0..days {
+            let date = start + Duration::days(d as i64);
+            let slot = offset + d;
+            let x = area.x + (slot % 7) as u16 * CELL_WIDTH;
+            let y = area.y + 2 + (slot / 7) as u16;
+            if y >= area.y + area.height {
+                break;
+            }
+            let mut style = Style::default();
+            if let Some(level) = self.heat_level(self.amounts.get(&date).copied().unwrap_or(0), max) {
+                style = style.bg(self.heat[level]).fg(Color::Black);
+            }
+            if date == self.today {
+                style = style.add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+            }
+            if self.selected && date == self.cursor {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            buf.set_string(x, y, format!("{:>2}", date.day()), style);
+        }
+    }
+}
@@ -0,0 +1,624 @@
+use std::{collections::HashMap, error::Error, fmt, fs, io, path::Path};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+use tui::style::Color;
+
+use journaldb::time::{self, DisplayZone, TimeError, TimeFormat};
+
+use crate::calendar::Intensity;
+
+/// Default location of the journaltui configuration file
+pub const CONFIG_FILE: &str = "journaltui.toml";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    UnknownPreset(String),
+    UnknownAction(String),
+    InvalidKey(String),
+    InvalidColor(String),
+    InvalidIntensity(String),
+    Time(TimeError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "could not read config: {}", e),
+            ConfigError::Parse(e) => write!(f, "could not parse config: {}", e),
+            ConfigError::UnknownPreset(p) => write!(f, "unknown keymap preset '{}'", p),
+            ConfigError::UnknownAction(a) => write!(f, "unknown action '{}'", a),
+            ConfigError::InvalidKey(k) => write!(f, "invalid key '{}'", k),
+            ConfigError::InvalidColor(c) => write!(f, "invalid color '{}'", c),
+            ConfigError::Time(e) => write!(f, "{}", e),
+            ConfigError::InvalidIntensity(i) => write!(f, "invalid calendar intensity '{}', expected 'count' or 'words'", i),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl From<TimeError> for ConfigError {
+    fn from(e: TimeError) -> Self {
+        ConfigError::Time(e)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(e: toml::de::Error) -> Self {
+        ConfigError::Parse(e)
+    }
+}
+
+/// Everything a key can be bound to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    StartEditing,
+    StopEditing,
+    Submit,
+    NextField,
+    NewEntry,
+    NextEntry,
+    PreviousEntry,
+    NextDay,
+    PreviousDay,
+    NextWeek,
+    PreviousWeek,
+    JumpToToday,
+    ClearDateFilter,
+    NextTab,
+    ToggleHelp,
+    ChangePassphrase,
+    FollowLink,
+    NewFromTemplate,
+    OpenToday,
+    Search,
+    NextSavedSearch,
+    TogglePin,
+    ToggleStar,
+    ToggleArchive,
+}
+
+impl Action {
+    pub const ALL: [Action; 25] = [
+        Action::Quit,
+        Action::StartEditing,
+        Action::StopEditing,
+        Action::Submit,
+        Action::NextField,
+        Action::NewEntry,
+        Action::NextEntry,
+        Action::PreviousEntry,
+        Action::NextDay,
+        Action::PreviousDay,
+        Action::NextWeek,
+        Action::PreviousWeek,
+        Action::JumpToToday,
+        Action::ClearDateFilter,
+        Action::NextTab,
+        Action::ToggleHelp,
+        Action::ChangePassphrase,
+        Action::FollowLink,
+        Action::NewFromTemplate,
+        Action::OpenToday,
+        Action::Search,
+        Action::NextSavedSearch,
+        Action::TogglePin,
+        Action::ToggleStar,
+        Action::ToggleArchive,
+    ];
+
+    /// Name used for the action in the `[keys]` table of the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::StartEditing => "start_editing",
+            Action::StopEditing => "stop_editing",
+            Action::Submit => "submit",
+            Action::NextField => "next_field",
+            Action::NewEntry => "new_entry",
+            Action::NextEntry => "next_entry",
+            Action::PreviousEntry => "previous_entry",
+            Action::NextDay => "next_day",
+            Action::PreviousDay => "previous_day",
+            Action::NextWeek => "next_week",
+            Action::PreviousWeek => "previous_week",
+            Action::JumpToToday => "jump_to_today",
+            Action::ClearDateFilter => "clear_date_filter",
+            Action::NextTab => "next_tab",
+            Action::ToggleHelp => "toggle_help",
+            Action::ChangePassphrase => "change_passphrase",
+            Action::FollowLink => "follow_link",
+            Action::NewFromTemplate => "new_from_template",
+            Action::OpenToday => "open_today",
+            Action::Search => "search",
+            Action::NextSavedSearch => "next_saved_search",
+            Action::TogglePin => "toggle_pin",
+            Action::ToggleStar => "toggle_star",
+            Action::ToggleArchive => "toggle_archive",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::Quit => "exit",
+            Action::StartEditing => "start editing",
+            Action::StopEditing => "stop editing",
+            Action::Submit => "save the entry",
+            Action::NextField => "edit the next field",
+            Action::NewEntry => "write a new entry",
+            Action::NextEntry => "select next entry",
+            Action::PreviousEntry => "select previous entry",
+            Action::NextDay => "show entries of the next day",
+            Action::PreviousDay => "show entries of the previous day",
+            Action::NextWeek => "show entries a week later",
+            Action::PreviousWeek => "show entries a week earlier",
+            Action::JumpToToday => "show today's entries",
+            Action::ClearDateFilter => "show entries of all days",
+            Action::NextTab => "toggle the dashboard",
+            Action::ToggleHelp => "show or hide this help",
+            Action::ChangePassphrase => "set or change the passphrase",
+            Action::FollowLink => "open a [[linked]] entry",
+            Action::NewFromTemplate => "write an entry from a template",
+            Action::OpenToday => "edit today's daily entry",
+            Action::Search => "search entries, e.g. tag:work",
+            Action::NextSavedSearch => "apply the next saved search",
+            Action::TogglePin => "pin the entry to the top of the list, or unpin it",
+            Action::ToggleStar => "star the entry, or remove the star",
+            Action::ToggleArchive => "archive the entry so only searches show it, or bring it back",
+        }
+    }
+
+    /// Whether the action applies while editing. Every other action applies in normal mode.
+    pub fn while_editing(&self) -> bool {
+        matches!(self, Action::StopEditing | Action::Submit | Action::NextField)
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().copied().find(|a| a.name() == name)
+    }
+}
+
+/// A single key press, written in the config file as e.g. `q`, `Esc`, `C-n` or `M-x`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    pub fn parse(key: &str) -> Result<Self, ConfigError> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = key;
+        while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+            modifiers |= match &rest[..1] {
+                "C" => KeyModifiers::CONTROL,
+                "M" | "A" => KeyModifiers::ALT,
+                "S" => KeyModifiers::SHIFT,
+                _ => return Err(ConfigError::InvalidKey(key.to_string())),
+            };
+            rest = &rest[2..];
+        }
+        let code = match rest {
+            "Esc" => KeyCode::Esc,
+            "Enter" => KeyCode::Enter,
+            "Tab" => KeyCode::Tab,
+            "BackTab" => KeyCode::BackTab,
+            "Backspace" => KeyCode::Backspace,
+            "Delete" => KeyCode::Delete,
+            "Up" => KeyCode::Up,
+            "Down" => KeyCode::Down,
+            "Left" => KeyCode::Left,
+            "Right" => KeyCode::Right,
+            "Home" => KeyCode::Home,
+            "End" => KeyCode::End,
+            "PageUp" => KeyCode::PageUp,
+            "PageDown" => KeyCode::PageDown,
+            "Space" => KeyCode::Char(' '),
+            s if s.chars().count() == 1 => KeyCode::Char(s.chars().next().unwrap()),
+            s if s.starts_with('F') && s[1..].parse::<u8>().is_ok() => KeyCode::F(s[1..].parse().unwrap()),
+            _ => return Err(ConfigError::InvalidKey(key.to_string())),
+        };
+        Ok(KeyBinding { code, modifiers })
+    }
+
+    pub fn matches(&self, key: &KeyEvent) -> bool {
+        // Terminals report shifted characters with SHIFT set, but the character itself
+        // already carries that information.
+        let modifiers = match key.code {
+            KeyCode::Char(_) => key.modifiers - KeyModifiers::SHIFT,
+            _ => key.modifiers,
+        };
+        self.code == key.code && self.modifiers == modifiers
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "C-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "M-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "S-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            KeyCode::BackTab => write!(f, "BackTab"),
+            KeyCode::PageUp => write!(f, "PageUp"),
+            KeyCode::PageDown => write!(f, "PageDown"),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct KeyMap {
+    bindings: HashMap<Action, Vec<KeyBinding>>,
+}
+
+impl KeyMap {
+    fn from_keys(keys: &[(Action, &[&str])]) -> Self {
+        let bindings = keys
+            .iter()
+            .map(|(action, keys)| {
+                (*action, keys.iter().map(|k| KeyBinding::parse(k).unwrap()).collect())
+            })
+            .collect();
+        KeyMap { bindings }
+    }
+
+    pub fn preset(name: &str) -> Result<Self, ConfigError> {
+        let keymap = match name {
+            "default" => KeyMap::from_keys(&[
+                (Action::Quit, &["q"]),
+                (Action::StartEditing, &["e"]),
+                (Action::StopEditing, &["Esc"]),
+                (Action::Submit, &["C-s"]),
+                (Action::NextField, &["Tab"]),
+                (Action::NewEntry, &["n"]),
+                (Action::NextEntry, &["Down"]),
+                (Action::PreviousEntry, &["Up"]),
+                (Action::NextDay, &["Right"]),
+                (Action::PreviousDay, &["Left"]),
+                (Action::NextWeek, &["]"]),
+                (Action::PreviousWeek, &["["]),
+                (Action::JumpToToday, &["t"]),
+                (Action::ClearDateFilter, &["c"]),
+                (Action::NextTab, &["Tab"]),
+                (Action::ToggleHelp, &["?"]),
+                (Action::ChangePassphrase, &["P"]),
+                (Action::FollowLink, &["Enter"]),
+                (Action::NewFromTemplate, &["T"]),
+                (Action::OpenToday, &["d"]),
+                (Action::Search, &["/"]),
+                (Action::NextSavedSearch, &["s"]),
+                (Action::TogglePin, &["p"]),
+                (Action::ToggleStar, &["*"]),
+                (Action::ToggleArchive, &["a"]),
+            ]),
+            "vim" => KeyMap::from_keys(&[
+                (Action::Quit, &["q"]),
+                (Action::StartEditing, &["i", "a"]),
+                (Action::StopEditing, &["Esc", "C-["]),
+                (Action::Submit, &["C-s"]),
+                (Action::NextField, &["Tab"]),
+                (Action::NewEntry, &["o"]),
+                (Action::NextEntry, &["j", "Down"]),
+                (Action::PreviousEntry, &["k", "Up"]),
+                (Action::NextDay, &["l", "Right"]),
+                (Action::PreviousDay, &["h", "Left"]),
+                (Action::NextWeek, &["L", "]"]),
+                (Action::PreviousWeek, &["H", "["]),
+                (Action::JumpToToday, &["t"]),
+                (Action::ClearDateFilter, &["c"]),
+                (Action::NextTab, &["Tab"]),
+                (Action::ToggleHelp, &["?"]),
+                (Action::ChangePassphrase, &["P"]),
+                (Action::FollowLink, &["Enter"]),
+                (Action::NewFromTemplate, &["T"]),
+                (Action::OpenToday, &["d"]),
+                (Action::Search, &["/"]),
+                (Action::NextSavedSearch, &["s"]),
+                (Action::TogglePin, &["p"]),
+                (Action::ToggleStar, &["*"]),
+                (Action::ToggleArchive, &["A"]),
+            ]),
+            "emacs" => KeyMap::from_keys(&[
+                (Action::Quit, &["C-c", "C-q"]),
+                (Action::StartEditing, &["C-o"]),
+                (Action::StopEditing, &["C-g", "Esc"]),
+                (Action::Submit, &["C-s"]),
+                (Action::NextField, &["Tab", "M-n"]),
+                (Action::NewEntry, &["C-x"]),
+                (Action::NextEntry, &["C-n", "Down"]),
+                (Action::PreviousEntry, &["C-p", "Up"]),
+                (Action::NextDay, &["C-f", "Right"]),
+                (Action::PreviousDay, &["C-b", "Left"]),
+                (Action::NextWeek, &["M-f"]),
+                (Action::PreviousWeek, &["M-b"]),
+                (Action::JumpToToday, &["C-t"]),
+                (Action::ClearDateFilter, &["C-l"]),
+                (Action::NextTab, &["Tab"]),
+                (Action::ToggleHelp, &["?", "C-h"]),
+                (Action::ChangePassphrase, &["M-p"]),
+                (Action::FollowLink, &["Enter"]),
+                (Action::NewFromTemplate, &["M-t"]),
+                (Action::OpenToday, &["M-d"]),
+                (Action::Search, &["C-r"]),
+                (Action::NextSavedSearch, &["M-s"]),
+                (Action::TogglePin, &["M-^"]),
+                (Action::ToggleStar, &["M-*"]),
+                (Action::ToggleArchive, &["M-a"]),
+            ]),
+            _ => return Err(ConfigError::UnknownPreset(name.to_string())),
+        };
+        Ok(keymap)
+    }
+
+    pub fn action_for(&self, key: &KeyEvent, editing: bool) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .copied()
+            .filter(|a| a.while_editing() == editing)
+            .find(|a| self.keys(*a).iter().any(|k| k.matches(key)))
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyBinding] {
+        self.bindings.get(&action).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// The bound keys of an action joined for display, e.g. `j/Down`
+    pub fn describe(&self, action: Action) -> String {
+        self.keys(action)
+            .iter()
+            .map(|k| k.to_string())
+            .collect::<Vec<String>>()
+            .join("/")
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap::preset("default").unwrap()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub border: Color,
+    pub editing: Color,
+    pub selected: Color,
+    pub key_hint: Color,
+    pub help_border: Color,
+    /// Calendar shading from the least to the most written days
+    pub heat: [Color; 3],
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            border: Color::Reset,
+            editing: Color::Yellow,
+            selected: Color::LightBlue,
+            key_hint: Color::Reset,
+            help_border: Color::Cyan,
+            heat: [Color::DarkGray, Color::Green, Color::LightGreen],
+        }
+    }
+}
+
+/// Parses a color name (`yellow`, `light-blue`), a hex value (`#ff8800`) or a 256-color index
+pub fn parse_color(color: &str) -> Result<Color, ConfigError> {
+    let normalized = color.to_lowercase().replace(['-', '_', ' '], "");
+    let parsed = match normalized.as_str() {
+        "reset" | "default" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        s if s.len() == 7 && s.starts_with('#') => {
+            let channel = |i: usize| u8::from_str_radix(&s[i..i + 2], 16);
+            match (channel(1), channel(3), channel(5)) {
+                (Ok(r), Ok(g), Ok(b)) => Color::Rgb(r, g, b),
+                _ => return Err(ConfigError::InvalidColor(color.to_string())),
+            }
+        }
+        s => match s.parse::<u8>() {
+            Ok(i) => Color::Indexed(i),
+            Err(_) => return Err(ConfigError::InvalidColor(color.to_string())),
+        },
+    };
+    Ok(parsed)
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    journal: Option<String>,
+    preset: Option<String>,
+    #[serde(default)]
+    keys: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    theme: ThemeFile,
+    #[serde(default)]
+    calendar: CalendarFile,
+    #[serde(default)]
+    display: DisplayFile,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    border: Option<String>,
+    editing: Option<String>,
+    selected: Option<String>,
+    key_hint: Option<String>,
+    help_border: Option<String>,
+    heat_low: Option<String>,
+    heat_medium: Option<String>,
+    heat_high: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct DisplayFile {
+    timezone: Option<String>,
+    time_format: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CalendarFile {
+    intensity: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// SQLite journal file, or directory of Markdown files
+    pub journal: String,
+    pub keymap: KeyMap,
+    pub theme: Theme,
+    /// Whether calendar days are shaded by number of entries or by words written
+    pub intensity: Intensity,
+    /// Override the time zone and format stored in the journal settings
+    pub timezone: Option<DisplayZone>,
+    pub time_format: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            journal: "journal.db".to_string(),
+            keymap: KeyMap::default(),
+            theme: Theme::default(),
+            intensity: Intensity::Count,
+            timezone: None,
+            time_format: None,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file at `path`, falling back to the defaults when it does not exist
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Config::parse(&contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, ConfigError> {
+        let file: ConfigFile = toml::from_str(contents)?;
+        let mut keymap = KeyMap::preset(file.preset.as_deref().unwrap_or("default"))?;
+        for (name, keys) in file.keys {
+            let action = Action::from_name(&name).ok_or(ConfigError::UnknownAction(name))?;
+            let keys = keys
+                .iter()
+                .map(|k| KeyBinding::parse(k))
+                .collect::<Result<Vec<KeyBinding>, ConfigError>>()?;
+            keymap.bindings.insert(action, keys);
+        }
+
+        let mut theme = Theme::default();
+        let [heat_low, heat_medium, heat_high] = &mut theme.heat;
+        let colors = [
+            (&file.theme.border, &mut theme.border),
+            (&file.theme.editing, &mut theme.editing),
+            (&file.theme.selected, &mut theme.selected),
+            (&file.theme.key_hint, &mut theme.key_hint),
+            (&file.theme.help_border, &mut theme.help_border),
+            (&file.theme.heat_low, heat_low),
+            (&file.theme.heat_medium, heat_medium),
+            (&file.theme.heat_high, heat_high),
+        ];
+        for (value, color) in colors {
+            if let Some(value) = value {
+                *color = parse_color(value)?;
+            }
+        }
+        let intensity = match file.calendar.intensity.as_deref() {
+            None | Some("count") => Intensity::Count,
+            Some("words") => Intensity::Words,
+            Some(other) => return Err(ConfigError::InvalidIntensity(other.to_string())),
+        };
+        let timezone = file.display.timezone.map(|z| z.parse()).transpose()?;
+        if let Some(format) = &file.display.time_format {
+            time::check_format(format)?;
+        }
+        Ok(Config {
+            journal: file.journal.unwrap_or_else(|| Config::default().journal),
+            keymap,
+            theme,
+            intensity,
+            timezone,
+            time_format: file.display.time_format,
+        })
+    }
+
+    /// Applies the overrides of the config file to the format from the journal settings
+    pub fn apply_time_overrides(&self, mut time_format: TimeFormat) -> TimeFormat {
+        if let Some(zone) = self.timezone {
+            time_format.zone = zone;
+        }
+        if let Some(format) = &self.time_format {
+            time_format.format = format.clone();
+        }
+        time_format
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_key_bindings() {
+        let key = KeyBinding::parse("C-n").unwrap();
+        assert!(key.matches(&KeyEvent::new(KeyCode::Char('n'), KeyModifiers::CONTROL)));
+        assert!(!key.matches(&KeyEvent::new(KeyCode::Char('n'), KeyModifiers::NONE)));
+        assert_eq!(key.to_string(), "C-n");
+        let help = KeyBinding::parse("?").unwrap();
+        assert!(help.matches(&KeyEvent::new(KeyCode::Char('?'), KeyModifiers::SHIFT)));
+        assert!(KeyBinding::parse("X-y").is_err());
+    }
+
+    #[test]
+    fn config_overrides_preset() {
+        let config = Config::parse(
+            "preset = \"vim\"\n[keys]\nquit = [\"x\"]\n[theme]\nediting = \"#ff8800\"\n",
+        )
+        .unwrap();
+        let j = KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE);
+        assert_eq!(config.keymap.action_for(&j, false), Some(Action::NextEntry));
+        assert_eq!(config.keymap.describe(Action::Quit), "x");
+        assert_eq!(config.theme.editing, Color::Rgb(0xff, 0x88, 0x00));
+        assert!(matches!(Config::parse("preset = \"nano\""), Err(ConfigError::UnknownPreset(_))));
+    }
+}
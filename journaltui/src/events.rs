@@ -0,0 +1,54 @@
+use std::{error::Error, time::Duration};
+
+use crossterm::event::{self, Event};
+
+/// What the app loop reacts to
+pub enum Input {
+    Terminal(Event),
+    /// Nothing happened for a while, time to look for changes made by other processes
+    Idle,
+}
+
+/// Where the app loop gets its input from
+pub trait EventSource {
+    /// Blocks until the next event. `None` means there is no more input and the app should exit.
+    fn next_event(&mut self) -> Result<Option<Input>, Box<dyn Error>>;
+}
+
+/// Reads events from the terminal
+pub struct CrosstermEvents;
+
+/// How long the terminal is quiet before `Input::Idle`
+const IDLE_AFTER: Duration = Duration::from_millis(500);
+
+impl EventSource for CrosstermEvents {
+    fn next_event(&mut self) -> Result<Option<Input>, Box<dyn Error>> {
+        if event::poll(IDLE_AFTER)? {
+            Ok(Some(Input::Terminal(event::read()?)))
+        } else {
+            Ok(Some(Input::Idle))
+        }
+    }
+}
+
+/// Replays a fixed list of events, for driving the app in tests
+#[cfg(test)]
+pub struct ScriptedEvents {
+    events: std::collections::VecDeque<Event>,
+}
+
+#[cfg(test)]
+impl ScriptedEvents {
+    pub fn new(events: Vec<Event>) -> Self {
+        ScriptedEvents {
+            events: events.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl EventSource for ScriptedEvents {
+    fn next_event(&mut self) -> Result<Option<Input>, Box<dyn Error>> {
+        Ok(self.events.pop_front().map(Input::Terminal))
+    }
+}
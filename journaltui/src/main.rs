@@ -1,18 +1,12 @@
 mod app;
+mod calendar;
+mod config;
+mod events;
 mod ui;
 
 use app::{run_app, App};
-/// A simple example demonstrating how to handle user input. This is
-/// a bit out of the scope of the library as it does not provide any
-/// input handling out of the box. However, it may helps some to get
-/// started.
-///
-/// This is a very simple example:
-///   * A input box always focused. Every character you type is registered
-///   here
-///   * Pressing Backspace erases a character
-///   * Pressing Enter pushes the current input in the history of previous
-///   messages
+use config::{Config, CONFIG_FILE};
+use events::CrosstermEvents;
 use tui::Terminal;
 use crossterm::{
     event::{DisableMouseCapture, EnableMouseCapture},
@@ -26,6 +20,12 @@ use std::{error::Error, io};
 
 
 fn main() -> Result<(), Box<dyn Error>> {
+    // load the keymap, theme and journal before touching the terminal so errors are readable
+    let config = Config::load(CONFIG_FILE)?;
+    let store = journaldb::store::open(&config.journal)?;
+    // Shown once the terminal is back, the alternate screen would hide them
+    let warnings = store.warnings();
+
     // setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -34,8 +34,8 @@ fn main() -> Result<(), Box<dyn Error>> {
     let mut terminal = Terminal::new(backend)?;
 
     // create app and run it
-    let app = App::default();
-    let res = run_app(&mut terminal, app);
+    let app = App::new(store, config);
+    let res = run_app(&mut terminal, app, &mut CrosstermEvents);
 
     // restore terminal
     disable_raw_mode()?;
@@ -45,6 +45,9 @@ fn main() -> Result<(), Box<dyn Error>> {
         DisableMouseCapture
     )?;
     terminal.show_cursor()?;
+    for warning in warnings {
+        eprintln!("warning: {}", warning);
+    }
 
     if let Err(err) = res {
         println!("{:?}", err)
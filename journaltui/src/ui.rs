@@ -1,58 +1,267 @@
 use tui::{
     backend::{Backend},
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Span, Spans, Text},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{BarChart, Block, Borders, Clear, List, ListItem, ListState, Paragraph, Row, Sparkline, Table, Tabs},
     Frame,
 };
+use journaldb::stats::Stats;
 use unicode_width::UnicodeWidthStr;
 
-use crate::app::{InputMode, App};
+use crate::app::{Field, InputMode, App, PassphraseStep, Tab};
+use crate::calendar::{Calendar, CALENDAR_HEIGHT};
+use crate::config::Action;
+
 pub fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints(
             [
                 Constraint::Length(1),
-                // Constraint::Length(3),
-                // Constraint::Min(1),
+                Constraint::Length(1),
                 Constraint::Min(1),
             ]
             .as_ref(),
         )
         .split(f.size());
 
+    let keymap = &app.config.keymap;
+    let key_style = Style::default()
+        .fg(app.config.theme.key_hint)
+        .add_modifier(Modifier::BOLD);
     let (msg, style) = match app.input_mode {
         InputMode::Normal => (
             vec![
                 Span::raw("Press "),
-                Span::styled("q", Style::default().add_modifier(Modifier::BOLD)),
+                Span::styled(keymap.describe(Action::Quit), key_style),
                 Span::raw(" to exit, "),
-                Span::styled("e", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" to start editing."),
+                Span::styled(keymap.describe(Action::StartEditing), key_style),
+                Span::raw(" to start editing, "),
+                Span::styled(keymap.describe(Action::ToggleHelp), key_style),
+                Span::raw(" for help."),
             ],
             Style::default().add_modifier(Modifier::RAPID_BLINK),
         ),
         InputMode::Editing => (
             vec![
                 Span::raw("Press "),
-                Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                Span::styled(keymap.describe(Action::StopEditing), key_style),
                 Span::raw(" to stop editing, "),
-                Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" to record the message"),
+                Span::styled(keymap.describe(Action::Submit), key_style),
+                Span::raw(" to save the entry, "),
+                Span::styled(keymap.describe(Action::NextField), key_style),
+                Span::raw(" for the next field."),
             ],
             Style::default(),
         ),
     };
     let mut text = Text::from(Spans::from(msg));
     text.patch_style(style);
+    if let Some(status) = &app.status {
+        text = Text::from(Span::styled(format!("Error: {}", status), Style::default().fg(app.config.theme.editing)));
+    }
     let help_message = Paragraph::new(text);
     f.render_widget(help_message, chunks[0]);
-    draw_main(f, app, chunks[1]);
 
+    let titles = Tab::ALL.iter().map(|t| Spans::from(t.title())).collect();
+    let tabs = Tabs::new(titles)
+        .select(Tab::ALL.iter().position(|t| *t == app.tab).unwrap_or(0))
+        .highlight_style(Style::default().fg(app.config.theme.selected).add_modifier(Modifier::BOLD));
+    f.render_widget(tabs, chunks[1]);
+
+    match app.tab {
+        Tab::Journal => draw_main(f, app, chunks[2]),
+        Tab::Dashboard => draw_dashboard(f, app, chunks[2]),
+    }
+
+    if app.show_help {
+        draw_help(f, app);
+    }
+    if app.link_picker.is_some() {
+        draw_link_picker(f, app);
+    }
+    if app.template_picker.is_some() {
+        draw_template_picker(f, app);
+    }
+    if app.search_box.is_some() {
+        draw_search_box(f, app);
+    }
+    if app.passphrase_prompt.is_some() {
+        draw_passphrase_prompt(f, app);
+    }
+}
+
+fn draw_link_picker<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let Some(picker) = &app.link_picker else {
+        return;
+    };
+    let items: Vec<ListItem> = picker
+        .links
+        .iter()
+        .map(|link| match link.target.and_then(|id| app.store.get_entry_by_id(id)) {
+            Some(entry) => ListItem::new(format!("{}: {}", entry.get_id(), entry.get_title())),
+            None => ListItem::new(Span::styled(
+                format!("[[{}]] (no such entry)", link.text),
+                Style::default().add_modifier(Modifier::DIM),
+            )),
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(app.config.theme.help_border))
+                .title("Links"),
+        )
+        .highlight_style(Style::default().fg(app.config.theme.selected).add_modifier(Modifier::BOLD))
+        .highlight_symbol("> ");
+    let area = centered_rect(60, picker.links.len() as u16 + 2, f.size());
+    let mut state = ListState::default();
+    state.select(Some(picker.selected));
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_passphrase_prompt<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let Some(prompt) = &app.passphrase_prompt else {
+        return;
+    };
+    let title = match prompt.step {
+        PassphraseStep::Unlock => "Passphrase",
+        PassphraseStep::New => "New passphrase, empty to remove encryption",
+        PassphraseStep::Confirm(_) => "Repeat new passphrase",
+    };
+    let mut lines = vec![Spans::from("*".repeat(prompt.input.chars().count()))];
+    if let Some(error) = &prompt.error {
+        lines.push(Spans::from(Span::styled(error.as_str(), Style::default().fg(app.config.theme.editing))));
+    }
+    let area = centered_rect(60, 4, f.size());
+    let popup = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(app.config.theme.help_border))
+            .title(title),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+    f.set_cursor(area.x + 1 + prompt.input.chars().count() as u16, area.y + 1);
+}
+
+fn draw_search_box<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let Some(search_box) = &app.search_box else {
+        return;
+    };
+    let mut lines = vec![Spans::from(search_box.input.as_str())];
+    if let Some(error) = &search_box.error {
+        let pointer = format!("{}^ {}", " ".repeat(error.position), error.message);
+        lines.push(Spans::from(Span::styled(pointer, Style::default().fg(app.config.theme.editing))));
+    }
+    let area = centered_rect(60, 4, f.size());
+    let popup = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(app.config.theme.help_border))
+            .title("Search: tag: title: after: before: OR NOT"),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+    f.set_cursor(area.x + 1 + search_box.input.width() as u16, area.y + 1);
 }
 
+fn draw_template_picker<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let Some(picker) = &app.template_picker else {
+        return;
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(app.config.theme.help_border));
+    if let (Some(template), Some(question)) = (picker.template(), picker.question()) {
+        let area = centered_rect(60, 4, f.size());
+        let popup = Paragraph::new(vec![
+            Spans::from(Span::styled(question, Style::default().add_modifier(Modifier::BOLD))),
+            Spans::from(picker.input.as_str()),
+        ])
+        .block(block.title(template.name.as_str()));
+        f.render_widget(Clear, area);
+        f.render_widget(popup, area);
+        f.set_cursor(area.x + 1 + picker.input.width() as u16, area.y + 2);
+        return;
+    }
+
+    let items: Vec<ListItem> = if picker.templates.is_empty() {
+        vec![ListItem::new(Span::styled(
+            "No templates, add them with journalcli template add",
+            Style::default().add_modifier(Modifier::DIM),
+        ))]
+    } else {
+        picker
+            .templates
+            .iter()
+            .map(|t| ListItem::new(format!("{}: {}", t.name, t.title)))
+            .collect()
+    };
+    let area = centered_rect(60, items.len() as u16 + 2, f.size());
+    let list = List::new(items)
+        .block(block.title("Templates"))
+        .highlight_style(Style::default().fg(app.config.theme.selected).add_modifier(Modifier::BOLD))
+        .highlight_symbol("> ");
+    let mut state = ListState::default();
+    state.select(Some(picker.selected).filter(|_| !picker.templates.is_empty()));
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+/// Lists every action of the active keymap in a popup over the rest of the ui
+fn draw_help<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let area = centered_rect(70, Action::ALL.len() as u16 + 3, f.size());
+    // Borders and the header take 3 rows
+    let visible = (area.height as usize).saturating_sub(3).max(1);
+    app.help_scroll = app.help_scroll.min(Action::ALL.len().saturating_sub(visible));
+    let keymap = &app.config.keymap;
+    let title = if visible < Action::ALL.len() {
+        let last = (app.help_scroll + visible).min(Action::ALL.len());
+        let scroll = keymap.describe(Action::NextEntry);
+        format!("Help {}-{} of {}, scroll with {}", app.help_scroll + 1, last, Action::ALL.len(), scroll)
+    } else {
+        "Help".to_string()
+    };
+    let rows = Action::ALL.iter().skip(app.help_scroll).map(|action| {
+        let mode = if action.while_editing() { "editing" } else { "normal" };
+        Row::new(vec![keymap.describe(*action), mode.to_string(), action.description().to_string()])
+    });
+    let table = Table::new(rows)
+        .header(Row::new(vec!["Key", "Mode", "Action"]).style(Style::default().add_modifier(Modifier::BOLD)))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(app.config.theme.help_border))
+                .title(title),
+        );
+
+    // Borders and the spacing between the three columns take 4 cells
+    let description_width = area.width.saturating_sub(12 + 8 + 4);
+    let widths = [Constraint::Length(12), Constraint::Length(8), Constraint::Length(description_width)];
+    let table = table.widths(&widths);
+    f.render_widget(Clear, area);
+    f.render_widget(table, area);
+}
+
+/// A rect `percent_x` wide and `height` rows tall in the middle of `area`
+fn centered_rect(percent_x: u16, height: u16, area: Rect) -> Rect {
+    let height = height.min(area.height);
+    let width = area.width * percent_x / 100;
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// Saved searches shown before the list needs a scroll bar we don't have
+const MAX_SAVED_SEARCH_ROWS: usize = 5;
+
 fn draw_main<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
 where
     B: Backend,
@@ -61,31 +270,114 @@ where
     .direction(Direction::Horizontal)
     .constraints(
         [
-            Constraint::Length(20),
+            Constraint::Length(app.sidebar_width),
             Constraint::Min(1),
         ]
         .as_ref(),
     )
     .split(area);
 
-    let entries: Vec<ListItem> = app.db
-        .get_entries()
+    // Saved searches only take room once there are some
+    let searches_height = match app.saved_searches.len() {
+        0 => 0,
+        n => n.min(MAX_SAVED_SEARCH_ROWS) as u16 + 2,
+    };
+    let sidebar = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(CALENDAR_HEIGHT + 2),
+                Constraint::Min(1),
+                Constraint::Length(searches_height),
+            ]
+            .as_ref(),
+        )
+        .split(chunks[0]);
+
+    let amounts = app.daily_amounts();
+    let calendar = Calendar::new(app.date_filter.unwrap_or(app.today), app.today, &amounts)
+        .selected(app.date_filter.is_some())
+        .heat(app.config.theme.heat)
+        .block(bordered(app, "Calendar"));
+    f.render_widget(calendar, sidebar[0]);
+
+    draw_entry_list(f, app, sidebar[1]);
+    if searches_height > 0 {
+        draw_saved_searches(f, app, sidebar[2]);
+    }
+    app.areas.main = area;
+    app.areas.entries = sidebar[1];
+    app.areas.saved_searches = sidebar[2];
+
+    draw_editor(f, app, chunks[1]);
+}
+
+/// Saved searches with their live number of matching entries
+fn draw_saved_searches<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let items: Vec<ListItem> = app
+        .saved_searches
         .iter()
+        .map(|(search, count)| match count {
+            Ok(count) => ListItem::new(format!("{} ({})", search.name, count)),
+            Err(e) => ListItem::new(format!("{} (?) {}", search.name, e)),
+        })
+        .collect();
+    let list = List::new(items)
+        .block(bordered(app, "Saved searches"))
+        .highlight_style(Style::default().fg(app.config.theme.selected).add_modifier(Modifier::BOLD))
+        .highlight_symbol("> ");
+    let mut state = ListState::default();
+    state.select(app.active_saved_search());
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_entry_list<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
+where
+    B: Backend,
+{
+    // Keep track of the scroll position ourselves so mouse clicks can be mapped to entries
+    let height = area.height.saturating_sub(2).max(1) as usize;
+    let selected = app.entries.state.selected();
+    if let Some(selected) = selected {
+        if selected < app.list_offset {
+            app.list_offset = selected;
+        } else if selected >= app.list_offset + height {
+            app.list_offset = selected + 1 - height;
+        }
+    }
+    app.list_offset = app.list_offset.min(app.entries.items.len().saturating_sub(1));
+
+    let entries: Vec<ListItem> = app.entries
+        .items
+        .iter()
+        .skip(app.list_offset)
         .map(|x| {
             let entry_id = x.clone().get_id();
             let entry_title = x.clone().get_title();
-            ListItem::new(vec![Spans::from(Span::raw(format!("{}: {}", entry_id, entry_title)))])}
+            let line = match x.markers().as_str() {
+                "" => format!("{}: {}", entry_id, entry_title),
+                markers => format!("{}: {} {}", entry_id, markers, entry_title),
+            };
+            ListItem::new(vec![Spans::from(Span::raw(line))])}
         )
         .collect();
 
+    let mut list_title = match app.date_filter {
+        Some(date) => format!("Entries {}", date.format("%Y-%m-%d")),
+        None => String::from("Entries"),
+    };
+    if !app.search.is_empty() {
+        list_title = format!("{} matching {}", list_title, app.search);
+    }
     let entry_list = List::new(entries)
-        .block(Block::default().borders(Borders::ALL).title("Entries"));
-    f.render_widget(entry_list, chunks[0]);
-
-    draw_editor(f, app, chunks[1]);
+        .block(bordered(app, &list_title))
+        .highlight_style(Style::default().fg(app.config.theme.selected).add_modifier(Modifier::BOLD))
+        .highlight_symbol("> ");
+    let mut state = ListState::default();
+    state.select(selected.map(|i| i - app.list_offset));
+    f.render_stateful_widget(entry_list, area, &mut state);
 }
 
-    
 fn draw_editor<B>(f: &mut Frame<B>, app: &mut App, area: Rect)
 where
     B: Backend,
@@ -100,14 +392,39 @@ where
             .as_ref(),
         )
         .split(area);
+    app.areas.title = chunks[0];
+    app.areas.content = chunks[1];
+    app.areas.tags = chunks[2];
+
+    let field_style = |field: Field| match app.input_mode {
+        InputMode::Editing if app.focus == field => Style::default().fg(app.config.theme.editing),
+        _ => Style::default(),
+    };
+
+    let title_label = match app.selected_entry() {
+        Some(entry) => format!(
+            "Title (created {}, updated {})",
+            app.time_format.format(&entry.get_created_time()),
+            app.time_format.format(&entry.get_updated_time()),
+        ),
+        None => String::from("Title"),
+    };
+    let title = Paragraph::new(app.title.as_ref())
+        .style(field_style(Field::Title))
+        .block(bordered(app, &title_label));
+    f.render_widget(title, chunks[0]);
+
+    let content = Paragraph::new(app.content.as_ref())
+        .style(field_style(Field::Content))
+        .scroll((app.content_scroll, 0))
+        .block(bordered(app, "Content"));
+    f.render_widget(content, chunks[1]);
+
+    let tags = Paragraph::new(app.tags.as_ref())
+        .style(field_style(Field::Tags))
+        .block(bordered(app, "Tags"));
+    f.render_widget(tags, chunks[2]);
 
-    let input = Paragraph::new(app.input.as_ref())
-        .style(match app.input_mode {
-            InputMode::Normal => Style::default(),
-            InputMode::Editing => Style::default().fg(Color::Yellow),
-        })
-        .block(Block::default().borders(Borders::ALL).title("Title"));
-    f.render_widget(input, chunks[0]);
     match app.input_mode {
         InputMode::Normal =>
             // Hide the cursor. `Frame` does this by default, so we don't need to do anything here
@@ -115,28 +432,96 @@ where
 
         InputMode::Editing => {
             // Make the cursor visible and ask tui-rs to put it at the specified coordinates after rendering
+            let (area, text) = match app.focus {
+                Field::Title => (chunks[0], app.title.as_str()),
+                Field::Content => (chunks[1], app.content.as_str()),
+                Field::Tags => (chunks[2], app.tags.as_str()),
+            };
+            // Put cursor past the end of the last line of the text
+            let line = text.split('\n').count().saturating_sub(1) as u16;
+            let last = text.rsplit('\n').next().unwrap_or("");
+            let scroll = if app.focus == Field::Content { app.content_scroll } else { 0 };
             f.set_cursor(
-                // Put cursor past the end of the input text
-                chunks[1].x + app.input.width() as u16 + 1,
-                // Move one line down, from the border to the input line
-                chunks[1].y + 1,
+                area.x + last.width() as u16 + 1,
+                area.y + 1 + line.saturating_sub(scroll),
             )
         }
     }
+}
+
+fn draw_dashboard<B>(f: &mut Frame<B>, app: &App, area: Rect)
+where
+    B: Backend,
+{
+    let stats = Stats::compute(&app.store.get_entries(), &app.time_format, app.today, 8);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(10),
+                Constraint::Min(6),
+                Constraint::Length(6),
+            ]
+            .as_ref(),
+        )
+        .split(area);
 
-    let messages: Vec<ListItem> = app
-        .messages
+    let top = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .split(rows[0]);
+    let summary = vec![
+        Spans::from(format!("Entries:        {}", stats.total_entries)),
+        Spans::from(format!("Words:          {}", stats.total_words)),
+        Spans::from(format!("Words/entry:    {:.1}", stats.average_words())),
+        Spans::from(format!("Current streak: {} days", stats.current_streak)),
+        Spans::from(format!("Longest streak: {} days", stats.longest_streak)),
+    ];
+    f.render_widget(Paragraph::new(summary).block(bordered(app, "Summary")), top[0]);
+    let tags: Vec<ListItem> = stats
+        .top_tags
         .iter()
-        .enumerate()
-        .map(|(i, m)| {
-            let content = vec![Spans::from(Span::raw(format!("{}: {}", i, m)))];
-            ListItem::new(content)
-        })
+        .map(|(tag, count)| ListItem::new(format!("{:>4} {}", count, tag)))
         .collect();
-    let messages =
-        List::new(messages).block(Block::default().borders(Borders::ALL).title("Content"));
-    f.render_widget(messages, chunks[1]);
+    f.render_widget(List::new(tags).block(bordered(app, "Top tags")), top[1]);
 
-    let tags = Block::default().borders(Borders::ALL).title("Tags");
-    f.render_widget(tags, chunks[2]);
+    let charts = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(7 * 4 + 2), Constraint::Min(12 * 4 + 2)].as_ref())
+        .split(rows[1]);
+    let weekdays: Vec<(&str, u64)> = ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"]
+        .into_iter()
+        .zip(stats.entries_per_weekday)
+        .collect();
+    let weekday_chart = BarChart::default()
+        .block(bordered(app, "Weekdays"))
+        .data(&weekdays)
+        .bar_width(3)
+        .bar_gap(1)
+        .bar_style(Style::default().fg(app.config.theme.heat[2]));
+    f.render_widget(weekday_chart, charts[0]);
+    let months: Vec<(&str, u64)> = ["Ja", "Fe", "Mr", "Ap", "My", "Jn", "Jl", "Au", "Se", "Oc", "No", "De"]
+        .into_iter()
+        .zip(stats.entries_per_month)
+        .collect();
+    let month_chart = BarChart::default()
+        .block(bordered(app, "Months"))
+        .data(&months)
+        .bar_width(3)
+        .bar_gap(1)
+        .bar_style(Style::default().fg(app.config.theme.heat[2]));
+    f.render_widget(month_chart, charts[1]);
+
+    let sparkline = Sparkline::default()
+        .block(bordered(app, "Words per entry"))
+        .data(&stats.words_per_entry)
+        .style(Style::default().fg(app.config.theme.heat[1]));
+    f.render_widget(sparkline, rows[2]);
+}
+
+fn bordered<'a>(app: &App, title: &'a str) -> Block<'a> {
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(app.config.theme.border))
+        .title(title)
 }